@@ -20,17 +20,39 @@ end
 
 fn parse_compile_run_gcd(a: i64, b: i64) -> Result<Value, RuntimeError> {
   let (_, fn_decl) = parser::declaration::function_decl(SOURCE_CODE).unwrap();
-  let main_procedure = compiler::compile_function(&fn_decl);
+  let main_procedure = compiler::compile_function(&fn_decl).unwrap();
   let mut vm = VirtualMachine::with_functions([
     (fn_decl.name.0.clone(), main_procedure),
   ]);
   vm.run("gcd", [a.into(), b.into()])
 }
 
+fn compiled_gcd_vm(register_capacity: usize) -> VirtualMachine {
+  let (_, fn_decl) = parser::declaration::function_decl(SOURCE_CODE).unwrap();
+  let main_procedure = compiler::compile_function(&fn_decl).unwrap();
+  VirtualMachine::with_capacity([(fn_decl.name.0.clone(), main_procedure)], register_capacity)
+}
+
 pub fn bench_gcd(c: &mut Criterion) {
   c.bench_function("gcd", |b| b.iter(|| parse_compile_run_gcd(black_box(25000), black_box(135))));
 }
 
-criterion_group!(benches, bench_gcd);
+pub fn bench_gcd_reused_vm(c: &mut Criterion) {
+  let mut vm = compiled_gcd_vm(0);
+  c.bench_function("gcd reused vm", |b| b.iter(|| vm.run("gcd", [black_box(25000_i64).into(), black_box(135_i64).into()])));
+  let mut vm = compiled_gcd_vm(64);
+  c.bench_function("gcd reused vm with capacity", |b| b.iter(|| vm.run("gcd", [black_box(25000_i64).into(), black_box(135_i64).into()])));
+}
+
+/// The dispatch table against matching on the opcode's bit fields for every instruction
+pub fn bench_gcd_dispatch(c: &mut Criterion) {
+  let mut group = c.benchmark_group("gcd dispatch");
+  let mut vm = compiled_gcd_vm(64);
+  group.bench_function("table", |b| b.iter(|| vm.run("gcd", [black_box(25000_i64).into(), black_box(135_i64).into()])));
+  group.bench_function("nested match", |b| b.iter(|| vm.run_with_nested_dispatch("gcd", [black_box(25000_i64).into(), black_box(135_i64).into()])));
+  group.finish();
+}
+
+criterion_group!(benches, bench_gcd, bench_gcd_reused_vm, bench_gcd_dispatch);
 criterion_main!(benches);
 
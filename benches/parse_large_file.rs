@@ -0,0 +1,25 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use lualite::parser;
+
+pub fn bench_parse_large_file(c: &mut Criterion) {
+  // about 10k lines
+  let source_code = parser::generated_program(770);
+  c.bench_function("parse 10k lines", |b| b.iter(|| parser::parse_file(black_box(&source_code)).unwrap()));
+}
+
+/// Parses files of doubling size, whose throughput should stay about the same
+pub fn bench_parse_by_size(c: &mut Criterion) {
+  let mut group = c.benchmark_group("parse by size");
+  for functions in [1000, 2000, 4000, 8000] {
+    let source_code = parser::generated_program(functions);
+    group.throughput(Throughput::Bytes(source_code.len() as u64));
+    group.bench_with_input(BenchmarkId::from_parameter(functions), &source_code, |b, source_code| {
+      b.iter(|| parser::parse_file(black_box(source_code)).unwrap())
+    });
+  }
+  group.finish();
+}
+
+criterion_group!(benches, bench_parse_large_file, bench_parse_by_size);
+criterion_main!(benches);
@@ -35,7 +35,7 @@ end
 
 fn main() {
   let (_, declarations) = parser::parse_file(SOURCE_CODE).unwrap();
-  let functions = compiler::compile_declarations(declarations.iter());
+  let functions = compiler::compile_declarations(declarations.iter()).unwrap();
   let mut vm = VirtualMachine::with_functions(functions);
 
   let result = vm.run("calculate_pi", []);
@@ -18,7 +18,7 @@ end
 
 fn main() {
   let (_, fn_decl) = parser::declaration::function_decl(SOURCE_CODE).unwrap();
-  let procedure = compiler::compile_function(&fn_decl);
+  let procedure = compiler::compile_function(&fn_decl).unwrap();
   let name = fn_decl.name.0;
   println!("{SOURCE_CODE}");
   println!("{name:?}:\n{procedure}");
@@ -11,7 +11,7 @@ end
 
 fn main() {
   let (_, main_ast) = parser::declaration::function_decl(SOURCE_CODE).unwrap();
-  let main_procedure = compiler::compile_function(&main_ast);
+  let main_procedure = compiler::compile_function(&main_ast).unwrap();
   let mut vm = VirtualMachine::with_functions([
     ("main".to_owned(), main_procedure),
   ]);
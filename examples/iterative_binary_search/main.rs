@@ -9,7 +9,7 @@ function binary_search(array, length, needle)
   first = 0
   last = length - 1
   while first <= last do
-    mid = (first + last) / 2
+    mid = (first + last) // 2
     if needle < array[mid] then
       last = mid - 1
     elseif needle > array[mid] then
@@ -26,7 +26,7 @@ end
 
 fn main() {
   let (_, declarations) = parser::parse_file(SOURCE_CODE).unwrap();
-  let functions = compiler::compile_declarations(declarations.iter());
+  let functions = compiler::compile_declarations(declarations.iter()).unwrap();
   let mut vm = VirtualMachine::with_functions(functions);
 
   let rust_array = [1, 3, 4, 6, 8, 9, 10, 11, 14, 15];
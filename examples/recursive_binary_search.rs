@@ -7,7 +7,7 @@ const SOURCE_CODE: &str = r#"
 
 function binary_search_helper(array, first, last, needle)
   if first <= last then
-    mid = (first + last) / 2
+    mid = (first + last) // 2
     mid_value = array[mid]
     if needle < mid_value then
       return binary_search_helper(array, first, mid - 1, needle)
@@ -30,7 +30,7 @@ end
 
 fn main() {
   let (_, declarations) = parser::parse_file(SOURCE_CODE).unwrap();
-  let functions = compiler::compile_declarations(declarations.iter());
+  let functions = compiler::compile_declarations(declarations.iter()).unwrap();
   let mut vm = VirtualMachine::with_functions(functions);
 
   let rust_array = [1, 3, 4, 6, 8, 9, 10, 11, 14, 15];
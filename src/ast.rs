@@ -7,6 +7,9 @@
 //! of the [compiler](../compiler/index.html).  More information on how source code is
 //! mapped to AST components can be found in the parser documentation.
 
+use core::fmt;
+use alloc::{boxed::Box, string::String, vec::Vec};
+
 /// Top-level declarations in a file
 #[derive(Debug, Clone, PartialEq)]
 pub enum Declaration {
@@ -21,8 +24,17 @@ pub struct FunctionDecl {
   pub name: Identifier,
   /// List of parameter names
   pub params: Vec<Identifier>,
+  /// Default value of each parameter, in the same order as `params`
+  ///
+  /// A default is used when its argument is missing or `nil`.  The function can't tell the
+  /// two apart, so an explicit `nil` argument also gets the default, and a parameter with a
+  /// default is never `nil` unless the default itself is.  Each default is evaluated at the
+  /// start of every call that needs it.
+  pub defaults: Vec<Option<Expression>>,
   /// List of statements in the function body
   pub body: Vec<Statement>,
+  /// Where the declaration starts, see [`Span`]
+  pub span: Span,
 }
 
 /// Static variable/constant declaration
@@ -30,6 +42,8 @@ pub struct FunctionDecl {
 pub struct StaticDecl {
   pub name: Identifier,
   pub value: Option<Expression>,
+  /// Where the declaration starts, see [`Span`]
+  pub span: Span,
 }
 
 /// Statement in a function
@@ -41,12 +55,30 @@ pub enum Statement {
   /// f(x + 1, z, true)
   /// ```
   SingleStatement(Expression),
+  /// Comma-separated expressions as a statement, evaluated from left to right
+  ///
+  /// Unlike a [`SingleStatement`](Statement::SingleStatement), it's never the return value
+  /// of a function, even when it's the last statement.
+  /// ## Example:
+  /// ```text
+  /// f(a), g(b)
+  /// ```
+  ExpressionList(Vec<Expression>),
   /// Assignment to an identifier from the result of an expression
   /// ## Example:
   /// ```text
   /// y = (x + 1) * 2
   /// ```
   AssignStatement(Identifier, Expression),
+  /// Declaration of a local that's only visible until the end of the enclosing block
+  ///
+  /// A variable with the same name from outside the block is shadowed, and keeps its
+  /// value for after the block.
+  /// ## Example:
+  /// ```text
+  /// local x = y * 2
+  /// ```
+  LocalStatement(Identifier, Expression),
   /// Assignment to an indexed slot in a container
   /// ## Example:
   /// ```text
@@ -83,6 +115,34 @@ pub enum Statement {
     /// Statement body of the loop
     body: Vec<Statement>,
   },
+  /// Function defined inside another function's body
+  ///
+  /// The function can only be called by name from the enclosing function after its
+  /// definition.  It can't refer to the enclosing function's locals.
+  /// ## Example:
+  /// ```text
+  /// function helper(x)
+  ///   return x * 2
+  /// end
+  /// ```
+  FunctionStatement(FunctionDecl),
+  /// For loop over the integers in a range, including `start` but not `end`
+  /// ## Example:
+  /// ```text
+  /// for i in 0..n do
+  ///   total = total + i
+  /// end
+  /// ```
+  ForStatement {
+    /// Loop variable, set to the next integer at the start of each iteration
+    variable: Identifier,
+    /// First value of the loop variable
+    start: Expression,
+    /// Value the loop stops at, evaluated once before the loop starts
+    end: Expression,
+    /// Statement body of the loop
+    body: Vec<Statement>,
+  },
   /// If (else) statement
   ///
   /// If statements can have zero or more `elseif` clauses after the `if` clause and an
@@ -113,21 +173,44 @@ pub enum Statement {
     /// Optional statement body of the `else` clause
     else_body: Option<Vec<Statement>>,
   },
+  /// Leaves the innermost loop
+  /// ## Example:
+  /// ```text
+  /// break
+  /// ```
+  BreakStatement,
+  /// Skips to the next iteration of the innermost loop
+  ///
+  /// In a `for` loop the loop variable still advances to the next integer.
+  /// ## Example:
+  /// ```text
+  /// continue
+  /// ```
+  ContinueStatement,
 }
 
 /// Expression in a statement
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Expression {
   /// Identifier as an expression
   Identifier(Identifier),
+  /// The `nil` literal
+  Nil,
   /// Integer literal
   Integer(IntegerLiteral),
+  /// Integer literal too large for an `i64`
+  #[cfg(feature = "bigint")]
+  BigInteger(BigIntegerLiteral),
   /// Float literal
   Float(FloatLiteral),
   /// Boolean literal
   Boolean(BooleanLiteral),
   /// String literal
   String(StringLiteral),
+  /// Byte string literal, such as `b"abc"`
+  Bytes(BytesLiteral),
+  /// Array literal, such as `[1, x, "three"]`
+  Array(Vec<Expression>),
   /// Unary prefix operator expressions
   Unary {
     /// Unary operator
@@ -151,13 +234,43 @@ pub enum Expression {
     /// List of expressions
     args: Vec<Expression>,
   },
+  /// Call a function with named arguments, such as `f(x = 1, y = 2)`
+  ///
+  /// The compiler passes each argument as the parameter with the same name.
+  NamedCall {
+    /// Name of the function to call
+    left: Box<Expression>,
+    /// Parameter names paired with their arguments, in the order they were written
+    args: Vec<(Identifier, Expression)>,
+  },
   /// Retrieve an element from a container
   Index {
     /// Container (i.e. arrays and hashmaps) to index
     left: Box<Expression>,
     /// Index
     index: Box<Expression>,
-  }
+  },
+  /// Copy a range of elements out of an array, such as `a[1:3]`
+  ///
+  /// Compiles to a call to the [`slice`](crate::runtime::native::slice) native.
+  Slice {
+    /// Array (or string) to slice
+    left: Box<Expression>,
+    /// First index included in the slice, the start of `left` if missing
+    start: Option<Box<Expression>>,
+    /// Index the slice stops before, the end of `left` if missing
+    end: Option<Box<Expression>>,
+  },
+  /// Conditional expression, such as `a > b ? a : b`
+  ///
+  /// Only the branch picked by `condition` is evaluated.
+  Conditional {
+    condition: Box<Expression>,
+    /// Value when `condition` is truthy
+    then: Box<Expression>,
+    /// Value when `condition` is `nil` or `false`
+    else_: Box<Expression>,
+  },
 }
 
 /// Identifier for local variable names, function names, statics, etc.
@@ -168,30 +281,82 @@ pub struct Identifier(pub String);
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct IntegerLiteral(pub i64);
 
+/// Integer literal too large for an `i64`
+#[cfg(feature = "bigint")]
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct BigIntegerLiteral(pub num_bigint::BigInt);
+
 /// Float literal
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+///
+/// Float literals are compared and hashed by their bit patterns, so every literal is
+/// equal to itself (even `NaN`) and `0.0` is a different literal than `-0.0`.  This lets
+/// expressions be used as keys in maps and sets.
+///
+/// # Example:
+/// ```rust
+/// # use lualite::ast::FloatLiteral;
+/// assert_eq!(FloatLiteral(f64::NAN), FloatLiteral(f64::NAN));
+/// assert_ne!(FloatLiteral(0.0), FloatLiteral(-0.0));
+/// assert!(FloatLiteral(-1.5) < FloatLiteral(0.25));
+/// ```
+#[derive(Debug, Clone)]
 pub struct FloatLiteral(pub f64);
 
+impl PartialEq for FloatLiteral {
+  fn eq(&self, other: &Self) -> bool {
+    self.0.to_bits() == other.0.to_bits()
+  }
+}
+
+impl Eq for FloatLiteral { }
+
+impl PartialOrd for FloatLiteral {
+  fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for FloatLiteral {
+  fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+    self.0.total_cmp(&other.0)
+  }
+}
+
+impl core::hash::Hash for FloatLiteral {
+  fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+    self.0.to_bits().hash(state);
+  }
+}
+
 /// Boolean literal
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct BooleanLiteral(pub bool);
 
 /// String literal
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct StringLiteral(pub String);
 
+/// Byte string literal
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct BytesLiteral(pub Vec<u8>);
+
 /// Unary operators
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum UnaryOperator {
   Neg,
+  /// Logical not, evaluates to `true` if the operand is falsy and `false` otherwise
+  Not,
 }
 
 /// Binary Operators
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum BinaryOperator {
   Pow,
   Mul,
+  /// Division, always evaluates to a float (`7 / 2 == 3.5`)
   Div,
+  /// Floor division, evaluates to an integer when both operands are integers (`7 // 2 == 3`)
+  FloorDiv,
   Rem,
   Add,
   Sub,
@@ -201,5 +366,88 @@ pub enum BinaryOperator {
   Gt,
   Le,
   Ge,
+  /// Logical and, evaluates to the left operand if it is falsy and the right otherwise
+  And,
+  /// Logical or, evaluates to the left operand if it is truthy and the right otherwise
+  Or,
+}
+
+/// Writes the operator as it appears in source code
+impl fmt::Display for UnaryOperator {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      UnaryOperator::Neg => f.write_str("-"),
+      UnaryOperator::Not => f.write_str("not "),
+    }
+  }
+}
+
+/// Writes the operator as it appears in source code
+///
+/// # Example:
+/// ```rust
+/// # use lualite::ast::BinaryOperator;
+/// assert_eq!(BinaryOperator::FloorDiv.to_string(), "//");
+/// assert_eq!(format!("a {} b", BinaryOperator::Ne), "a != b");
+/// ```
+impl fmt::Display for BinaryOperator {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let symbol = match self {
+      BinaryOperator::Pow => "^",
+      BinaryOperator::Mul => "*",
+      BinaryOperator::Div => "/",
+      BinaryOperator::FloorDiv => "//",
+      BinaryOperator::Rem => "%",
+      BinaryOperator::Add => "+",
+      BinaryOperator::Sub => "-",
+      BinaryOperator::Eq => "==",
+      BinaryOperator::Ne => "!=",
+      BinaryOperator::Lt => "<",
+      BinaryOperator::Gt => ">",
+      BinaryOperator::Le => "<=",
+      BinaryOperator::Ge => ">=",
+      BinaryOperator::And => "and",
+      BinaryOperator::Or => "or",
+    };
+    f.write_str(symbol)
+  }
+}
+
+
+/// Location of an AST node in the source code
+///
+/// Top-level [`FunctionDecl`]s and [`StaticDecl`]s parsed from a whole file (with
+/// [`parse_file`](crate::parser::parse_file) or one of the parsers built on it) have
+/// their span filled in.  Declarations parsed on their own, and nested functions, have the
+/// default span of line and column 0.
+///
+/// Spans always compare equal, so a node that stores a `Span` can still derive
+/// `PartialEq` and be compared structurally.  Two ASTs parsed from differently formatted
+/// but otherwise identical source are equal.
+///
+/// # Example:
+/// ```rust
+/// # use lualite::{ast::{Declaration, Span}, parser};
+/// assert_eq!(Span { line: 1, column: 1 }, Span { line: 7, column: 3 });
+///
+/// let declarations = parser::parse_program("static X = 1\n\n  function f() end").unwrap();
+/// match &declarations[1] {
+///   Declaration::Function(function) => assert_eq!((function.span.line, function.span.column), (3, 3)),
+///   other => panic!("expected a function, got {other:?}"),
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Span {
+  /// Line number, starting at 1
+  pub line: u32,
+  /// Column number in characters, starting at 1
+  pub column: u32,
+}
+
+impl PartialEq for Span {
+  fn eq(&self, _other: &Self) -> bool {
+    true
+  }
 }
 
+impl Eq for Span { }
@@ -1,6 +1,11 @@
 
-use std::fmt;
-use crate::ast::{IntegerLiteral, FloatLiteral, BooleanLiteral, StringLiteral};
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use alloc::{rc::Rc, string::String, vec::Vec};
+use either::Either;
+use super::operand::ConstantKey;
+use crate::ast::{IntegerLiteral, FloatLiteral, BooleanLiteral, StringLiteral, BytesLiteral};
+#[cfg(feature = "std")]
 use crate::runtime;
 
 /// Constant literals in a function
@@ -9,29 +14,170 @@ use crate::runtime;
 /// from that procedure can refer to its constant values with a [`ConstantKey`]
 /// (from [`bytecode::operand`]).  A constant key is just an index into that table.
 ///
-/// Each procedure has its own table of `ConstantValue`s.
+/// Each procedure has its own table of `ConstantValue`s, unless the procedures of a
+/// [`Program`] were made to [share](crate::bytecode::Program::share_constants) one pool.
 ///
 /// A [`VirtualMachine`] can create a runtime [`Value`] directly from a `ConstantValue`.
 ///
 /// [`ConstantKey`]: crate::bytecode::operand::ConstantKey
 /// [`bytecode::operand`]: crate::bytecode::operand
 /// [`Procedure`]: crate::bytecode::Procedure
+/// [`Program`]: crate::bytecode::Program
 /// [`VirtualMachine`]: crate::runtime::VirtualMachine
 /// [`Value`]: crate::runtime::Value
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+///
+/// Constants are equal when they're exactly the same, so floats are compared by their bits:
+/// `0.0` and `-0.0` are different constants, and a NaN is equal to a NaN with the same bits.
+#[derive(Debug, Clone)]
 pub enum ConstantValue {
+  Nil,
   Integer(i64),
+  /// Integer too large for an `i64`
+  #[cfg(feature = "bigint")]
+  BigInt(num_bigint::BigInt),
   Float(f64),
   Boolean(bool),
   String(String),
+  /// Byte buffer
+  ///
+  /// Each time it's loaded, the virtual machine creates a new buffer from it.
+  Bytes(Vec<u8>),
+  /// Array where every element is itself a constant
+  ///
+  /// Each time it's loaded, the virtual machine creates a new array from it.
+  Array(Vec<ConstantValue>),
+  /// Name of a function used as a value, such as `gcd` in `f = gcd`
+  Function(String),
+}
+
+impl PartialEq for ConstantValue {
+  fn eq(&self, other: &Self) -> bool {
+    match (self, other) {
+      (ConstantValue::Nil, ConstantValue::Nil) => true,
+      (ConstantValue::Integer(left), ConstantValue::Integer(right)) => left == right,
+      #[cfg(feature = "bigint")]
+      (ConstantValue::BigInt(left), ConstantValue::BigInt(right)) => left == right,
+      (ConstantValue::Float(left), ConstantValue::Float(right)) => left.to_bits() == right.to_bits(),
+      (ConstantValue::Boolean(left), ConstantValue::Boolean(right)) => left == right,
+      (ConstantValue::String(left), ConstantValue::String(right)) => left == right,
+      (ConstantValue::Bytes(left), ConstantValue::Bytes(right)) => left == right,
+      (ConstantValue::Array(left), ConstantValue::Array(right)) => left == right,
+      (ConstantValue::Function(left), ConstantValue::Function(right)) => left == right,
+      _ => false,
+    }
+  }
+}
+
+impl Eq for ConstantValue { }
+
+impl Hash for ConstantValue {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    core::mem::discriminant(self).hash(state);
+    match self {
+      ConstantValue::Nil => (),
+      ConstantValue::Integer(integer) => integer.hash(state),
+      #[cfg(feature = "bigint")]
+      ConstantValue::BigInt(integer) => integer.hash(state),
+      ConstantValue::Float(float) => float.to_bits().hash(state),
+      ConstantValue::Boolean(boolean) => boolean.hash(state),
+      ConstantValue::String(string) => string.hash(state),
+      ConstantValue::Bytes(bytes) => bytes.hash(state),
+      ConstantValue::Array(elements) => elements.hash(state),
+      ConstantValue::Function(name) => name.hash(state),
+    }
+  }
+}
+
+/// Constant table of a [`Code`](crate::bytecode::Code), indexed by
+/// [`ConstantKey`]
+///
+/// A compiled procedure owns its constants.  After
+/// [`Program::share_constants`](crate::bytecode::Program::share_constants) they're moved
+/// into a pool shared with the other procedures of the program, and the procedure keeps
+/// the index into the pool of each of its constants, so a constant key `k` refers to
+/// `pool[keys[k]]`.
+///
+/// Tables are equal when they hold the same constants in the same order, however they're
+/// stored.
+///
+/// [`ConstantKey`]: crate::bytecode::operand::ConstantKey
+///
+/// # Example:
+/// ```rust
+/// # use lualite::bytecode::{constant_value::{Constants, ConstantValue}, operand::ConstantKey};
+/// let owned = Constants::from(vec![ConstantValue::Integer(1), ConstantValue::Nil]);
+/// let shared = Constants::Shared { pool: vec![ConstantValue::Nil, ConstantValue::Integer(1)].into(), keys: vec![1, 0] };
+/// assert_eq!(shared.get(ConstantKey(0)), Some(&ConstantValue::Integer(1)));
+/// assert_eq!(owned, shared);
+/// ```
+#[derive(Debug, Clone)]
+pub enum Constants {
+  Owned(Vec<ConstantValue>),
+  Shared { pool: Rc<[ConstantValue]>, keys: Vec<usize> },
+}
+
+impl Constants {
+  /// Constant that `key` refers to
+  pub fn get(&self, key: ConstantKey) -> Option<&ConstantValue> {
+    match self {
+      Constants::Owned(constants) => constants.get(key.0 as usize),
+      Constants::Shared { pool, keys } => keys.get(key.0 as usize).and_then(|&index| pool.get(index)),
+    }
+  }
+
+  /// Every constant, in order of their keys
+  pub fn iter(&self) -> impl Iterator<Item=&ConstantValue> {
+    match self {
+      Constants::Owned(constants) => Either::Left(constants.iter()),
+      Constants::Shared { pool, keys } => Either::Right(keys.iter().filter_map(|&index| pool.get(index))),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    match self {
+      Constants::Owned(constants) => constants.len(),
+      Constants::Shared { keys, .. } => keys.len(),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+impl Default for Constants {
+  fn default() -> Self {
+    Constants::Owned(Vec::new())
+  }
 }
 
+impl From<Vec<ConstantValue>> for Constants {
+  fn from(constants: Vec<ConstantValue>) -> Self {
+    Constants::Owned(constants)
+  }
+}
+
+impl PartialEq for Constants {
+  fn eq(&self, other: &Self) -> bool {
+    self.iter().eq(other.iter())
+  }
+}
+
+impl Eq for Constants { }
+
 impl From<IntegerLiteral> for ConstantValue {
   fn from(integer: IntegerLiteral) -> Self {
     Self::Integer(integer.0)
   }
 }
 
+#[cfg(feature = "bigint")]
+impl From<crate::ast::BigIntegerLiteral> for ConstantValue {
+  fn from(integer: crate::ast::BigIntegerLiteral) -> Self {
+    Self::BigInt(integer.0)
+  }
+}
+
 impl From<FloatLiteral> for ConstantValue {
   fn from(float: FloatLiteral) -> Self {
     Self::Float(float.0)
@@ -50,13 +196,22 @@ impl From<StringLiteral> for ConstantValue {
   }
 }
 
+impl From<BytesLiteral> for ConstantValue {
+  fn from(bytes: BytesLiteral) -> Self {
+    Self::Bytes(bytes.0)
+  }
+}
+
 impl fmt::Display for ConstantValue {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
+      ConstantValue::Nil => f.write_str("nil"),
       ConstantValue::Integer(integer) => {
         integer.fmt(f)?;
         f.write_str("_i64")
       }
+      #[cfg(feature = "bigint")]
+      ConstantValue::BigInt(integer) => integer.fmt(f),
       ConstantValue::Float(float) => {
         float.fmt(f)?;
         f.write_str("_f64")
@@ -69,17 +224,38 @@ impl fmt::Display for ConstantValue {
         string.fmt(f)?;
         f.write_str("\"")
       }
+      ConstantValue::Bytes(bytes) => write!(f, "b\"{}\"", bytes.escape_ascii()),
+      ConstantValue::Array(elements) => {
+        f.write_str("[")?;
+        let mut iter = elements.iter();
+        if let Some(first) = iter.next() {
+          first.fmt(f)?;
+        }
+        for element in iter {
+          f.write_str(", ")?;
+          element.fmt(f)?;
+        }
+        f.write_str("]")
+      }
+      ConstantValue::Function(name) => write!(f, "function {name}"),
     }
   }
 }
 
+#[cfg(feature = "std")]
 impl From<ConstantValue> for runtime::Value {
   fn from(constant_value: ConstantValue) -> runtime::Value {
     match constant_value {
+      ConstantValue::Nil => runtime::Value::Nil,
       ConstantValue::Integer(integer) => runtime::Value::from(integer),
+      #[cfg(feature = "bigint")]
+      ConstantValue::BigInt(integer) => runtime::Value::from(integer),
       ConstantValue::Float(float) => runtime::Value::from(float),
       ConstantValue::Boolean(boolean) => runtime::Value::from(boolean),
       ConstantValue::String(string) => runtime::Value::from(string),
+      ConstantValue::Bytes(bytes) => runtime::Value::Bytes(std::rc::Rc::new(std::cell::RefCell::new(bytes))),
+      ConstantValue::Array(elements) => runtime::Value::from_iter(elements),
+      ConstantValue::Function(name) => runtime::Value::Function(name.into()),
     }
   }
 }
@@ -1,6 +1,8 @@
 
-use std::fmt;
+use core::fmt::{self, Write};
+use alloc::{string::String, vec};
 use crate::bytecode::instruction::Instruction;
+use crate::bytecode::procedure::Procedure;
 use crate::bytecode::opcode::{
   SuperCode,
   misc::{self, move_subcode::DecodedMove},
@@ -10,8 +12,8 @@ use crate::bytecode::opcode::{
 };
 use crate::bytecode::operand::{
   FromDestination, FromSource,
-  RawRegister, Global,
-  WildDestination,
+  RawRegister, Global, Immediate,
+  WildDestination, WildSource,
   InstructionPointer, FunctionKey
 };
 
@@ -58,8 +60,13 @@ pub fn disassemble_instruction(f: &mut fmt::Formatter, instruction: Instruction)
           }
         }
         misc::Subcode::Move => {
-          let DecodedMove { destination, source } = misc::move_subcode::decode(instruction);
-          write!(f, "mov   {destination} = {source}")
+          use misc::move_subcode::ImmediateType;
+          let DecodedMove { destination, source, immediate_type } = misc::move_subcode::decode(instruction);
+          match (source, immediate_type) {
+            (WildSource::Immediate(Immediate(boolean)), ImmediateType::Boolean) =>
+              write!(f, "mov   {destination} = {}", boolean != 0),
+            (source, _) => write!(f, "mov   {destination} = {source}"),
+          }
         }
         misc::Subcode::Call => {
           use misc::call_subcode::ArgCount;
@@ -102,6 +109,8 @@ pub fn disassemble_instruction(f: &mut fmt::Formatter, instruction: Instruction)
       let DecodedArithmetic { subcode, destination, sources } = arithmetic::decode(instruction);
       let subcode_op_str = subcode.op_str();
       match sources {
+        Sources::SecondIsWild(first, _) if matches!(subcode, arithmetic::Subcode::Neg) =>
+          write!(f, "{subcode:<4}  {destination} = {subcode_op_str}{first}"),
         Sources::FirstIsWild(first, second) =>
           write!(f, "{subcode:<4}  {destination} = {first} {subcode_op_str} {second}"),
         Sources::SecondIsWild(first, second) =>
@@ -110,3 +119,55 @@ pub fn disassemble_instruction(f: &mut fmt::Formatter, instruction: Instruction)
     }
   }
 }
+
+/// Line by line diff of the bytecode of `a` and `b`
+///
+/// Instructions in both procedures start with a space, ones only in `a` start with `-`,
+/// and ones only in `b` start with `+`.  Each is followed by its index in `b` if it's only
+/// in `b`, or in `a` otherwise.  Instructions are lined up by the longest run they have in
+/// common, so removing one instruction gives a single `-` line rather than changing every
+/// line after it.
+///
+/// # Example:
+/// ```rust
+/// # use lualite::{parser, compiler, bytecode::disassemble};
+/// let (_, fn_decl) = parser::declaration::function_decl("function f(x) return x end").unwrap();
+/// let before = compiler::compile_function(&fn_decl).unwrap();
+/// let mut after = compiler::compile_function(&fn_decl).unwrap();
+/// after.bytecode.remove(0);
+/// assert!(disassemble::diff(&before, &after).starts_with("-    0  nop\n"));
+/// ```
+pub fn diff(a: &Procedure, b: &Procedure) -> String {
+  let (a, b) = (&a.bytecode, &b.bytecode);
+  // common[i][j] is how many instructions `a[i..]` and `b[j..]` have in common
+  let mut common = vec![vec![0_usize; b.len() + 1]; a.len() + 1];
+  for i in (0..a.len()).rev() {
+    for j in (0..b.len()).rev() {
+      common[i][j] = if a[i] == b[j] {
+        common[i + 1][j + 1] + 1
+      } else {
+        common[i + 1][j].max(common[i][j + 1])
+      };
+    }
+  }
+  let mut output = String::new();
+  let mut line = |marker: char, ip: usize, instruction: Instruction| {
+    // writing to a `String` can't fail
+    let _ = writeln!(output, "{marker} {ip:>4}  {instruction}");
+  };
+  let (mut i, mut j) = (0, 0);
+  while i < a.len() || j < b.len() {
+    if i < a.len() && j < b.len() && a[i] == b[j] {
+      line(' ', i, a[i]);
+      i += 1;
+      j += 1;
+    } else if j == b.len() || (i < a.len() && common[i + 1][j] >= common[i][j + 1]) {
+      line('-', i, a[i]);
+      i += 1;
+    } else {
+      line('+', j, b[j]);
+      j += 1;
+    }
+  }
+  output
+}
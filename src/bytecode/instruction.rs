@@ -1,9 +1,9 @@
 
-use std::fmt;
-use std::ops::{BitOr, BitOrAssign};
+use core::fmt;
+use core::ops::{BitOr, BitOrAssign};
 
 /// Transparent wrapper around an unsigned 32 bit integer
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[repr(transparent)]
 pub struct Instruction(pub u32);
 
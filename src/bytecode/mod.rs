@@ -18,16 +18,30 @@
 
 mod instruction;
 mod procedure;
+mod program;
 pub mod opcode;
 pub mod operand;
 pub mod disassemble;
 pub mod constant_value;
 
 use opcode::{SuperCode, misc, index, comparison, arithmetic};
-use operand::{AsDestination, AsSource, Register, WildSource, WildDestination, InstructionPointer, FunctionKey};
+use operand::{AsDestination, AsSource, Register, Immediate, WildSource, WildDestination, InstructionPointer, FunctionKey};
 
 pub use instruction::Instruction;
-pub use procedure::Procedure;
+pub use procedure::{Procedure, Code, ProcedureError};
+pub use program::Program;
+pub(crate) use program::Fnv;
+
+/// Names of the natives the compiler calls for language constructs
+///
+/// Each starts with a `.`, which a script's functions can't, so a script can't replace
+/// them by declaring a function with the same name.
+pub mod builtin {
+  /// Slice expressions like `a[i:j]` compile to `.slice(a, i, j)`
+  pub const SLICE: &str = ".slice";
+  /// Calling a local that holds a function value, like `f(x)`, compiles to `.call(f, x)`
+  pub const CALL: &str = ".call";
+}
 
 // Misc
 
@@ -68,6 +82,12 @@ pub fn mov<RD: Register, RS: Register>(dest: WildDestination<RD>, source: WildSo
     | dest.as_destination() | source.as_first()
 }
 
+/// Moves `true` or `false` into `dest` as a boolean immediate
+pub fn mov_bool<R: Register>(dest: WildDestination<R>, boolean: bool) -> Instruction {
+  use misc::move_subcode::ImmediateType;
+  mov(dest, WildSource::<R>::Immediate(Immediate(boolean as i8))) | ImmediateType::Boolean
+}
+
 pub fn call<RD: Register, RA: Register>(
   arg_count: misc::call_subcode::ArgCount,
   dest: RD,
@@ -100,6 +120,9 @@ pub fn index<RD: Register, RS: Register, RI: Register, D: Into<WildDestination<R
 }
 
 // Comparison
+//
+// There are no `_ww` builders: the opcode byte only has a single `WhichSourceIsWild` bit, so
+// one of the two sources must always be a register.
 
 pub fn cmp_wr<RD: Register, RF: Register, RS: Register, W: Into<WildSource<RF>>>(
   subcode: comparison::Subcode,
@@ -159,3 +182,8 @@ pub fn math_rw<RD: Register, RF: Register, RS: Register, W: Into<WildSource<RS>>
     | dest.as_destination() | first.as_first() | second.as_second()
 }
 
+
+pub fn neg<RD: Register, RS: Register>(dest: RD, source: RS) -> Instruction {
+  let unused: WildSource<RS> = operand::Immediate(0).into();
+  math_rw(arithmetic::Subcode::Neg, dest, source, unused)
+}
@@ -15,7 +15,7 @@
 //! +-- super code (always 0b_11 for arithmetic)
 //! ```
 
-use std::fmt;
+use core::fmt;
 use super::super::instruction::Instruction;
 use super::super::operand::{
   FromDestination, FromSource,
@@ -34,10 +34,10 @@ pub enum Subcode {
   Div = 0b_011_00,
   Rem = 0b_100_00,
   Pow = 0b_101_00,
-  /// Currently unused
-  Rot = 0b_110_00,
-  /// Currently unused
-  Log = 0b_111_00,
+  /// Unary negation of the first source operand (the second source is ignored)
+  Neg = 0b_110_00,
+  /// Division rounded towards negative infinity
+  FloorDiv = 0b_111_00,
 }
 
 impl Subcode {
@@ -52,8 +52,8 @@ impl Subcode {
       Div => "/",
       Rem => "%",
       Pow => "^",
-      Rot => unimplemented!(),
-      Log => unimplemented!(),
+      Neg => "-",
+      FloorDiv => "//",
     }
   }
 }
@@ -68,8 +68,8 @@ impl From<Instruction> for Subcode {
       0b_011 => Div,
       0b_100 => Rem,
       0b_101 => Pow,
-      0b_110 => Rot,
-      0b_111 => Log,
+      0b_110 => Neg,
+      0b_111 => FloorDiv,
       _ => unreachable!(),
     }
   }
@@ -85,8 +85,8 @@ impl fmt::Display for Subcode {
       Div => "div",
       Rem => "rem",
       Pow => "pow",
-      Rot => "root",
-      Log => "log",
+      Neg => "neg",
+      FloorDiv => "idiv",
     };
     s.fmt(f)
   }
@@ -1,5 +1,5 @@
 
-use std::ops::BitOr;
+use core::ops::BitOr;
 use super::super::operand::{WildDestination, WildSource, Register};
 use super::super::instruction::Instruction;
 
@@ -16,7 +16,7 @@
 //! +-- super code (always 0b_10 for comparison)
 //! ```
 
-use std::fmt;
+use core::fmt;
 use super::super::instruction::Instruction;
 use super::super::operand::{
   FromDestination, FromSource, Register,
@@ -63,6 +63,7 @@ pub type SourceType = common::WildSourceType<4>;
 /// How to interpret the index operand (bits 6..8)
 pub type IndexType = common::WildSourceType<6>;
 
+#[derive(Debug, Clone)]
 pub struct DecodedIndex {
   pub index_on: On,
   pub destination: WildDestination<RawRegister>,
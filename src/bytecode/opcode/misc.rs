@@ -7,12 +7,13 @@
 //! - Call
 //! - Interrupt (currently unimplemented)
 
-use std::fmt;
+use core::fmt;
 use super::super::instruction::Instruction;
 use super::super::operand::{
   FromDestination, FromSource,
   Register, RawRegister, Global, Immediate, ConstantKey,
   WildDestination, WildSource,
+  InstructionPointer, FunctionKey,
 };
 use super::common;
 
@@ -66,9 +67,9 @@ pub mod jump_subcode {
     Special = 0b_00_0000,
     /// Unconditional jump (no conditional operand)
     Always  = 0b_01_0000, // remaining 2 bits are unused
-    /// Jump if conditional operand (bits 8..16) is false
+    /// Jump if conditional operand (bits 8..16) is falsy (`nil` or `false`)
     IfFalse = 0b_10_0000,
-    /// Jump if conditional operand (bits 8..16) is true
+    /// Jump if conditional operand (bits 8..16) is truthy (not `nil` or `false`)
     IfTrue  = 0b_11_0000,
   }
 
@@ -161,9 +162,47 @@ pub mod move_subcode {
   pub type DestinationType = common::WildDestinationType<4>;
   pub type SourceType = common::WildSourceType<5>;
 
+  /// How to interpret an immediate source (bit 7)
+  ///
+  /// Only used when the source is [`SourceType::Immediate`](common::WildSourceType::Immediate).
+  /// Boolean immediates are `0` for `false` and `1` for `true`, so boolean literals don't
+  /// take up a slot in the constant table.
+  #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+  #[repr(u32)]
+  pub enum ImmediateType {
+    Integer = 0b_0000_0000,
+    Boolean = 0b_1000_0000,
+  }
+
+  impl ImmediateType {
+    pub const OFFSET: u32 = 7;
+    pub const MASK: u32 = 0b_1;
+  }
+
+  impl From<Instruction> for ImmediateType {
+    fn from(instruction: Instruction) -> Self {
+      match instruction.0 >> Self::OFFSET & Self::MASK {
+        0b_0 => ImmediateType::Integer,
+        0b_1 => ImmediateType::Boolean,
+        _ => unreachable!(),
+      }
+    }
+  }
+
+  impl From<ImmediateType> for Instruction {
+    fn from(immediate_type: ImmediateType) -> Instruction {
+      Instruction(immediate_type as u32)
+    }
+  }
+
+  #[derive(Debug, Clone)]
   pub struct DecodedMove {
     pub destination: WildDestination<RawRegister>,
     pub source: WildSource<RawRegister>,
+    /// Whether an [`Immediate`] source is an integer or a boolean
+    ///
+    /// Always [`ImmediateType::Integer`] for other sources.
+    pub immediate_type: ImmediateType,
   }
 
   pub fn decode(instruction: Instruction) -> DecodedMove {
@@ -179,7 +218,11 @@ pub mod move_subcode {
       SourceType::Immediate => Immediate::from_first(instruction).into(),
       SourceType::Constant => ConstantKey::from_first(instruction).into(),
     };
-    DecodedMove { destination, source }
+    let immediate_type = match source_type {
+      SourceType::Immediate => ImmediateType::from(instruction),
+      _ => ImmediateType::Integer,
+    };
+    DecodedMove { destination, source, immediate_type }
   }
 }
 
@@ -207,5 +250,22 @@ pub mod call_subcode {
       Instruction((arg_count.0 as u32) << ArgCount::OFFSET)
     }
   }
+
+  #[derive(Debug, Clone)]
+  pub struct DecodedCall {
+    pub arg_count: ArgCount,
+    pub destination: RawRegister,
+    pub function: FunctionKey,
+    pub arg_start: RawRegister,
+  }
+
+  pub fn decode(instruction: Instruction) -> DecodedCall {
+    DecodedCall {
+      arg_count: ArgCount::from(instruction),
+      destination: RawRegister::from_destination(instruction),
+      function: FunctionKey::from_first(instruction),
+      arg_start: RawRegister::from_second(instruction),
+    }
+  }
 }
 
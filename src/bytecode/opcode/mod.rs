@@ -12,8 +12,9 @@ pub mod index;
 pub mod comparison;
 pub mod arithmetic;
 
-use std::ops::BitOr;
+use core::ops::BitOr;
 use super::instruction::Instruction;
+use super::operand::{FromDestination, RawRegister, Global, WildDestination, InstructionPointer};
 
 /// Determines instruction category (bits 0..2)
 #[derive(Debug, Copy, Clone)]
@@ -76,3 +77,76 @@ impl<Rhs: Into<Instruction>> BitOr<Rhs> for SuperCode {
   }
 }
 
+
+/// An instruction with its opcode and operands decoded
+///
+/// Use [`decode`] to create one from an [`Instruction`].
+#[derive(Debug, Clone)]
+pub enum DecodedInstruction {
+  /// `nop`
+  NoOp,
+  /// `ret`
+  Return,
+  /// Unconditional jump
+  Jump(InstructionPointer),
+  /// Jump when the condition is falsy
+  JumpIfFalse {
+    condition: WildDestination<RawRegister>,
+    target: InstructionPointer,
+  },
+  /// Jump when the condition is truthy
+  JumpIfTrue {
+    condition: WildDestination<RawRegister>,
+    target: InstructionPointer,
+  },
+  Move(misc::move_subcode::DecodedMove),
+  Call(misc::call_subcode::DecodedCall),
+  Index(index::DecodedIndex),
+  Comparison(comparison::DecodedComparison),
+  Arithmetic(arithmetic::DecodedArithmetic),
+  /// Opcode reserved for instructions that don't exist yet
+  Reserved(Instruction),
+}
+
+/// Decodes an instruction of any category
+///
+/// # Example:
+/// ```rust
+/// # use lualite::bytecode::{self, opcode::{decode, DecodedInstruction}};
+/// assert!(matches!(decode(bytecode::ret()), DecodedInstruction::Return));
+/// ```
+pub fn decode(instruction: Instruction) -> DecodedInstruction {
+  use misc::jump_subcode::{Reason, Special, ConditionType};
+  let condition = || -> WildDestination<RawRegister> {
+    match ConditionType::from(instruction) {
+      ConditionType::Register => RawRegister::from_destination(instruction).into(),
+      ConditionType::Global => Global::from_destination(instruction).into(),
+    }
+  };
+  match SuperCode::from(instruction) {
+    SuperCode::Misc => match misc::Subcode::from(instruction) {
+      misc::Subcode::Jump => match Reason::from(instruction) {
+        Reason::Special => match Special::from(instruction) {
+          Special::NoOp => DecodedInstruction::NoOp,
+          Special::Return => DecodedInstruction::Return,
+          Special::Xa | Special::Xb => DecodedInstruction::Reserved(instruction),
+        },
+        Reason::Always => DecodedInstruction::Jump(InstructionPointer::from_both_operands(instruction)),
+        Reason::IfFalse => DecodedInstruction::JumpIfFalse {
+          condition: condition(),
+          target: InstructionPointer::from_both_operands(instruction),
+        },
+        Reason::IfTrue => DecodedInstruction::JumpIfTrue {
+          condition: condition(),
+          target: InstructionPointer::from_both_operands(instruction),
+        },
+      },
+      misc::Subcode::Move => DecodedInstruction::Move(misc::move_subcode::decode(instruction)),
+      misc::Subcode::Call => DecodedInstruction::Call(misc::call_subcode::decode(instruction)),
+      misc::Subcode::Interrupt => DecodedInstruction::Reserved(instruction),
+    },
+    SuperCode::Index => DecodedInstruction::Index(index::decode(instruction)),
+    SuperCode::Comparison => DecodedInstruction::Comparison(comparison::decode(instruction)),
+    SuperCode::Arithmetic => DecodedInstruction::Arithmetic(arithmetic::decode(instruction)),
+  }
+}
@@ -1,6 +1,6 @@
 //! Representation of the operand bytes
 
-use std::fmt;
+use core::fmt;
 use super::instruction::Instruction;
 
 /// Bit offset for the destination byte
@@ -187,11 +187,24 @@ pub struct ConstantKey(pub u8);
 
 impl fmt::Display for ConstantKey {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    // TODO: find a better way to forward format args
-    format!("&{}", self.0).fmt(f)
+    pad_with_prefix(f, b'&', self.0)
   }
 }
 
+/// Writes `prefix` and `number` through [`fmt::Formatter::pad`] so width and alignment
+/// apply to both of them together, without allocating
+fn pad_with_prefix(f: &mut fmt::Formatter, prefix: u8, number: u8) -> fmt::Result {
+  let mut buf = [prefix, 0, 0, 0];
+  let mut len = 1;
+  for divisor in [100, 10, 1] {
+    if number >= divisor || divisor == 1 {
+      buf[len] = b'0' + number / divisor % 10;
+      len += 1;
+    }
+  }
+  f.pad(core::str::from_utf8(&buf[..len]).unwrap_or_default())
+}
+
 impl AsSource for ConstantKey {
   fn as_first(self) -> Instruction {
     Instruction((self.0 as u32) << FIRST_SOURCE_OFFSET)
@@ -371,7 +384,7 @@ impl FunctionKey {
 
 impl fmt::Display for FunctionKey {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    format!("F{}", self.0).fmt(f)
+    pad_with_prefix(f, b'F', self.0)
   }
 }
 
@@ -1,28 +1,340 @@
 
-use std::fmt;
-use super::constant_value::ConstantValue;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ops::{Deref, DerefMut};
+use alloc::{rc::Rc, string::String, vec::Vec};
+use super::constant_value::{ConstantValue, Constants};
 use super::instruction::Instruction;
-use super::operand::{ConstantKey, FunctionKey};
+use super::operand::{ConstantKey, FunctionKey, RawRegister, WildSource, WildDestination};
+use super::opcode::{self, DecodedInstruction, arithmetic, comparison};
 
 /// Compiled function
-#[derive(Debug)]
+///
+/// A procedure is its [`name`](Self::name) along with its [`Code`], which it derefs to.
+/// The code is behind an [`Rc`] so that procedures compiled from identical functions can
+/// share it while keeping their own names.  Changing the code of a procedure that shares
+/// it gives the procedure its own copy first.
+#[derive(Debug, Clone)]
 pub struct Procedure {
+  /// Name the function was compiled under, such as `outer.inner` for a nested function
+  ///
+  /// Empty for procedures that weren't compiled from a named function.
+  pub name: String,
+  code: Rc<Code>,
+}
+
+/// Bytecode of a [`Procedure`] along with the tables its instructions refer to
+#[derive(Debug, Clone)]
+pub struct Code {
   pub bytecode: Vec<Instruction>,
   pub register_count: usize,
   pub max_args: usize,
-  pub constants: Vec<ConstantValue>,
+  pub constants: Constants,
   pub functions: Vec<String>,
+  /// Whether `bytecode` starts with a `nop` that is never executed
+  ///
+  /// With a leading `nop` the virtual machine advances the pc before fetching each
+  /// instruction, so a jump to `ip n` continues at instruction `n + 1`.  Without it, the
+  /// virtual machine fetches and then advances, and a jump to `ip n` continues at
+  /// instruction `n`.
+  pub leading_nop: bool,
+}
+
+/// Operand of a [`Procedure`]'s bytecode that refers past the end of what it indexes
+///
+/// Returned by [`Procedure::validate`].  `ip` is the index of the instruction in
+/// [`Code::bytecode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcedureError {
+  /// A [`ConstantKey`] not less than the length of the constant table
+  ConstantOutOfRange { ip: usize, key: u8 },
+  /// A [`FunctionKey`] not less than the length of the function table
+  FunctionOutOfRange { ip: usize, key: u8 },
+  /// A register not less than [`Code::register_count`], including the registers
+  /// a call passes as arguments
+  RegisterOutOfRange { ip: usize, register: usize },
+}
+
+impl Procedure {
+  pub fn new<S: Into<String>, C: Into<Rc<Code>>>(name: S, code: C) -> Self {
+    Self { name: name.into(), code: code.into() }
+  }
+
+  /// Code of the procedure, which other procedures may share
+  pub fn code(&self) -> &Rc<Code> {
+    &self.code
+  }
+
+  /// Names of the functions this procedure calls, indexed by [`FunctionKey`]
+  ///
+  /// Names are in the order the compiler first reached a call to them, and disassembly
+  /// lists them in this same order.
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::{parser, compiler};
+  /// let (_, fn_decl) = parser::declaration::function_decl("function f(x) return g(h(x), g(x)) end").unwrap();
+  /// let procedure = compiler::compile_function(&fn_decl).unwrap();
+  /// assert_eq!(procedure.function_table(), ["h", "g"]);
+  /// ```
+  pub fn function_table(&self) -> &[String] {
+    &self.functions
+  }
+
+  /// Whether the procedure calls itself by its own [`name`](Self::name)
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::{parser, compiler};
+  /// let (_, fn_decl) = parser::declaration::function_decl("function fact(n) return n < 2 ? 1 : n * fact(n - 1) end").unwrap();
+  /// let procedure = compiler::compile_function(&fn_decl).unwrap();
+  /// assert_eq!(procedure.name, "fact");
+  /// assert!(procedure.is_recursive());
+  /// ```
+  pub fn is_recursive(&self) -> bool {
+    !self.name.is_empty() && self.functions.contains(&self.name)
+  }
+
+  /// Iterates over the bytecode with each instruction already decoded
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::{parser, compiler, bytecode::opcode::DecodedInstruction};
+  /// let (_, fn_decl) = parser::declaration::function_decl("function f() return 1 end").unwrap();
+  /// let procedure = compiler::compile_function(&fn_decl).unwrap();
+  /// let decoded: Vec<DecodedInstruction> = procedure.decoded_instructions().collect();
+  /// assert!(matches!(decoded[..], [DecodedInstruction::NoOp, DecodedInstruction::Move(_), DecodedInstruction::Return]));
+  /// ```
+  pub fn decoded_instructions(&self) -> impl Iterator<Item=DecodedInstruction> + '_ {
+    self.bytecode.iter().map(|instruction| opcode::decode(*instruction))
+  }
+
+  /// Checks that every constant, function, and register operand is in range
+  ///
+  /// The compiler only produces valid procedures, this is for ones built by hand or
+  /// loaded from elsewhere before handing them to a virtual machine.  Jump targets aren't
+  /// checked since jumping out of bounds is already a runtime error.
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::{parser, compiler, bytecode::{self, operand::{RawRegister, ConstantKey, WildSource}}};
+  /// # use lualite::bytecode::ProcedureError;
+  /// let (_, fn_decl) = parser::declaration::function_decl("function f() return 1.5 end").unwrap();
+  /// let mut procedure = compiler::compile_function(&fn_decl).unwrap();
+  /// assert_eq!(procedure.validate(), Ok(()));
+  ///
+  /// procedure.bytecode.push(bytecode::mov(RawRegister(0).into(), WildSource::<RawRegister>::Constant(ConstantKey(1))));
+  /// assert_eq!(procedure.validate(), Err(ProcedureError::ConstantOutOfRange { ip: 3, key: 1 }));
+  /// ```
+  pub fn validate(&self) -> Result<(), ProcedureError> {
+    use DecodedInstruction::*;
+    for (ip, instruction) in self.decoded_instructions().enumerate() {
+      let operands = Operands { procedure: self, ip };
+      match instruction {
+        NoOp | Return | Jump(_) | Reserved(_) => (),
+        JumpIfFalse { condition, .. } | JumpIfTrue { condition, .. } => operands.destination(&condition)?,
+        Move(decoded) => {
+          operands.destination(&decoded.destination)?;
+          operands.source(&decoded.source)?;
+        }
+        Call(decoded) => {
+          operands.register(decoded.destination)?;
+          operands.function(decoded.function)?;
+          let arg_start = decoded.arg_start.0 as usize;
+          for register in arg_start..arg_start + decoded.arg_count.0 as usize {
+            operands.register_index(register)?;
+          }
+        }
+        Index(decoded) => {
+          operands.destination(&decoded.destination)?;
+          operands.source(&decoded.source)?;
+          operands.source(&decoded.index)?;
+        }
+        Comparison(decoded) => {
+          operands.register(decoded.destination)?;
+          match decoded.sources {
+            comparison::Sources::FirstIsWild(first, second) => {
+              operands.source(&first)?;
+              operands.register(second)?;
+            }
+            comparison::Sources::SecondIsWild(first, second) => {
+              operands.register(first)?;
+              operands.source(&second)?;
+            }
+          }
+        }
+        Arithmetic(decoded) => {
+          operands.register(decoded.destination)?;
+          match decoded.sources {
+            arithmetic::Sources::FirstIsWild(first, second) => {
+              operands.source(&first)?;
+              operands.register(second)?;
+            }
+            arithmetic::Sources::SecondIsWild(first, second) => {
+              operands.register(first)?;
+              operands.source(&second)?;
+            }
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Range checks for the operands of the instruction at `ip`
+struct Operands<'a> {
+  procedure: &'a Procedure,
+  ip: usize,
+}
+
+impl Operands<'_> {
+  fn register_index(&self, register: usize) -> Result<(), ProcedureError> {
+    if register < self.procedure.register_count {
+      Ok(())
+    } else {
+      Err(ProcedureError::RegisterOutOfRange { ip: self.ip, register })
+    }
+  }
+
+  fn register(&self, register: RawRegister) -> Result<(), ProcedureError> {
+    self.register_index(register.0 as usize)
+  }
+
+  fn function(&self, key: FunctionKey) -> Result<(), ProcedureError> {
+    if (key.0 as usize) < self.procedure.functions.len() {
+      Ok(())
+    } else {
+      Err(ProcedureError::FunctionOutOfRange { ip: self.ip, key: key.0 })
+    }
+  }
+
+  fn destination(&self, destination: &WildDestination<RawRegister>) -> Result<(), ProcedureError> {
+    match destination {
+      WildDestination::Register(register) => self.register(*register),
+      WildDestination::Global(_) => Ok(()),
+    }
+  }
+
+  fn source(&self, source: &WildSource<RawRegister>) -> Result<(), ProcedureError> {
+    match source {
+      WildSource::Register(register) => self.register(*register),
+      WildSource::Constant(key) if self.procedure.constant(*key).is_none() =>
+        Err(ProcedureError::ConstantOutOfRange { ip: self.ip, key: key.0 }),
+      WildSource::Constant(_) | WildSource::Global(_) | WildSource::Immediate(_) => Ok(()),
+    }
+  }
+}
+
+#[cfg(feature = "std")]
+impl Procedure {
+  /// Whether `self` and `other` give the same results when called with each argument list
+  /// in `inputs`
+  ///
+  /// Each run is in a fresh [`VirtualMachine`](crate::runtime::VirtualMachine) with the
+  /// standard natives and the procedures of `functions`, where `print` writes nowhere.
+  /// The procedure is added under its [`name`](Self::name), replacing any procedure of
+  /// `functions` with that name, so it can call itself.  Runs that fail are equal when both
+  /// fail with the same kind of [`RuntimeError`](crate::runtime::RuntimeError).
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::{parser, compiler, bytecode::Program};
+  /// let compile = |source_code| compiler::compile_function(&parser::declaration::function_decl(source_code).unwrap().1).unwrap();
+  /// let double = compile("function double(x) return x * 2 end");
+  /// let add_twice = compile("function add_twice(x) return x + x end");
+  /// let square = compile("function square(x) return x * x end");
+  /// let inputs = [vec![1.into()], vec![3.into()], vec![(-4).into()]];
+  /// assert!(double.behaviorally_equal_on(&add_twice, &Program::new(), &inputs));
+  /// assert!(!double.behaviorally_equal_on(&square, &Program::new(), &inputs));
+  /// ```
+  pub fn behaviorally_equal_on(&self, other: &Procedure, functions: &super::Program, inputs: &[Vec<crate::runtime::Value>]) -> bool {
+    use crate::runtime::VirtualMachine;
+    let run = |procedure: &Procedure, args: &[crate::runtime::Value]| {
+      let name = if procedure.name.is_empty() { VirtualMachine::ENTRY_NAME } else { procedure.name.as_str() };
+      let mut vm = VirtualMachine::with_functions(functions.clone());
+      vm.set_output(std::boxed::Box::new(std::io::sink()));
+      vm.insert_function(name, procedure.clone());
+      vm.run(name, args.iter().cloned())
+    };
+    inputs.iter().all(|args| match (run(self, args), run(other, args)) {
+      (Ok(left), Ok(right)) => left == right,
+      (Err(left), Err(right)) => core::mem::discriminant(&left) == core::mem::discriminant(&right),
+      _ => false,
+    })
+  }
+}
+
+impl Code {
+  /// Constant that `key` refers to, looked up in the shared pool if there is one
+  pub fn constant(&self, key: ConstantKey) -> Option<&ConstantValue> {
+    self.constants.get(key)
+  }
+
+  /// Every constant of the code, indexed by [`ConstantKey`]
+  pub fn constant_values(&self) -> impl Iterator<Item=&ConstantValue> {
+    self.constants.iter()
+  }
+}
+
+impl Deref for Procedure {
+  type Target = Code;
+  fn deref(&self) -> &Code {
+    &self.code
+  }
+}
+
+impl DerefMut for Procedure {
+  fn deref_mut(&mut self) -> &mut Code {
+    Rc::make_mut(&mut self.code)
+  }
+}
+
+/// Procedures are equal when they would run the same way, whatever their
+/// [`name`](Procedure::name)
+impl PartialEq for Procedure {
+  fn eq(&self, other: &Self) -> bool {
+    Rc::ptr_eq(&self.code, &other.code) || self.code == other.code
+  }
+}
+
+impl Eq for Procedure { }
+
+/// Code is equal when it would run the same way, even if one has its constants in a
+/// shared pool
+impl PartialEq for Code {
+  fn eq(&self, other: &Self) -> bool {
+    self.bytecode == other.bytecode
+      && self.register_count == other.register_count
+      && self.max_args == other.max_args
+      && self.constants == other.constants
+      && self.functions == other.functions
+      && self.leading_nop == other.leading_nop
+  }
+}
+
+impl Eq for Code { }
+
+/// Hashes everything but the constants, which still agrees with equality
+impl Hash for Code {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.bytecode.hash(state);
+    self.register_count.hash(state);
+    self.max_args.hash(state);
+    self.functions.hash(state);
+    self.leading_nop.hash(state);
+  }
 }
 
 impl fmt::Display for Procedure {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     writeln!(f, "registers: {}", self.register_count)?;
     writeln!(f, "arg count: {}", self.max_args)?;
-    if self.constants.is_empty() {
+    if self.constant_values().next().is_none() {
       writeln!(f, "constant table: (empty)")?;
     } else {
       writeln!(f, "constant table:")?;
-      for (constant_key, constant_value) in self.constants.iter().enumerate() {
+      for (constant_key, constant_value) in self.constant_values().enumerate() {
         let constant_key = ConstantKey(constant_key as u8);
         writeln!(f, "{constant_key:>4}: {constant_value:<}")?;
       }
@@ -31,7 +343,7 @@ impl fmt::Display for Procedure {
       writeln!(f, "function table: (empty)")?;
     } else {
       writeln!(f, "function table:")?;
-      for (function_key, function_name) in self.functions.iter().enumerate() {
+      for (function_key, function_name) in self.function_table().iter().enumerate() {
         let function_key = FunctionKey(function_key as u8);
         writeln!(f, "{function_key:>4}: {function_name:?}")?;
       }
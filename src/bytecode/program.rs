@@ -0,0 +1,165 @@
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use alloc::{rc::Rc, string::String, vec::Vec};
+use alloc::collections::{BTreeMap, btree_map};
+use super::procedure::{Procedure, Code};
+use super::constant_value::{ConstantValue, Constants};
+
+/// Compiled procedures of a program, each under the name it's called by
+///
+/// A `Program` can be collected from the output of
+/// [`compile_declarations`](crate::compiler::compile_declarations), and passed to
+/// [`VirtualMachine::with_functions`](crate::runtime::VirtualMachine::with_functions) in
+/// its place.  Displaying a program disassembles every procedure in order of their names.
+///
+/// # Example:
+/// ```rust
+/// # use lualite::{parser, compiler, bytecode::Program};
+/// let (_, declarations) = parser::parse_file("function one() return 1 end").unwrap();
+/// let program: Program = compiler::compile_declarations(declarations.iter()).unwrap().into_iter().collect();
+/// assert_eq!(program.procedure_names(), ["one"]);
+/// assert!(program.to_string().starts_with("\"one\":\n"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+  procedures: BTreeMap<String, Rc<Procedure>>,
+}
+
+impl Program {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Procedure named `name`, if the program has one
+  pub fn get<S: AsRef<str>>(&self, name: S) -> Option<&Rc<Procedure>> {
+    self.procedures.get(name.as_ref())
+  }
+
+  /// Adds `procedure` under `name`, returning the procedure it replaces
+  pub fn insert<S: Into<String>, P: Into<Rc<Procedure>>>(&mut self, name: S, procedure: P) -> Option<Rc<Procedure>> {
+    self.procedures.insert(name.into(), procedure.into())
+  }
+
+  /// Names of every procedure, sorted
+  pub fn procedure_names(&self) -> Vec<&str> {
+    self.procedures.keys().map(String::as_str).collect()
+  }
+
+  /// Procedures along with their names, sorted by name
+  pub fn iter(&self) -> impl Iterator<Item=(&str, &Rc<Procedure>)> {
+    self.procedures.iter().map(|(name, procedure)| (name.as_str(), procedure))
+  }
+
+  /// Moves the constants of every procedure into one pool they share
+  ///
+  /// A constant used by many procedures, like a long string, is then only stored once.
+  /// Constants are bucketed by their hash, so each is only compared with the pooled
+  /// constants in its bucket.
+  /// Procedures still refer to their constants with the same
+  /// [`ConstantKey`](super::operand::ConstantKey)s, so their bytecode is unchanged.
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::{parser, compiler, bytecode::{Program, constant_value::Constants}};
+  /// let source_code = "function a() return \"shared\" end function b() x = 1.5 return \"shared\" end";
+  /// let (_, declarations) = parser::parse_file(source_code).unwrap();
+  /// let mut program: Program = compiler::compile_declarations(declarations.iter()).unwrap().into_iter().collect();
+  /// program.share_constants();
+  /// let Constants::Shared { pool, .. } = &program.get("a").unwrap().constants else { panic!() };
+  /// assert_eq!(pool.len(), 2);
+  /// ```
+  pub fn share_constants(&mut self) {
+    let mut pool: Vec<ConstantValue> = Vec::new();
+    let mut buckets: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    // names that share code keep sharing it, so each code is only moved once
+    let mut codes: BTreeMap<*const Code, (Rc<Code>, Vec<usize>)> = BTreeMap::new();
+    for procedure in self.procedures.values() {
+      let code = procedure.code();
+      if let btree_map::Entry::Vacant(entry) = codes.entry(Rc::as_ptr(code)) {
+        let keys = code.constant_values()
+          .map(|constant| {
+            let mut hasher = Fnv::default();
+            constant.hash(&mut hasher);
+            let bucket = buckets.entry(hasher.finish()).or_default();
+            match bucket.iter().find(|&&index| pool[index] == *constant) {
+              Some(&index) => index,
+              None => {
+                pool.push(constant.clone());
+                bucket.push(pool.len() - 1);
+                pool.len() - 1
+              }
+            }
+          })
+          .collect();
+        entry.insert((Rc::clone(code), keys));
+      }
+    }
+    let pool: Rc<[ConstantValue]> = pool.into();
+    let shared: BTreeMap<*const Code, Rc<Code>> = codes.into_iter()
+      .map(|(pointer, (original, keys))| {
+        let mut code = Code::clone(&original);
+        code.constants = Constants::Shared { pool: Rc::clone(&pool), keys };
+        (pointer, Rc::new(code))
+      })
+      .collect();
+    for procedure in self.procedures.values_mut() {
+      let code = Rc::clone(&shared[&Rc::as_ptr(procedure.code())]);
+      *procedure = Rc::new(Procedure::new(procedure.name.clone(), code));
+    }
+  }
+}
+
+impl<S, P> FromIterator<(S, P)> for Program
+where
+  S: Into<String>,
+  P: Into<Rc<Procedure>>,
+{
+  fn from_iter<I: IntoIterator<Item=(S, P)>>(procedures: I) -> Self {
+    let procedures = procedures.into_iter()
+      .map(|(name, procedure)| (name.into(), procedure.into()))
+      .collect();
+    Self { procedures }
+  }
+}
+
+impl IntoIterator for Program {
+  type Item = (String, Rc<Procedure>);
+  type IntoIter = btree_map::IntoIter<String, Rc<Procedure>>;
+  fn into_iter(self) -> Self::IntoIter {
+    self.procedures.into_iter()
+  }
+}
+
+impl fmt::Display for Program {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for (index, (name, procedure)) in self.procedures.iter().enumerate() {
+      if index > 0 {
+        writeln!(f)?;
+      }
+      writeln!(f, "{name:?}:")?;
+      write!(f, "{procedure}")?;
+    }
+    Ok(())
+  }
+}
+
+/// FNV-1a hasher, since `core` doesn't have one
+pub(crate) struct Fnv(u64);
+
+impl Default for Fnv {
+  fn default() -> Self {
+    Self(0xcbf2_9ce4_8422_2325)
+  }
+}
+
+impl Hasher for Fnv {
+  fn finish(&self) -> u64 {
+    self.0
+  }
+
+  fn write(&mut self, bytes: &[u8]) {
+    for &byte in bytes {
+      self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3);
+    }
+  }
+}
@@ -0,0 +1,194 @@
+//! Common subexpression elimination within a statement
+//!
+//! The pass is opt-in with
+//! [`CompileOptions::common_subexpressions`](super::CompileOptions::common_subexpressions).
+//! When the same arithmetic expression appears more than once in a statement, like
+//!
+//! ```text
+//! area = (w + margin) * (w + margin)
+//! ```
+//!
+//! it's computed once into a hidden local before the statement:
+//!
+//! ```text
+//! .cse0 = w + margin
+//! area = .cse0 * .cse0
+//! ```
+//!
+//! Only expressions built from arithmetic operators, identifiers, and number literals are
+//! eliminated, since those always give the same value and can't fail.  Statements with a
+//! call anywhere in them are left alone, because the call could change a static between
+//! the repeats.  Conditions of `if` and `while` and the range of a `for` loop are never
+//! rewritten, but the statements in their bodies are.
+
+use alloc::{format, vec, vec::Vec};
+use crate::ast::{Statement, Expression, Identifier, BinaryOperator, UnaryOperator};
+
+/// Statements of `body` with repeated arithmetic within each statement computed once
+///
+/// # Example:
+/// ```rust
+/// # use lualite::parser::{statement::body, expression::expression};
+/// # use lualite::compiler::common_subexpressions::eliminate_common_subexpressions;
+/// # use lualite::ast::{Statement, Expression, Identifier, BinaryOperator};
+/// let (_, original) = body("y = (a + b) * (a + b)").unwrap();
+/// let hidden = || Box::new(Expression::Identifier(Identifier(".cse0".to_owned())));
+/// let expected = [
+///   Statement::AssignStatement(Identifier(".cse0".to_owned()), expression("a + b").unwrap().1),
+///   Statement::AssignStatement(Identifier("y".to_owned()), Expression::Binary {
+///     left: hidden(),
+///     op: BinaryOperator::Mul,
+///     right: hidden(),
+///   }),
+/// ];
+/// assert_eq!(eliminate_common_subexpressions(&original), expected);
+/// ```
+pub fn eliminate_common_subexpressions(body: &[Statement]) -> Vec<Statement> {
+  Eliminator { hidden_count: 0 }.body(body)
+}
+
+struct Eliminator {
+  /// Number of hidden locals made so far, used to name the next one
+  hidden_count: usize,
+}
+
+impl Eliminator {
+  fn body(&mut self, body: &[Statement]) -> Vec<Statement> {
+    body.iter().flat_map(|statement| self.statement(statement)).collect()
+  }
+
+  fn statement(&mut self, statement: &Statement) -> Vec<Statement> {
+    match statement {
+      Statement::WhileStatement { condition, body } => vec![Statement::WhileStatement {
+        condition: condition.clone(),
+        body: self.body(body),
+      }],
+      Statement::ForStatement { variable, start, end, body } => vec![Statement::ForStatement {
+        variable: variable.clone(),
+        start: start.clone(),
+        end: end.clone(),
+        body: self.body(body),
+      }],
+      Statement::IfStatement { condition, body, else_body } => vec![Statement::IfStatement {
+        condition: condition.clone(),
+        body: self.body(body),
+        else_body: else_body.as_deref().map(|else_body| self.body(else_body)),
+      }],
+      statement => {
+        let mut statement = statement.clone();
+        if expressions_mut(&mut statement).iter().any(|expression| has_call(expression)) {
+          return vec![statement];
+        }
+        let mut hoisted = Vec::new();
+        while let Some(repeated) = first_repeated(&mut statement) {
+          let hidden = Identifier(format!(".cse{}", self.hidden_count));
+          self.hidden_count += 1;
+          for expression in expressions_mut(&mut statement) {
+            replace(expression, &repeated, &hidden);
+          }
+          // the hoisted expression can have repeats of its own
+          hoisted.extend(self.statement(&Statement::AssignStatement(hidden, repeated)));
+        }
+        hoisted.push(statement);
+        hoisted
+      }
+    }
+  }
+}
+
+/// Expressions evaluated directly by a statement that isn't a loop or `if`
+fn expressions_mut(statement: &mut Statement) -> Vec<&mut Expression> {
+  match statement {
+    Statement::SingleStatement(expression) |
+    Statement::AssignStatement(_, expression) |
+    Statement::LocalStatement(_, expression) |
+    Statement::ReturnStatement(Some(expression)) => vec![expression],
+    Statement::IndexAssignStatement { table, index, value } => vec![table, index, value],
+    Statement::ExpressionList(expressions) => expressions.iter_mut().collect(),
+    _ => Vec::new(),
+  }
+}
+
+/// First expression, outermost first, that can be eliminated and appears more than once
+fn first_repeated(statement: &mut Statement) -> Option<Expression> {
+  let mut candidates = Vec::new();
+  for expression in expressions_mut(statement) {
+    collect_candidates(expression, &mut candidates);
+  }
+  candidates.iter()
+    .find(|candidate| candidates.iter().filter(|other| other == candidate).count() > 1)
+    .map(|repeated| (*repeated).clone())
+}
+
+fn collect_candidates<'a>(expression: &'a Expression, candidates: &mut Vec<&'a Expression>) {
+  if is_arithmetic(expression) {
+    candidates.push(expression);
+  }
+  for child in children(expression) {
+    collect_candidates(child, candidates);
+  }
+}
+
+/// Whether `expression` is an arithmetic operator applied to only identifiers, number
+/// literals, and more arithmetic
+fn is_arithmetic(expression: &Expression) -> bool {
+  let is_operand = |operand: &Expression| match operand {
+    Expression::Identifier(_) | Expression::Integer(_) | Expression::Float(_) => true,
+    #[cfg(feature = "bigint")]
+    Expression::BigInteger(_) => true,
+    operand => is_arithmetic(operand),
+  };
+  match expression {
+    Expression::Unary { op: UnaryOperator::Neg, right } => is_operand(right),
+    Expression::Binary { left, op, right } => matches!(op,
+      BinaryOperator::Pow | BinaryOperator::Mul | BinaryOperator::Div | BinaryOperator::FloorDiv |
+      BinaryOperator::Rem | BinaryOperator::Add | BinaryOperator::Sub
+    ) && is_operand(left) && is_operand(right),
+    _ => false,
+  }
+}
+
+/// Whether evaluating `expression` calls a function, including natives like `slice`
+fn has_call(expression: &Expression) -> bool {
+  match expression {
+    Expression::FunctionCall { .. } | Expression::NamedCall { .. } | Expression::Slice { .. } => true,
+    expression => children(expression).into_iter().any(has_call),
+  }
+}
+
+/// Replaces every `target` in `expression` with `hidden`
+fn replace(expression: &mut Expression, target: &Expression, hidden: &Identifier) {
+  if expression == target {
+    *expression = Expression::Identifier(hidden.clone());
+    return;
+  }
+  match expression {
+    Expression::Array(elements) => elements.iter_mut().for_each(|element| replace(element, target, hidden)),
+    Expression::Unary { right, .. } => replace(right, target, hidden),
+    Expression::Binary { left, right, .. } | Expression::Index { left, index: right } => {
+      replace(left, target, hidden);
+      replace(right, target, hidden);
+    }
+    Expression::Conditional { condition, then, else_ } => {
+      replace(condition, target, hidden);
+      replace(then, target, hidden);
+      replace(else_, target, hidden);
+    }
+    // statements with calls aren't rewritten
+    _ => (),
+  }
+}
+
+fn children(expression: &Expression) -> Vec<&Expression> {
+  match expression {
+    Expression::Array(elements) => elements.iter().collect(),
+    Expression::Unary { right, .. } => vec![&**right],
+    Expression::Binary { left, right, .. } | Expression::Index { left, index: right } => vec![&**left, &**right],
+    Expression::FunctionCall { left, args } => core::iter::once(&**left).chain(args.iter()).collect(),
+    Expression::NamedCall { left, args } => core::iter::once(&**left).chain(args.iter().map(|(_, arg)| arg)).collect(),
+    Expression::Slice { left, start, end } =>
+      core::iter::once(&**left).chain(start.as_deref()).chain(end.as_deref()).collect(),
+    Expression::Conditional { condition, then, else_ } => vec![&**condition, &**then, &**else_],
+    _ => Vec::new(),
+  }
+}
@@ -0,0 +1,43 @@
+use alloc::string::String;
+use crate::ast::Identifier;
+
+#[derive(Debug)]
+pub enum CompileError {
+  UnresolvedFunction(String),
+  /// A function declared with the name of a builtin, like `call`
+  ReservedFunctionName(String),
+  /// A static's initializer can't be evaluated at compile time
+  NonConstStatic(String),
+  /// Two statics with the same name
+  DuplicateStatic(String),
+  /// A language construct that parses but can't be compiled yet
+  Unsupported {
+    what: &'static str,
+    line: Option<u32>,
+  },
+  /// A function calls more distinct functions than a [`FunctionKey`] can refer to
+  ///
+  /// [`FunctionKey`]: crate::bytecode::operand::FunctionKey
+  TooManyCalledFunctions,
+  /// A function uses more distinct constants than a [`ConstantKey`] can refer to
+  ///
+  /// [`ConstantKey`]: crate::bytecode::operand::ConstantKey
+  TooManyConstants,
+  /// A nested function refers to a local of the function it's defined in, which isn't
+  /// supported yet
+  CapturedVariable(String),
+  /// A `break` or `continue` that isn't inside a loop
+  OutsideLoop(&'static str),
+  /// A named argument that isn't one of the called function's parameters
+  UnknownParameter {
+    function: String,
+    parameter: String,
+  },
+  /// An assignment to a parameter in [strict](super::CompileOptions::strict) mode
+  AssignToParameter(Identifier),
+  /// A call that names the same parameter more than once
+  DuplicateArgument {
+    function: String,
+    parameter: String,
+  },
+}
@@ -1,23 +1,25 @@
 
-use std::collections::HashMap;
-use std::cell::RefCell;
-use std::rc::Weak;
+use core::cell::RefCell;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::rc::Weak;
+use alloc::{boxed::Box, vec, vec::Vec};
 use either::{Either, Left, Right};
 use crate::ast::{
   self, Identifier, IntegerLiteral, FloatLiteral, BooleanLiteral, StringLiteral,
-  Statement, Expression, BinaryOperator,
+  Statement, Expression, BinaryOperator, UnaryOperator,
 };
 use crate::bytecode;
 use crate::bytecode::operand::{
   self, AsDestination, AsSource, Register,
-  RawRegister, Immediate, ConstantKey,
+  RawRegister, Global, Immediate, ConstantKey,
   WildSource, WildDestination,
   InstructionPointer, FunctionKey,
 };
 use crate::bytecode::constant_value::ConstantValue;
 use crate::bytecode::Instruction;
-use crate::bytecode::Procedure;
+use crate::bytecode::{Procedure, Code};
 use crate::compiler::temporary::{Temporary, TempManager};
+use crate::compiler::CompileError;
 
 type RawOrTemp = Either<RawRegister, Temporary>;
 impl Register for RawOrTemp { }
@@ -27,41 +29,175 @@ pub struct FunctionCompiler {
   bytecode: Vec<Instruction>,
   next_register: usize,
   param_count: usize,
-  ident_map: HashMap<Identifier, RawRegister>,
+  parameters: Vec<Identifier>,
+  strict: bool,
+  ident_map: BTreeMap<Identifier, RawRegister>,
+  globals: BTreeMap<Identifier, Global>,
   temps: TempManager,
   constants: Vec<ConstantValue>,
   function_keys: Vec<Identifier>,
+  leading_nop: bool,
+  name: Identifier,
+  local_functions: BTreeMap<Identifier, Identifier>,
+  function_values: BTreeSet<Identifier>,
+  parameter_names: BTreeMap<Identifier, Vec<Identifier>>,
+  nested_functions: Vec<(Identifier, ast::FunctionDecl)>,
+  loops: Vec<LoopContext>,
+  /// Bindings shadowed by `local` declarations in each block being compiled, restored
+  /// when the block ends
+  scopes: Vec<Vec<(Identifier, Option<RawRegister>)>>,
+}
+
+/// Jump targets of a loop being compiled, for `break` and `continue`
+#[derive(Debug, Default)]
+struct LoopContext {
+  /// Where `continue` jumps to, when it's known before the body is compiled
+  continue_target: Option<InstructionPointer>,
+  /// Offsets of `continue` jumps to patch once the continue target is known
+  continue_jumps: Vec<usize>,
+  /// Offsets of `break` jumps to patch with the end of the loop
+  break_jumps: Vec<usize>,
 }
 
 impl FunctionCompiler {
   pub fn with_parameters(parameters: &[Identifier]) -> Self {
+    Self::new(parameters, true)
+  }
+
+  /// Compiler for a procedure without the leading `nop`, see [`Code::leading_nop`]
+  pub fn nop_free_with_parameters(parameters: &[Identifier]) -> Self {
+    Self::new(parameters, false)
+  }
+
+  fn new(parameters: &[Identifier], leading_nop: bool) -> Self {
+    let bytecode = if leading_nop { vec![bytecode::nop()] } else { Vec::new() };
     Self {
-      bytecode: vec![bytecode::nop()],
+      temps: TempManager::new(bytecode.len()),
+      bytecode,
       next_register: 1 + parameters.len(), // +1 for return register
       param_count: parameters.len(),
-      ident_map: HashMap::from_iter(parameters.iter().zip(1..)
+      parameters: parameters.to_vec(),
+      strict: false,
+      ident_map: BTreeMap::from_iter(parameters.iter().zip(1..)
                                     .map(|(ident, reg_num)| (ident.clone(), RawRegister(reg_num)))),
-      temps: TempManager::new(1),
+      globals: BTreeMap::new(),
       constants: Vec::default(),
       function_keys: Vec::default(),
+      leading_nop,
+      name: Identifier(Default::default()),
+      local_functions: BTreeMap::new(),
+      function_values: BTreeSet::new(),
+      parameter_names: BTreeMap::new(),
+      nested_functions: Vec::new(),
+      loops: Vec::new(),
+      scopes: Vec::new(),
+    }
+  }
+
+  /// Makes assigning to a parameter a [`CompileError::AssignToParameter`]
+  pub fn set_strict(&mut self, strict: bool) {
+    self.strict = strict;
+  }
+
+  /// Sets the name the function is compiled under, used to name its nested functions
+  pub fn set_name(&mut self, name: &Identifier) {
+    self.name = name.clone();
+  }
+
+  /// Makes the nested functions of an enclosing function callable by their local names
+  ///
+  /// `local_functions` maps each local name to the name its procedure is compiled under.
+  pub fn set_local_functions(&mut self, local_functions: BTreeMap<Identifier, Identifier>) {
+    self.local_functions = local_functions;
+  }
+
+  /// Local names of the nested functions that can be called, mapped to the names their
+  /// procedures are compiled under
+  pub fn local_functions(&self) -> &BTreeMap<Identifier, Identifier> {
+    &self.local_functions
+  }
+
+  /// Takes the nested functions defined so far along with the names to compile them under
+  pub fn take_nested_functions(&mut self) -> Vec<(Identifier, ast::FunctionDecl)> {
+    core::mem::take(&mut self.nested_functions)
+  }
+
+  /// Whether `ident` is a parameter or local of the function
+  pub fn is_local(&self, ident: &Identifier) -> bool {
+    self.ident_map.contains_key(ident)
+  }
+
+  /// Whether `ident` refers to one of the function's parameters, and not to a `local`
+  /// shadowing it
+  fn is_parameter(&self, ident: &Identifier) -> bool {
+    self.parameters.iter().position(|parameter| parameter == ident)
+      .is_some_and(|index| self.ident_map.get(ident).map(|register| register.0 as usize) == Some(index + 1))
+  }
+
+  /// Makes the functions named in `functions` usable as values, such as `gcd` in `f = gcd`
+  ///
+  /// Names the function assigns or has as parameters should be left out, since those are
+  /// locals.
+  pub fn set_function_values(&mut self, functions: BTreeSet<Identifier>) {
+    self.function_values = functions;
+  }
+
+  /// Makes the functions in `parameter_names` callable with named arguments
+  ///
+  /// Each function name is mapped to the names of its parameters.  Nested functions are
+  /// added as they're declared.
+  pub fn set_parameter_names(&mut self, parameter_names: BTreeMap<Identifier, Vec<Identifier>>) {
+    self.parameter_names = parameter_names;
+  }
+
+  /// Parameter names of the function `callee` calls, if it can be called with named
+  /// arguments
+  pub fn parameter_names_of(&self, callee: &Identifier) -> Option<&[Identifier]> {
+    self.parameter_names.get(callee).map(Vec::as_slice)
+  }
+
+  /// Name of the function `ident` refers to when it's used as a value, if it isn't a local
+  /// or a static
+  pub fn function_value_for(&self, ident: &Identifier) -> Option<Identifier> {
+    if self.ident_map.contains_key(ident) || self.globals.contains_key(ident) {
+      None
+    } else if let Some(compiled_name) = self.local_functions.get(ident) {
+      Some(compiled_name.clone())
+    } else {
+      self.function_values.contains(ident).then(|| ident.clone())
     }
   }
 
+  /// Makes the statics named in `globals` visible to the function
+  ///
+  /// Each name refers to the global at its position in the slice.  Parameters and locals
+  /// shadow globals with the same name.
+  pub fn set_globals(&mut self, globals: &[Identifier]) {
+    self.globals = BTreeMap::from_iter(globals.iter().zip(0..=u8::MAX)
+                                       .map(|(ident, index)| (ident.clone(), Global(index))));
+  }
+
   pub fn finish(mut self) -> Procedure {
     self.temps.reconcile_deferred_temps(self.next_register as u8, &mut self.bytecode);
-    Procedure {
+    Procedure::new(self.name.0, Code {
       bytecode: self.bytecode,
-      register_count: 1 + self.ident_map.len() + self.temps.count(), // +1 for return register
+      register_count: self.next_register + self.temps.count(),
       max_args: self.param_count,
-      constants: self.constants,
+      constants: self.constants.into(),
       functions: self.function_keys.into_iter().map(|name| name.0).collect(),
-    }
+      leading_nop: self.leading_nop,
+    })
   }
 }
 
 impl FunctionCompiler {
+  /// Jump target that continues at the next instruction pushed
+  ///
+  /// With a leading `nop` the virtual machine advances before fetching, so the target is
+  /// one before the instruction itself.
   pub fn next_instruction_pointer(&self) -> InstructionPointer {
-    InstructionPointer((self.bytecode.len() - 1) as u16)
+    let offset = if self.leading_nop { 1 } else { 0 };
+    InstructionPointer((self.bytecode.len() - offset) as u16)
   }
 
   pub fn register_for_return(&self) -> RawRegister {
@@ -76,66 +212,69 @@ impl FunctionCompiler {
     })
   }
 
-  pub fn immediate_or_constant_for(&mut self, integer: &IntegerLiteral) -> WildSource<RawOrTemp> {
-    match integer {
-      IntegerLiteral(small_int @ -128..=127) => Immediate(*small_int as i8).into(),
-      IntegerLiteral(large_int) => {
-        let integer_as_constant = ConstantValue::Integer(*large_int);
-        for (index, constant) in self.constants.iter().enumerate() {
-          if &integer_as_constant == constant {
-            return ConstantKey(index as u8).into();
-          }
-        }
-        self.constants.push(integer_as_constant);
-        ConstantKey((self.constants.len() - 1) as u8).into()
-      }
+  pub fn global_for(&self, ident: &Identifier) -> Option<Global> {
+    if self.ident_map.contains_key(ident) {
+      None
+    } else {
+      self.globals.get(ident).copied()
     }
   }
 
-  pub fn constant_for_string(&mut self, string: &StringLiteral) -> ConstantKey {
-    for (index, constant) in self.constants.iter().enumerate() {
-      match constant {
-        ConstantValue::String(existing_string) if existing_string == &string.0 =>
-          return ConstantKey(index as u8),
-        _ => (),
-      }
+  pub fn source_for(&mut self, ident: &Identifier) -> Result<WildSource<RawOrTemp>, CompileError> {
+    if let Some(function) = self.function_value_for(ident) {
+      return Ok(self.constant_for(ConstantValue::Function(function.0))?.into());
+    }
+    match self.global_for(ident) {
+      Some(global) => Ok(global.into()),
+      None => Ok(WildSource::Register(Left(self.register_for(ident)))),
     }
-    self.constants.push(ConstantValue::String(string.0.clone()));
-    ConstantKey((self.constants.len() - 1) as u8)
   }
 
-  pub fn constant_for_float(&mut self, float: &FloatLiteral) -> ConstantKey {
-    for (index, constant) in self.constants.iter().enumerate() {
-      match constant {
-        ConstantValue::Float(existing_float) if existing_float == &float.0 =>
-          return ConstantKey(index as u8),
-        _ => (),
-      }
+  pub fn immediate_or_constant_for(&mut self, integer: &IntegerLiteral) -> Result<WildSource<RawOrTemp>, CompileError> {
+    match integer {
+      IntegerLiteral(small_int @ -128..=127) => Ok(Immediate(*small_int as i8).into()),
+      IntegerLiteral(large_int) => Ok(self.constant_for(ConstantValue::Integer(*large_int))?.into()),
     }
-    self.constants.push(ConstantValue::Float(float.0));
-    ConstantKey((self.constants.len() - 1) as u8)
   }
 
-  pub fn constant_for_boolean(&mut self, boolean: &BooleanLiteral) -> ConstantKey {
+  pub fn constant_for_string(&mut self, string: &StringLiteral) -> Result<ConstantKey, CompileError> {
+    self.constant_for(ConstantValue::String(string.0.clone()))
+  }
+
+  /// Floats are only shared with a constant that has exactly the same bits, see
+  /// [`ConstantValue`]'s equality
+  pub fn constant_for_float(&mut self, float: &FloatLiteral) -> Result<ConstantKey, CompileError> {
+    self.constant_for(ConstantValue::Float(float.0))
+  }
+
+  pub fn constant_for_nil(&mut self) -> Result<ConstantKey, CompileError> {
+    self.constant_for(ConstantValue::Nil)
+  }
+
+  /// Key of `value` in the constant table, adding it if it isn't there yet
+  ///
+  /// Fails with [`CompileError::TooManyConstants`] once the table is full.
+  pub fn constant_for(&mut self, value: ConstantValue) -> Result<ConstantKey, CompileError> {
     for (index, constant) in self.constants.iter().enumerate() {
-      match constant {
-        ConstantValue::Boolean(existing_boolean) if existing_boolean == &boolean.0 =>
-          return ConstantKey(index as u8),
-        _ => (),
+      if &value == constant {
+        return Ok(ConstantKey(index as u8));
       }
     }
-    self.constants.push(ConstantValue::Boolean(boolean.0));
-    ConstantKey((self.constants.len() - 1) as u8)
+    let key = u8::try_from(self.constants.len()).map_err(|_| CompileError::TooManyConstants)?;
+    self.constants.push(value);
+    Ok(ConstantKey(key))
   }
 
-  pub fn function_key_for(&mut self, function_name: Identifier) -> FunctionKey {
+  pub fn function_key_for(&mut self, function_name: Identifier) -> Result<FunctionKey, CompileError> {
+    let function_name = self.local_functions.get(&function_name).cloned().unwrap_or(function_name);
     for (index, existing_function_name) in self.function_keys.iter().enumerate() {
       if function_name == *existing_function_name {
-        return FunctionKey(index as u8);
+        return Ok(FunctionKey(index as u8));
       }
     }
+    let key = u8::try_from(self.function_keys.len()).map_err(|_| CompileError::TooManyCalledFunctions)?;
     self.function_keys.push(function_name);
-    FunctionKey((self.function_keys.len() - 1) as u8)
+    Ok(FunctionKey(key))
   }
 
   pub fn push(&mut self, instruction: Instruction) {
@@ -146,81 +285,240 @@ impl FunctionCompiler {
 
 // Statements
 impl FunctionCompiler {
-  pub fn compile_statement(&mut self, statement: &Statement) {
+  pub fn compile_statement(&mut self, statement: &Statement) -> Result<(), CompileError> {
     use Statement::*;
     match statement {
-      SingleStatement(expression) => self.compile_expression(Left(RawRegister(0)), expression), // TODO: Create a "void" destination
-      AssignStatement(identifier, expression) => {
-        let dest = self.register_for(identifier);
-        self.compile_expression(Left(dest), expression);
+      // the result is discarded, a scratch temp keeps it out of the return register
+      SingleStatement(expression) => {
+        let discard = Right(self.temps.take_temp());
+        self.compile_expression(discard, expression)?;
+      }
+      ExpressionList(expressions) => for expression in expressions.iter() {
+        let discard = Right(self.temps.take_temp());
+        self.compile_expression(discard, expression)?;
+      },
+      AssignStatement(identifier, _) if self.strict && self.is_parameter(identifier) =>
+        return Err(CompileError::AssignToParameter(identifier.clone())),
+      AssignStatement(identifier, expression) => match self.global_for(identifier) {
+        Some(global) => match expression {
+          Expression::Boolean(b) =>
+            self.push(bytecode::mov_bool(WildDestination::<RawOrTemp>::Global(global), b.0)),
+          _ => {
+            let source = self.compile_into_wildcard(expression)?;
+            self.push(bytecode::mov(WildDestination::<RawOrTemp>::Global(global), source));
+          }
+        },
+        None => {
+          let dest = self.register_for(identifier);
+          self.compile_expression(Left(dest), expression)?;
+        }
+      },
+      LocalStatement(identifier, expression) => {
+        // the new register is bound after the value is compiled, so `local x = x + 1`
+        // reads the `x` being shadowed
+        let dest = RawRegister(self.next_register.try_into().unwrap());
+        self.next_register += 1;
+        self.compile_expression(Left(dest), expression)?;
+        self.bind_local(identifier, dest);
       }
       IndexAssignStatement { table, index, value } => {
-        let dest = self.compile_into_register(table);
-        let index_wildcard = self.compile_into_wildcard(index);
-        let value_wildcard = self.compile_into_wildcard(value);
+        let dest = self.compile_into_register(table)?;
+        let index_wildcard = self.compile_into_wildcard(index)?;
+        let value_wildcard = self.compile_into_wildcard(value)?;
         let index_on = bytecode::opcode::index::On::Destination;
         self.push(bytecode::index(index_on, dest, value_wildcard, index_wildcard));
       }
       ReturnStatement(maybe_expression) => {
         if let Some(expression) = maybe_expression {
-          self.compile_expression(Left(self.register_for_return()), expression);
+          self.compile_expression(Left(self.register_for_return()), expression)?;
         }
         self.push(bytecode::ret());
       }
       WhileStatement { condition, body } =>
-        self.compile_while_statement(condition, body),
+        self.compile_while_statement(condition, body)?,
+      // the loop variable is assigned on every iteration
+      ForStatement { variable, .. } if self.strict && self.is_parameter(variable) =>
+        return Err(CompileError::AssignToParameter(variable.clone())),
+      ForStatement { variable, start, end, body } =>
+        self.compile_for_statement(variable, start, end, body)?,
+      FunctionStatement(function) => self.declare_nested_function(function),
+      BreakStatement => {
+        let jump_offset = self.bytecode.len();
+        let context = self.loops.last_mut().ok_or(CompileError::OutsideLoop("break"))?;
+        context.break_jumps.push(jump_offset);
+        self.push(bytecode::jmp(InstructionPointer::empty_place_holder()));
+      }
+      ContinueStatement => {
+        let jump_offset = self.bytecode.len();
+        let context = self.loops.last_mut().ok_or(CompileError::OutsideLoop("continue"))?;
+        match context.continue_target {
+          Some(target) => self.push(bytecode::jmp(target)),
+          None => {
+            context.continue_jumps.push(jump_offset);
+            self.push(bytecode::jmp(InstructionPointer::empty_place_holder()));
+          }
+        }
+      }
       IfStatement { condition, body, else_body } =>
-        self.compile_if_statement(condition, body, else_body.as_deref()),
+        self.compile_if_statement(condition, body, else_body.as_deref())?,
     }
+    Ok(())
   }
 
-  pub fn compile_while_statement(&mut self, condition: &Expression, body: &[Statement]) {
+  /// Compiles the body of a loop or `if` clause
+  ///
+  /// Locals declared with `local` in the body go out of scope at the end of it, and any
+  /// variables they shadowed are visible again.
+  pub fn compile_block(&mut self, body: &[Statement]) -> Result<(), CompileError> {
+    self.scopes.push(Vec::new());
+    let compiled = body.iter().try_for_each(|statement| self.compile_statement(statement));
+    let shadowed = self.scopes.pop().expect("a block was started");
+    for (identifier, register) in shadowed.into_iter().rev() {
+      match register {
+        Some(register) => self.ident_map.insert(identifier, register),
+        None => self.ident_map.remove(&identifier),
+      };
+    }
+    compiled
+  }
+
+  /// Binds `identifier` to `register` for the rest of the current block
+  fn bind_local(&mut self, identifier: &Identifier, register: RawRegister) {
+    let shadowed = self.ident_map.insert(identifier.clone(), register);
+    if let Some(scope) = self.scopes.last_mut() {
+      scope.push((identifier.clone(), shadowed));
+    }
+  }
+
+  /// Makes a nested function callable by its name for the rest of the function
+  ///
+  /// No bytecode is emitted, the nested function is compiled into its own procedure named
+  /// `enclosing.nested`.
+  pub fn declare_nested_function(&mut self, function: &ast::FunctionDecl) {
+    let compiled_name = Identifier(alloc::format!("{}.{}", self.name.0, function.name.0));
+    self.local_functions.insert(function.name.clone(), compiled_name.clone());
+    self.parameter_names.insert(function.name.clone(), function.params.clone());
+    self.nested_functions.push((compiled_name, function.clone()));
+  }
+
+  /// Fills in parameters that are missing or `nil` with their default values
+  ///
+  /// Missing arguments are `nil` when a function is called, so each default compiles to
+  /// `if param == nil then param = default end` at the start of the function.  An
+  /// argument that is explicitly `nil` gets its default too, see [`FunctionDecl::defaults`].
+  ///
+  /// [`FunctionDecl::defaults`]: crate::ast::FunctionDecl::defaults
+  pub fn compile_parameter_defaults(&mut self, params: &[Identifier], defaults: &[Option<Expression>]) -> Result<(), CompileError> {
+    for (param, default) in params.iter().zip(defaults.iter()) {
+      if let Some(default) = default {
+        let is_nil = Expression::Binary {
+          left: Box::new(Expression::Identifier(param.clone())),
+          op: BinaryOperator::Eq,
+          right: Box::new(Expression::Nil),
+        };
+        let assign_default = Statement::AssignStatement(param.clone(), default.clone());
+        // filling in a default isn't an assignment from the source
+        let strict = core::mem::replace(&mut self.strict, false);
+        let compiled = self.compile_if_statement(&is_nil, &[assign_default], None);
+        self.strict = strict;
+        compiled?;
+      }
+    }
+    Ok(())
+  }
+
+  pub fn compile_while_statement(&mut self, condition: &Expression, body: &[Statement]) -> Result<(), CompileError> {
     let begin_ip = self.next_instruction_pointer();
     // while
-    let conditional_register = match condition {
-      Expression::Identifier(conditional_ident) => Left(self.register_for(conditional_ident)),
-      other => {
-        let temp_dest = Right(self.temps.take_temp());
-        self.compile_expression(temp_dest.clone(), condition);
-        temp_dest
-      }
-    };
+    // a condition temporary is freed by the jump, so the body may reuse its register;
+    // that's safe since the condition is recomputed at `begin_ip` on every iteration
+    let condition = self.compile_condition(condition)?;
     let jump_offset = self.bytecode.len();
-    self.push(bytecode::jmp_if_false(conditional_register.into(), InstructionPointer::empty_place_holder()));
+    self.push(bytecode::jmp_if_false(condition, InstructionPointer::empty_place_holder()));
     // do
-    for statement in body.iter() {
-      self.compile_statement(statement);
+    self.loops.push(LoopContext { continue_target: Some(begin_ip), ..LoopContext::default() });
+    self.compile_block(body)?;
+    self.push(bytecode::jmp(begin_ip));
+    // end
+    let end_ip = self.next_instruction_pointer();
+    self.bytecode[jump_offset] |= end_ip.as_both_operands();
+    self.finish_loop(begin_ip, end_ip);
+    Ok(())
+  }
+
+  /// Patches the `break` and `continue` jumps of the innermost loop
+  fn finish_loop(&mut self, continue_ip: InstructionPointer, end_ip: InstructionPointer) {
+    let context = self.loops.pop().expect("a loop was started");
+    for offset in context.continue_jumps {
+      self.bytecode[offset] |= continue_ip.as_both_operands();
+    }
+    for offset in context.break_jumps {
+      self.bytecode[offset] |= end_ip.as_both_operands();
     }
+  }
+
+  /// Compiles a range for loop using two hidden temporaries
+  ///
+  /// The counter is kept apart from the loop variable so assigning to the variable in the
+  /// body doesn't change how many times the loop runs. The loop variable is always a local.
+  pub fn compile_for_statement(&mut self, variable: &Identifier, start: &Expression, end: &Expression, body: &[Statement]) -> Result<(), CompileError> {
+    use bytecode::opcode::{arithmetic, comparison};
+    // for variable in start..end
+    let counter = Right(self.temps.take_temp());
+    self.compile_expression(counter.clone(), start)?;
+    let limit = Right(self.temps.take_temp());
+    self.compile_expression(limit.clone(), end)?;
+    let begin_ip = self.next_instruction_pointer();
+    let condition = Right(self.temps.take_temp());
+    self.push(bytecode::cmp_rw(comparison::Subcode::Lt, condition.clone(), counter.clone(), WildSource::Register(limit.clone())));
+    let jump_offset = self.bytecode.len();
+    self.push(bytecode::jmp_if_false(WildDestination::Register(condition), InstructionPointer::empty_place_holder()));
+    let variable = Left(self.register_for(variable));
+    self.push(bytecode::mov(WildDestination::Register(variable), WildSource::Register(counter.clone())));
+    // do
+    self.loops.push(LoopContext::default());
+    self.compile_block(body)?;
+    // `continue` comes here rather than `begin_ip` so the counter still advances
+    let increment_ip = self.next_instruction_pointer();
+    self.push(bytecode::math_rw(arithmetic::Subcode::Add, counter.clone(), counter, WildSource::<RawOrTemp>::from(Immediate(1))));
     self.push(bytecode::jmp(begin_ip));
     // end
     let end_ip = self.next_instruction_pointer();
     self.bytecode[jump_offset] |= end_ip.as_both_operands();
+    self.finish_loop(increment_ip, end_ip);
+    // the counter and limit stay taken until here, so nothing in the body overwrites them
+    drop(limit);
+    Ok(())
   }
 
-  pub fn compile_if_statement(&mut self, condition: &Expression, body: &[Statement], else_body: Option<&[Statement]>) {
-    // if
-    let conditional_register = match condition {
-      Expression::Identifier(conditional_ident) => Left(self.register_for(conditional_ident)),
+  pub fn compile_condition(&mut self, condition: &Expression) -> Result<WildDestination<RawOrTemp>, CompileError> {
+    let compiled = match condition {
+      Expression::Identifier(conditional_ident) if self.function_value_for(conditional_ident).is_none() => match self.global_for(conditional_ident) {
+        Some(global) => global.into(),
+        None => WildDestination::Register(Left(self.register_for(conditional_ident))),
+      },
       other => {
         let temp_dest = Right(self.temps.take_temp());
-        self.compile_expression(temp_dest.clone(), condition);
-        temp_dest
+        self.compile_expression(temp_dest.clone(), condition)?;
+        WildDestination::Register(temp_dest)
       }
     };
+    Ok(compiled)
+  }
+
+  pub fn compile_if_statement(&mut self, condition: &Expression, body: &[Statement], else_body: Option<&[Statement]>) -> Result<(), CompileError> {
+    // if
+    let condition = self.compile_condition(condition)?;
     let if_false_jump_offset = self.bytecode.len();
-    self.push(bytecode::jmp_if_false(WildDestination::Register(conditional_register), InstructionPointer::empty_place_holder()));
+    self.push(bytecode::jmp_if_false(condition, InstructionPointer::empty_place_holder()));
     // then
-    for statement in body.iter() {
-      self.compile_statement(statement);
-    }
+    self.compile_block(body)?;
     if let Some(else_body) = else_body {
       // else
       let jump_over_else_offset = self.bytecode.len();
       self.push(bytecode::jmp(InstructionPointer::empty_place_holder())); // jump to end_ip
       let else_body_ip = self.next_instruction_pointer();
-      for statement in else_body.iter() {
-        self.compile_statement(statement);
-      }
+      self.compile_block(else_body)?;
       let end_ip = self.next_instruction_pointer();
       self.bytecode[if_false_jump_offset] |= else_body_ip.as_both_operands();
       self.bytecode[jump_over_else_offset] |= end_ip.as_both_operands();
@@ -230,41 +528,63 @@ impl FunctionCompiler {
       self.bytecode[if_false_jump_offset] |= end_ip.as_both_operands();
       // end
     }
+    Ok(())
   }
 }
 
 // Expressions
 impl FunctionCompiler {
-  pub fn compile_expression<D: Into<RawOrTemp>>(&mut self, dest: D, expression: &Expression) {
+  pub fn compile_expression<D: Into<RawOrTemp>>(&mut self, dest: D, expression: &Expression) -> Result<(), CompileError> {
     let dest: RawOrTemp = dest.into();
     use Expression::*;
     match expression {
       Identifier(ident) => {
         let dest = WildDestination::Register(dest);
-        let src = WildSource::Register(self.register_for(ident));
+        let src = self.source_for(ident)?;
         self.push(bytecode::mov(dest, src));
       }
       Integer(int) => {
         let dest = WildDestination::Register(dest);
-        let src = self.immediate_or_constant_for(int);
+        let src = self.immediate_or_constant_for(int)?;
+        self.push(bytecode::mov(dest, src));
+      }
+      #[cfg(feature = "bigint")]
+      BigInteger(int) => {
+        let dest = WildDestination::Register(dest);
+        let src: WildSource<RawRegister> = self.constant_for(ConstantValue::BigInt(int.0.clone()))?.into();
         self.push(bytecode::mov(dest, src));
       }
       Float(flt) => {
         let dest = WildDestination::Register(dest);
-        let src: WildSource<RawRegister> = self.constant_for_float(flt).into();
+        let src: WildSource<RawRegister> = self.constant_for_float(flt)?.into();
         self.push(bytecode::mov(dest, src));
       }
       String(string) => {
         let dest = WildDestination::Register(dest);
-        let src: WildSource<RawRegister> = self.constant_for_string(string).into();
+        let src: WildSource<RawRegister> = self.constant_for_string(string)?.into();
         self.push(bytecode::mov(dest, src));
       }
-      Boolean(b) => {
+      Bytes(bytes) => {
         let dest = WildDestination::Register(dest);
-        let src: WildSource<RawRegister> = self.constant_for_boolean(b).into();
+        let src: WildSource<RawRegister> = self.constant_for(ConstantValue::Bytes(bytes.0.clone()))?.into();
         self.push(bytecode::mov(dest, src));
       }
-      Unary { op, right } => todo!(),
+      Boolean(b) => self.push(bytecode::mov_bool(WildDestination::Register(dest), b.0)),
+      Nil => {
+        let dest = WildDestination::Register(dest);
+        let src: WildSource<RawRegister> = self.constant_for_nil()?.into();
+        self.push(bytecode::mov(dest, src));
+      }
+      Array(elements) => self.compile_array_literal(dest, elements)?,
+      Unary { op: UnaryOperator::Neg, right } => {
+        let source = self.compile_into_register(right)?;
+        self.push(bytecode::neg(dest, source));
+      }
+      Unary { op: UnaryOperator::Not, right } => self.compile_not_expression(dest, right)?,
+      Binary { left, op: op @ (BinaryOperator::And | BinaryOperator::Or), right } =>
+        self.compile_logical_expression(dest, left, op, right)?,
+      Conditional { condition, then, else_ } =>
+        self.compile_conditional_expression(dest, condition, then, else_)?,
       Binary { left, op, right } => { // TODO: rewrite this arm
         use bytecode::opcode::{arithmetic, comparison};
         let arithmetic_subcode = match op {
@@ -272,6 +592,7 @@ impl FunctionCompiler {
           BinaryOperator::Sub => Some(arithmetic::Subcode::Sub),
           BinaryOperator::Mul => Some(arithmetic::Subcode::Mul),
           BinaryOperator::Div => Some(arithmetic::Subcode::Div),
+          BinaryOperator::FloorDiv => Some(arithmetic::Subcode::FloorDiv),
           BinaryOperator::Rem => Some(arithmetic::Subcode::Rem),
           BinaryOperator::Pow => Some(arithmetic::Subcode::Pow),
           _ => None,
@@ -285,29 +606,32 @@ impl FunctionCompiler {
           BinaryOperator::Ge => Some(comparison::Subcode::Ge),
           _ => None,
         };
-        if self.needs_wildcard(left) {
-          let first = self.compile_into_wildcard(left);
-          let second = self.compile_into_register(right);
-          match (arithmetic_subcode, comparison_subcode) {
+        match self.compile_binary_sources(left, right)? {
+          BinarySources::FirstIsWild(first, second) => match (arithmetic_subcode, comparison_subcode) {
             (Some(subcode), None) => self.push(bytecode::math_wr(subcode, dest, first, second)),
             (None, Some(subcode)) => self.push(bytecode::cmp_wr(subcode, dest, first, second)),
             _ => unreachable!(),
-          }
-        } else {
-          let first = self.compile_into_register(left);
-          let second = self.compile_into_wildcard(right);
-          match (arithmetic_subcode, comparison_subcode) {
+          },
+          BinarySources::SecondIsWild(first, second) => match (arithmetic_subcode, comparison_subcode) {
             (Some(subcode), None) => self.push(bytecode::math_rw(subcode, dest, first, second)),
             (None, Some(subcode)) => self.push(bytecode::cmp_rw(subcode, dest, first, second)),
             _ => unreachable!(),
-          }
+          },
         }
       }
+      // a local holds a function value, which the `call` builtin calls
+      FunctionCall { left, args } if matches!(&**left, Expression::Identifier(callee) if self.is_local(callee)) => {
+        let call = Expression::FunctionCall {
+          left: Box::new(Expression::Identifier(ast::Identifier(bytecode::builtin::CALL.into()))),
+          args: core::iter::once((**left).clone()).chain(args.iter().cloned()).collect(),
+        };
+        self.compile_expression(dest, &call)?;
+      }
       FunctionCall { left, args } if matches!(**left, Expression::Identifier(_)) => {
         use crate::bytecode::opcode::misc::call_subcode::ArgCount;
         let arg_temps = self.temps.take_temp_range(args.len());
         for (arg_temp, arg_expression) in arg_temps.iter().zip(args.iter()) {
-          self.compile_expression(Right(arg_temp.clone()), arg_expression);
+          self.compile_expression(Right(arg_temp.clone()), arg_expression)?;
         }
         let arg_start = match arg_temps.iter().next() {
           Some(arg) => Right(arg.clone()),
@@ -317,17 +641,162 @@ impl FunctionCompiler {
           Expression::Identifier(ref ident) => ident.clone(),
           _ => unreachable!(),
         };
-        let fn_key = self.function_key_for(function_name);
+        let fn_key = self.function_key_for(function_name)?;
         self.push(bytecode::call(ArgCount(args.len() as u8), dest, fn_key, arg_start));
       }
-      FunctionCall { left, args } => todo!(),
+      FunctionCall { left, args } =>
+        return Err(CompileError::Unsupported { what: "calling an expression that isn't a function name", line: None }),
+      NamedCall { left, args } => {
+        use crate::bytecode::opcode::misc::call_subcode::ArgCount;
+        let callee = match **left {
+          Expression::Identifier(ref callee) if !self.is_local(callee) => callee,
+          _ => return Err(CompileError::Unsupported { what: "named arguments to a function value", line: None }),
+        };
+        let positions = self.named_argument_positions(callee, args)?;
+        // arguments are evaluated in the order they're written, each into its parameter's
+        // register, and skipped parameters are `nil`
+        let arg_count = positions.iter().max().map_or(0, |last| last + 1);
+        let arg_temps = self.temps.take_temp_range(arg_count);
+        for (position, (_, arg_expression)) in positions.iter().zip(args.iter()) {
+          self.compile_expression(Right(arg_temps[*position].clone()), arg_expression)?;
+        }
+        for (position, arg_temp) in arg_temps.iter().enumerate() {
+          if !positions.contains(&position) {
+            self.compile_expression(Right(arg_temp.clone()), &Expression::Nil)?;
+          }
+        }
+        let arg_start = match arg_temps.first() {
+          Some(arg) => Right(arg.clone()),
+          None => Left(RawRegister(0)),
+        };
+        let fn_key = self.function_key_for(callee.clone())?;
+        self.push(bytecode::call(ArgCount(arg_count as u8), dest, fn_key, arg_start));
+      }
       Index { left, index } => {
-        let source_register = self.compile_into_wildcard(left);
-        let index_wildcard = self.compile_into_wildcard(index);
+        let source_register = self.compile_into_wildcard(left)?;
+        let index_wildcard = self.compile_into_wildcard(index)?;
         let index_on = bytecode::opcode::index::On::Source;
         self.push(bytecode::index(index_on, dest, source_register, index_wildcard));
       },
+      Slice { left, start, end } => {
+        // `a[i:j]` is `.slice(a, i, j)`, with `nil` for a missing bound
+        let bound = |bound: &Option<Box<Expression>>| bound.as_deref().cloned().unwrap_or(Expression::Nil);
+        let call = Expression::FunctionCall {
+          left: Box::new(Expression::Identifier(ast::Identifier(bytecode::builtin::SLICE.into()))),
+          args: vec![(**left).clone(), bound(start), bound(end)],
+        };
+        self.compile_expression(dest, &call)?;
+      }
     }
+    Ok(())
+  }
+}
+
+impl FunctionCompiler {
+  /// Parameter position of each named argument in a call to `callee`
+  pub fn named_argument_positions(&self, callee: &Identifier, args: &[(Identifier, Expression)]) -> Result<Vec<usize>, CompileError> {
+    let params = self.parameter_names_of(callee)
+      .ok_or(CompileError::Unsupported { what: "named arguments to a function without known parameters", line: None })?;
+    let mut positions: Vec<usize> = Vec::with_capacity(args.len());
+    for (name, _) in args.iter() {
+      let position = params.iter().position(|param| param == name)
+        .ok_or_else(|| CompileError::UnknownParameter { function: callee.0.clone(), parameter: name.0.clone() })?;
+      if positions.contains(&position) {
+        return Err(CompileError::DuplicateArgument { function: callee.0.clone(), parameter: name.0.clone() });
+      }
+      positions.push(position);
+    }
+    Ok(positions)
+  }
+
+  /// Compiles an array literal by loading it from the constant table
+  ///
+  /// Elements that aren't literals are left as `nil` in the constant and filled in with
+  /// index instructions afterwards.
+  pub fn compile_array_literal(&mut self, dest: RawOrTemp, elements: &[Expression]) -> Result<(), CompileError> {
+    let template = elements.iter()
+      .map(|element| literal_constant(element).unwrap_or(ConstantValue::Nil))
+      .collect();
+    let array_constant: WildSource<RawRegister> = self.constant_for(ConstantValue::Array(template))?.into();
+    let dynamic_elements: Vec<(usize, &Expression)> = elements.iter()
+      .enumerate()
+      .filter(|(_, element)| literal_constant(element).is_none())
+      .collect();
+    if dynamic_elements.is_empty() {
+      self.push(bytecode::mov(WildDestination::Register(dest), array_constant));
+      return Ok(());
+    }
+    // build in a temporary so elements can still read the old value of `dest`
+    let array = Right(self.temps.take_temp());
+    self.push(bytecode::mov(WildDestination::Register(array.clone()), array_constant));
+    for (index, element) in dynamic_elements {
+      let value = self.compile_into_wildcard(element)?;
+      let index = self.immediate_or_constant_for(&IntegerLiteral(index as i64))?;
+      let index_on = bytecode::opcode::index::On::Destination;
+      self.push(bytecode::index(index_on, array.clone(), value, index));
+    }
+    self.push(bytecode::mov(WildDestination::Register(dest), WildSource::Register(array)));
+    Ok(())
+  }
+
+  /// Compiles `and`/`or` so the result is one of the operand values, not a boolean
+  ///
+  /// The right operand is only evaluated when the left operand doesn't decide the result.
+  /// `dest` is not written until the deciding operand is known, so `x = y or x` still reads
+  /// the old value of `x`.
+  pub fn compile_logical_expression(&mut self, dest: RawOrTemp, left: &Expression, op: &BinaryOperator, right: &Expression) -> Result<(), CompileError> {
+    let left_register = self.compile_into_register(left)?;
+    let condition = WildDestination::Register(left_register.clone());
+    let jump_to_right_offset = self.bytecode.len();
+    match op {
+      BinaryOperator::And => self.push(bytecode::jmp_if_true(condition, InstructionPointer::empty_place_holder())),
+      BinaryOperator::Or => self.push(bytecode::jmp_if_false(condition, InstructionPointer::empty_place_holder())),
+      _ => unreachable!(),
+    }
+    // left operand decides the result
+    self.push(bytecode::mov(WildDestination::Register(dest.clone()), WildSource::Register(left_register)));
+    let jump_to_end_offset = self.bytecode.len();
+    self.push(bytecode::jmp(InstructionPointer::empty_place_holder()));
+    // right operand decides the result
+    let right_ip = self.next_instruction_pointer();
+    self.compile_expression(dest, right)?;
+    let end_ip = self.next_instruction_pointer();
+    self.bytecode[jump_to_right_offset] |= right_ip.as_both_operands();
+    self.bytecode[jump_to_end_offset] |= end_ip.as_both_operands();
+    Ok(())
+  }
+
+  /// Compiles `not right` into a boolean by jumping over one of two boolean moves
+  pub fn compile_not_expression(&mut self, dest: RawOrTemp, right: &Expression) -> Result<(), CompileError> {
+    let condition = self.compile_condition(right)?;
+    let jump_to_false_offset = self.bytecode.len();
+    self.push(bytecode::jmp_if_true(condition, InstructionPointer::empty_place_holder()));
+    self.push(bytecode::mov_bool(WildDestination::Register(dest.clone()), true));
+    let jump_to_end_offset = self.bytecode.len();
+    self.push(bytecode::jmp(InstructionPointer::empty_place_holder()));
+    let false_ip = self.next_instruction_pointer();
+    self.push(bytecode::mov_bool(WildDestination::Register(dest), false));
+    let end_ip = self.next_instruction_pointer();
+    self.bytecode[jump_to_false_offset] |= false_ip.as_both_operands();
+    self.bytecode[jump_to_end_offset] |= end_ip.as_both_operands();
+    Ok(())
+  }
+
+  /// Compiles `condition ? then : else_` so that only the taken branch is evaluated, with
+  /// both branches writing to `dest`
+  pub fn compile_conditional_expression(&mut self, dest: RawOrTemp, condition: &Expression, then: &Expression, else_: &Expression) -> Result<(), CompileError> {
+    let condition = self.compile_condition(condition)?;
+    let jump_to_else_offset = self.bytecode.len();
+    self.push(bytecode::jmp_if_false(condition, InstructionPointer::empty_place_holder()));
+    self.compile_expression(dest.clone(), then)?;
+    let jump_to_end_offset = self.bytecode.len();
+    self.push(bytecode::jmp(InstructionPointer::empty_place_holder()));
+    let else_ip = self.next_instruction_pointer();
+    self.compile_expression(dest, else_)?;
+    let end_ip = self.next_instruction_pointer();
+    self.bytecode[jump_to_else_offset] |= else_ip.as_both_operands();
+    self.bytecode[jump_to_end_offset] |= end_ip.as_both_operands();
+    Ok(())
   }
 }
 
@@ -337,60 +806,125 @@ impl FunctionCompiler {
     match expression {
       Identifier(_) => false,
       Integer(_) => true,
+      #[cfg(feature = "bigint")]
+      BigInteger(_) => true,
       Float(_) => true,
       String(_) => true,
-      Boolean(_) => true,
+      Bytes(_) => true,
+      // booleans are only immediates in move instructions, so they go in a register
+      Boolean(_) => false,
+      Nil => true,
       other => false,
     }
   }
-  pub fn compile_into_register(&mut self, expression: &Expression) -> RawOrTemp {
+  /// Compiles both sources of a binary arithmetic or comparison instruction
+  ///
+  /// The encoding only has room for one wildcard source, so when both sides are literals
+  /// (e.g. `5 < 3`) the left one stays a wildcard and the right one is spilled into exactly
+  /// one temporary.
+  pub fn compile_binary_sources(&mut self, left: &Expression, right: &Expression) -> Result<BinarySources, CompileError> {
+    if self.needs_wildcard(left) {
+      let first = self.compile_into_wildcard(left)?;
+      let second = self.compile_into_register(right)?;
+      Ok(BinarySources::FirstIsWild(first, second))
+    } else {
+      let first = self.compile_into_register(left)?;
+      let second = self.compile_into_wildcard(right)?;
+      Ok(BinarySources::SecondIsWild(first, second))
+    }
+  }
+  pub fn compile_into_register(&mut self, expression: &Expression) -> Result<RawOrTemp, CompileError> {
     use Expression::*;
-    match expression {
-      Identifier(ident) => Left(self.register_for(ident)),
+    let compiled = match expression {
+      Identifier(ident) if self.function_value_for(ident).is_none() => match self.global_for(ident) {
+        Some(global) => {
+          let temp = Right(self.temps.take_temp());
+          self.push(bytecode::mov(temp.clone().into(), WildSource::<RawRegister>::Global(global)));
+          temp
+        }
+        None => Left(self.register_for(ident)),
+      },
       Integer(int) => {
         let temp = Right(self.temps.take_temp());
-        let source = self.immediate_or_constant_for(int);
+        let source = self.immediate_or_constant_for(int)?;
         self.push(bytecode::mov(temp.clone().into(), source));
         temp
       }
       Float(flt) => {
         let temp = Right(self.temps.take_temp());
-        let source: WildSource<RawRegister> = self.constant_for_float(flt).into();
+        let source: WildSource<RawRegister> = self.constant_for_float(flt)?.into();
         self.push(bytecode::mov(temp.clone().into(), source));
         temp
       }
       String(s) => {
         let temp = Right(self.temps.take_temp());
-        let source: WildSource<RawRegister> = self.constant_for_string(s).into();
+        let source: WildSource<RawRegister> = self.constant_for_string(s)?.into();
         self.push(bytecode::mov(temp.clone().into(), source));
         temp
       }
       Boolean(b) => {
         let temp = Right(self.temps.take_temp());
-        let source: WildSource<RawRegister> = self.constant_for_boolean(b).into();
+        self.push(bytecode::mov_bool(temp.clone().into(), b.0));
+        temp
+      }
+      Nil => {
+        let temp = Right(self.temps.take_temp());
+        let source: WildSource<RawRegister> = self.constant_for_nil()?.into();
         self.push(bytecode::mov(temp.clone().into(), source));
         temp
       }
       other => {
         let temp = Right(self.temps.take_temp());
-        self.compile_expression(temp.clone(), expression);
+        self.compile_expression(temp.clone(), expression)?;
         temp
       }
-    }
+    };
+    Ok(compiled)
   }
-  pub fn compile_into_wildcard(&mut self, expression: &Expression) -> WildSource<RawOrTemp> {
+  pub fn compile_into_wildcard(&mut self, expression: &Expression) -> Result<WildSource<RawOrTemp>, CompileError> {
     use Expression::*;
-    match expression {
-      Identifier(ident) => WildSource::Register(Left(self.register_for(ident))),
-      Integer(int) => self.immediate_or_constant_for(int),
-      Float(flt) => self.constant_for_float(flt).into(),
-      String(s) => self.constant_for_string(s).into(),
-      Boolean(b) => self.constant_for_boolean(b).into(),
+    let compiled = match expression {
+      Identifier(ident) => self.source_for(ident)?,
+      Integer(int) => self.immediate_or_constant_for(int)?,
+      #[cfg(feature = "bigint")]
+      BigInteger(int) => self.constant_for(ConstantValue::BigInt(int.0.clone()))?.into(),
+      Float(flt) => self.constant_for_float(flt)?.into(),
+      String(s) => self.constant_for_string(s)?.into(),
+      Bytes(bytes) => self.constant_for(ConstantValue::Bytes(bytes.0.clone()))?.into(),
+      Nil => self.constant_for_nil()?.into(),
+      Array(_) if literal_constant(expression).is_some() =>
+        self.constant_for(literal_constant(expression).unwrap())?.into(),
       other => {
         let temp = Right(self.temps.take_temp());
-        self.compile_expression(temp.clone(), expression);
+        self.compile_expression(temp.clone(), expression)?;
         WildSource::Register(temp)
       }
-    }
+    };
+    Ok(compiled)
+  }
+}
+
+/// Sources of a binary instruction, at most one of which is a wildcard
+pub enum BinarySources {
+  FirstIsWild(WildSource<RawOrTemp>, RawOrTemp),
+  SecondIsWild(RawOrTemp, WildSource<RawOrTemp>),
+}
+
+/// Constant value of a literal, including arrays made only of literals
+fn literal_constant(expression: &Expression) -> Option<ConstantValue> {
+  match expression {
+    Expression::Nil => Some(ConstantValue::Nil),
+    Expression::Integer(int) => Some(ConstantValue::Integer(int.0)),
+    #[cfg(feature = "bigint")]
+    Expression::BigInteger(int) => Some(ConstantValue::BigInt(int.0.clone())),
+    Expression::Float(flt) => Some(ConstantValue::Float(flt.0)),
+    Expression::Boolean(b) => Some(ConstantValue::Boolean(b.0)),
+    Expression::String(s) => Some(ConstantValue::String(s.0.clone())),
+    Expression::Bytes(bytes) => Some(ConstantValue::Bytes(bytes.0.clone())),
+    Expression::Array(elements) => elements.iter()
+      .map(literal_constant)
+      .collect::<Option<Vec<_>>>()
+      .map(ConstantValue::Array),
+    _ => None,
   }
 }
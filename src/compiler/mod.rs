@@ -1,41 +1,442 @@
 
 pub mod function;
 pub mod temporary;
+pub mod statics;
+pub mod simplify;
+pub mod tail_recursion;
+pub mod common_subexpressions;
+mod error;
 
-use std::fmt::Write;
-use std::borrow::Borrow;
-use crate::ast::{FunctionDecl, Declaration, Statement};
-use crate::bytecode::Procedure;
+use core::fmt::Write;
+use core::borrow::Borrow;
+use core::hash::{Hash, Hasher};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::{rc::Rc, string::String, vec::Vec};
+use crate::ast::{FunctionDecl, Declaration, Statement, Expression, Identifier};
+use crate::bytecode::{Procedure, Code, Fnv};
 use function::FunctionCompiler;
 
-pub fn compile_function(function: &FunctionDecl) -> Procedure {
-  let mut fc = FunctionCompiler::with_parameters(&function.params);
-  for statement in function.body.iter() {
-    fc.compile_statement(statement);
+pub use error::CompileError;
+pub use statics::compile_statics;
+
+/// Settings that change how functions are compiled
+///
+/// The default options compile the source as written.
+#[derive(Debug, Clone, Default)]
+pub struct CompileOptions {
+  /// Run the [`simplify`](mod@simplify) pass on each function body before compiling it
+  pub simplify: bool,
+  /// Leave out the leading `nop` of each procedure (see [`Code::leading_nop`])
+  pub nop_free: bool,
+  /// Compile functions that return calls to themselves as loops, see
+  /// [`tail_recursion`](mod@tail_recursion)
+  pub tail_recursion: bool,
+  /// Compute arithmetic repeated within a statement only once, see
+  /// [`common_subexpressions`](mod@common_subexpressions)
+  pub common_subexpressions: bool,
+  /// Treat parameters as read-only, assigning to one is a
+  /// [`CompileError::AssignToParameter`]
+  pub strict: bool,
+}
+
+/// Compiles a single function declaration into a [`Procedure`]
+///
+/// When the last statement of the body is an expression, its value is returned.
+///
+/// Language constructs the compiler doesn't support yet are returned as
+/// [`CompileError::Unsupported`] instead of panicking.
+pub fn compile_function(function: &FunctionDecl) -> Result<Procedure, CompileError> {
+  compile_function_with_globals(function, &[])
+}
+
+/// Compiles a function that can refer to the statics named in `globals`
+///
+/// The position of each name in `globals` is its index in the globals table.
+pub fn compile_function_with_globals(function: &FunctionDecl, globals: &[Identifier]) -> Result<Procedure, CompileError> {
+  compile_function_with_options(function, globals, &CompileOptions::default())
+}
+
+/// Compiles a function that can refer to the statics named in `globals` using `options`
+///
+/// # Example:
+/// ```rust
+/// # use lualite::{parser, compiler::{self, CompileOptions}};
+/// let (_, fn_decl) = parser::declaration::function_decl("function f() return 2 * 3 end").unwrap();
+/// let options = CompileOptions { simplify: true, ..CompileOptions::default() };
+/// let procedure = compiler::compile_function_with_options(&fn_decl, &[], &options).unwrap();
+/// assert_eq!(procedure.bytecode[1].to_string(), "mov   R0 = #6");
+/// ```
+///
+/// A function with nested functions compiles to more than one procedure, use
+/// [`compile_function_and_nested`] for those.
+pub fn compile_function_with_options(
+  function: &FunctionDecl,
+  globals: &[Identifier],
+  options: &CompileOptions,
+) -> Result<Procedure, CompileError> {
+  let mut procedures = compile_function_and_nested(function, globals, options)?;
+  if procedures.len() > 1 {
+    return Err(CompileError::Unsupported { what: "nested functions outside of compile_function_and_nested", line: None });
+  }
+  Ok(procedures.remove(0).1)
+}
+
+/// Compiles a function and every function nested inside it
+///
+/// The function itself comes first, followed by its nested functions named
+/// `enclosing.nested`.  Calls to a nested function by its local name are compiled as calls
+/// to that name.
+///
+/// Nested functions can't refer to the locals of the function they're defined in yet,
+/// reading one before the nested function assigns it is a
+/// [`CompileError::CapturedVariable`].  Assigning it first makes it the nested function's
+/// own local.
+///
+/// # Example:
+/// ```rust
+/// # use lualite::{parser, compiler::{self, CompileOptions}};
+/// let source_code = r"
+/// function outer(x)
+///   function double(y)
+///     return y * 2
+///   end
+///   return double(x)
+/// end
+/// ";
+/// let (_, fn_decl) = parser::declaration::function_decl(source_code).unwrap();
+/// let procedures = compiler::compile_function_and_nested(&fn_decl, &[], &CompileOptions::default()).unwrap();
+/// let names: Vec<&str> = procedures.iter().map(|(name, _)| name.as_str()).collect();
+/// assert_eq!(names, ["outer", "outer.double"]);
+/// assert_eq!(procedures[0].1.function_table(), ["outer.double"]);
+/// ```
+pub fn compile_function_and_nested(
+  function: &FunctionDecl,
+  globals: &[Identifier],
+  options: &CompileOptions,
+) -> Result<Vec<(String, Procedure)>, CompileError> {
+  let mut procedures = Vec::new();
+  compile_nested(function, &function.name, &BTreeMap::new(), globals, &BTreeMap::new(), options, &mut procedures)?;
+  Ok(procedures)
+}
+
+/// Compiles `function` under `name` into `procedures`, followed by its nested functions
+///
+/// The top-level functions in `functions` can be used as values and called with named
+/// arguments, each is mapped to the names of its parameters.
+fn compile_nested(
+  function: &FunctionDecl,
+  name: &Identifier,
+  local_functions: &BTreeMap<Identifier, Identifier>,
+  globals: &[Identifier],
+  functions: &BTreeMap<Identifier, Vec<Identifier>>,
+  options: &CompileOptions,
+  procedures: &mut Vec<(String, Procedure)>,
+) -> Result<(), CompileError> {
+  // the `call` builtin always calls its first argument
+  if function.name.0 == "call" {
+    return Err(CompileError::ReservedFunctionName(function.name.0.clone()));
+  }
+  let simplified_body;
+  let body = if options.simplify {
+    simplified_body = simplify::simplify_body(&function.body);
+    &simplified_body
+  } else {
+    &function.body
+  };
+  let looped_body = options.tail_recursion
+    .then(|| tail_recursion::loop_tail_recursion(function, body, globals))
+    .flatten();
+  let body = looped_body.as_deref().unwrap_or(body);
+  let eliminated_body = options.common_subexpressions
+    .then(|| common_subexpressions::eliminate_common_subexpressions(body));
+  let body = eliminated_body.as_deref().unwrap_or(body);
+  let mut fc = if options.nop_free {
+    FunctionCompiler::nop_free_with_parameters(&function.params)
+  } else {
+    FunctionCompiler::with_parameters(&function.params)
+  };
+  if options.strict && looped_body.is_some() {
+    // the loop assigns the parameters itself, so the source is checked instead
+    let mut assigned = BTreeSet::new();
+    assigned_locals(&function.body, &mut assigned);
+    if let Some(param) = function.params.iter().find(|param| assigned.contains(*param)) {
+      return Err(CompileError::AssignToParameter(param.clone()));
+    }
+  } else {
+    fc.set_strict(options.strict);
+  }
+  fc.set_globals(globals);
+  fc.set_name(name);
+  fc.set_local_functions(local_functions.clone());
+  let mut locals = BTreeSet::new();
+  assigned_locals(body, &mut locals);
+  let visible_functions: BTreeMap<Identifier, Vec<Identifier>> = functions.iter()
+    .filter(|(value, _)| !function.params.contains(value) && !locals.contains(value))
+    .map(|(value, params)| (value.clone(), params.clone()))
+    .collect();
+  fc.set_function_values(visible_functions.keys().cloned().collect());
+  fc.set_parameter_names(visible_functions);
+  fc.compile_parameter_defaults(&function.params, &function.defaults)?;
+  let (last, leading) = match body.split_last() {
+    Some((last, leading)) => (Some(last), leading),
+    None => (None, body),
+  };
+  for statement in leading.iter() {
+    fc.compile_statement(statement)?;
+  }
+  // a trailing expression is returned, otherwise add implicit return if it doesn't already exist
+  match last {
+    Some(Statement::SingleStatement(expression)) =>
+      fc.compile_statement(&Statement::ReturnStatement(Some(expression.clone())))?,
+    Some(statement @ Statement::ReturnStatement(_)) => fc.compile_statement(statement)?,
+    Some(statement) => {
+      fc.compile_statement(statement)?;
+      fc.compile_statement(&Statement::ReturnStatement(None))?;
+    }
+    None => fc.compile_statement(&Statement::ReturnStatement(None))?,
+  }
+  let nested_functions = fc.take_nested_functions();
+  for (_, nested) in nested_functions.iter() {
+    let mut bound: BTreeSet<Identifier> = nested.params.iter().cloned().collect();
+    let mut variables = BTreeSet::new();
+    nested.defaults.iter().flatten().for_each(|default| variables_in_expression(default, &mut variables));
+    variables.retain(|variable| !bound.contains(variable));
+    free_variables(&nested.body, &mut bound, &mut variables);
+    let captured = variables.into_iter().find(|variable| fc.is_local(variable));
+    if let Some(captured) = captured {
+      return Err(CompileError::CapturedVariable(captured.0));
+    }
+  }
+  let local_functions = fc.local_functions().clone();
+  // nested functions can also call the local functions they see with named arguments
+  let mut nested_scope = functions.clone();
+  nested_scope.extend(local_functions.keys()
+    .filter_map(|local| Some((local.clone(), fc.parameter_names_of(local)?.to_vec()))));
+  procedures.push((name.0.clone(), fc.finish()));
+  for (nested_name, nested) in nested_functions.iter() {
+    compile_nested(nested, nested_name, &local_functions, globals, &nested_scope, options, procedures)?;
+  }
+  Ok(())
+}
+
+/// Collects every identifier assigned in `body`, not counting nested functions
+fn assigned_locals(body: &[Statement], locals: &mut BTreeSet<Identifier>) {
+  for statement in body.iter() {
+    match statement {
+      Statement::AssignStatement(identifier, _) => {
+        locals.insert(identifier.clone());
+      }
+      Statement::ForStatement { variable, body, .. } => {
+        locals.insert(variable.clone());
+        assigned_locals(body, locals);
+      }
+      Statement::WhileStatement { body, .. } => assigned_locals(body, locals),
+      Statement::IfStatement { body, else_body, .. } => {
+        assigned_locals(body, locals);
+        else_body.iter().for_each(|else_body| assigned_locals(else_body, locals));
+      }
+      _ => (),
+    }
+  }
+}
+
+/// Collects the variables `body` reads before assigning them into `free`, not counting
+/// nested functions
+///
+/// `bound` starts with the names already assigned (like parameters) and ends with the
+/// names every path through `body` assigns.  A name only assigned in one branch of an `if`,
+/// or in the body of a loop, is still free when it's read afterwards.
+fn free_variables(body: &[Statement], bound: &mut BTreeSet<Identifier>, free: &mut BTreeSet<Identifier>) {
+  let read = |expression: &Expression, bound: &BTreeSet<Identifier>, free: &mut BTreeSet<Identifier>| {
+    let mut variables = BTreeSet::new();
+    variables_in_expression(expression, &mut variables);
+    free.extend(variables.into_iter().filter(|variable| !bound.contains(variable)));
+  };
+  for statement in body.iter() {
+    match statement {
+      Statement::SingleStatement(expression) => read(expression, bound, free),
+      Statement::ExpressionList(expressions) =>
+        expressions.iter().for_each(|expression| read(expression, bound, free)),
+      Statement::AssignStatement(identifier, expression) | Statement::LocalStatement(identifier, expression) => {
+        read(expression, bound, free);
+        bound.insert(identifier.clone());
+      }
+      Statement::IndexAssignStatement { table, index, value } => {
+        read(table, bound, free);
+        read(index, bound, free);
+        read(value, bound, free);
+      }
+      Statement::ReturnStatement(expression) =>
+        expression.iter().for_each(|expression| read(expression, bound, free)),
+      Statement::WhileStatement { condition, body } => {
+        read(condition, bound, free);
+        free_variables(body, &mut bound.clone(), free);
+      }
+      Statement::ForStatement { variable, start, end, body } => {
+        read(start, bound, free);
+        read(end, bound, free);
+        let mut body_bound = bound.clone();
+        body_bound.insert(variable.clone());
+        free_variables(body, &mut body_bound, free);
+      }
+      Statement::IfStatement { condition, body, else_body } => {
+        read(condition, bound, free);
+        let mut then_bound = bound.clone();
+        free_variables(body, &mut then_bound, free);
+        if let Some(else_body) = else_body {
+          let mut else_bound = bound.clone();
+          free_variables(else_body, &mut else_bound, free);
+          bound.extend(then_bound.intersection(&else_bound).cloned());
+        }
+      }
+      Statement::FunctionStatement(_) | Statement::BreakStatement | Statement::ContinueStatement => (),
+    }
   }
-  // add implicit return if it doesn't already exist
-  match function.body.last() {
-    Some(Statement::ReturnStatement(_)) => (),
-    _ => fc.compile_statement(&Statement::ReturnStatement(None)),
+}
+
+/// Collects the variables read in `expression`, not counting the names of called functions
+fn variables_in_expression(expression: &Expression, variables: &mut BTreeSet<Identifier>) {
+  match expression {
+    Expression::Identifier(identifier) => {
+      variables.insert(identifier.clone());
+    }
+    Expression::Array(elements) =>
+      elements.iter().for_each(|element| variables_in_expression(element, variables)),
+    Expression::Unary { right, .. } => variables_in_expression(right, variables),
+    Expression::Binary { left, right, .. } => {
+      variables_in_expression(left, variables);
+      variables_in_expression(right, variables);
+    }
+    Expression::FunctionCall { left, args } => {
+      if !matches!(**left, Expression::Identifier(_)) {
+        variables_in_expression(left, variables);
+      }
+      args.iter().for_each(|arg| variables_in_expression(arg, variables));
+    }
+    Expression::NamedCall { left, args } => {
+      if !matches!(**left, Expression::Identifier(_)) {
+        variables_in_expression(left, variables);
+      }
+      args.iter().for_each(|(_, arg)| variables_in_expression(arg, variables));
+    }
+    Expression::Index { left, index } => {
+      variables_in_expression(left, variables);
+      variables_in_expression(index, variables);
+    }
+    Expression::Slice { left, start, end } => {
+      variables_in_expression(left, variables);
+      start.iter().chain(end.iter()).for_each(|bound| variables_in_expression(bound, variables));
+    }
+    Expression::Conditional { condition, then, else_ } => {
+      variables_in_expression(condition, variables);
+      variables_in_expression(then, variables);
+      variables_in_expression(else_, variables);
+    }
+    _ => (),
   }
-  fc.finish()
 }
 
-pub fn compile_declarations<I, D>(declarations: I) -> Vec<(String, Procedure)>
+/// Compiles every function declaration in `declarations`
+///
+/// Functions can read and assign the statics from the same declarations, and use the
+/// other functions as values.  Use
+/// [`compile_statics`] to get the initial values of the globals table.
+///
+/// Functions that compile to identical procedures share a single [`Code`], while keeping
+/// their own names.
+///
+/// # Example:
+/// ```rust
+/// # use std::rc::Rc;
+/// # use lualite::{parser, compiler};
+/// let (_, declarations) = parser::parse_file(r"
+/// function double(x) return x * 2 end
+/// function twice(x) return x * 2 end
+/// function triple(x) return x * 3 end
+/// ").unwrap();
+/// let functions = compiler::compile_declarations(declarations.iter()).unwrap();
+/// assert!(Rc::ptr_eq(functions[0].1.code(), functions[1].1.code()));
+/// assert_eq!(functions[1].1.name, "twice");
+/// assert!(!Rc::ptr_eq(functions[0].1.code(), functions[2].1.code()));
+/// ```
+pub fn compile_declarations<I, D>(declarations: I) -> Result<Vec<(String, Rc<Procedure>)>, CompileError>
 where
   I: IntoIterator<Item=D>,
   D: Borrow<Declaration>,
 {
-  let mut functions = Vec::new();
-  for declaration in declarations.into_iter() {
+  compile_declarations_with_options(declarations, &CompileOptions::default())
+}
+
+/// Compiles every function declaration in `declarations` using `options`
+pub fn compile_declarations_with_options<I, D>(declarations: I, options: &CompileOptions) -> Result<Vec<(String, Rc<Procedure>)>, CompileError>
+where
+  I: IntoIterator<Item=D>,
+  D: Borrow<Declaration>,
+{
+  let declarations: Vec<D> = declarations.into_iter().collect();
+  let globals: Vec<Identifier> = declarations.iter()
+    .filter_map(|declaration| match declaration.borrow() {
+      Declaration::Static(static_decl) => Some(static_decl.name.clone()),
+      _ => None,
+    })
+    .collect();
+  let functions: BTreeMap<Identifier, Vec<Identifier>> = declarations.iter()
+    .filter_map(|declaration| match declaration.borrow() {
+      Declaration::Function(fn_decl) => Some((fn_decl.name.clone(), fn_decl.params.clone())),
+      _ => None,
+    })
+    .collect();
+  let mut procedures = Vec::new();
+  for declaration in declarations.iter() {
     match declaration.borrow() {
-      Declaration::Function(fn_decl) => {
-        let name = fn_decl.name.0.clone();
-        let procedure = compile_function(&fn_decl);
-        functions.push((name, procedure));
-      }
+      Declaration::Function(fn_decl) =>
+        compile_nested(fn_decl, &fn_decl.name, &BTreeMap::new(), &globals, &functions, options, &mut procedures)?,
       _ => (),
     }
   }
-  functions
+  Ok(share_identical(procedures))
+}
+
+/// Puts each procedure in an [`Rc`], with identical procedures sharing one [`Code`]
+///
+/// Each procedure keeps its own name.  Code is bucketed by its hash, so only procedures
+/// in the same bucket are compared.
+fn share_identical(functions: Vec<(String, Procedure)>) -> Vec<(String, Rc<Procedure>)> {
+  let mut buckets: BTreeMap<u64, Vec<Rc<Code>>> = BTreeMap::new();
+  functions.into_iter()
+    .map(|(name, procedure)| {
+      let mut hasher = Fnv::default();
+      procedure.code().hash(&mut hasher);
+      let bucket = buckets.entry(hasher.finish()).or_default();
+      let procedure = match bucket.iter().find(|code| ***code == **procedure.code()) {
+        Some(code) => Procedure::new(procedure.name, Rc::clone(code)),
+        None => {
+          bucket.push(Rc::clone(procedure.code()));
+          procedure
+        }
+      };
+      (name, Rc::new(procedure))
+    })
+    .collect()
+}
+
+/// Checks that every function called by `functions` can be resolved by name
+///
+/// A called function resolves if it is one of the compiled `functions` or one of the
+/// `external` names (functions registered with a virtual machine some other way).
+/// The first unresolved name is returned as [`CompileError::UnresolvedFunction`].
+pub fn link<'a, E>(functions: &[(String, Rc<Procedure>)], external: E) -> Result<(), CompileError>
+where
+  E: IntoIterator<Item=&'a str>,
+{
+  let external: Vec<&str> = external.into_iter().collect();
+  for (_, procedure) in functions.iter() {
+    for called in procedure.functions.iter() {
+      let is_compiled = functions.iter().any(|(name, _)| name == called);
+      if !is_compiled && !external.contains(&called.as_str()) {
+        return Err(CompileError::UnresolvedFunction(called.clone()));
+      }
+    }
+  }
+  Ok(())
 }
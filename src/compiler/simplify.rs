@@ -0,0 +1,120 @@
+//! Algebraic simplification of the AST before compiling
+//!
+//! The pass is opt-in with [`CompileOptions::simplify`](super::CompileOptions::simplify).
+//! It folds `+`, `-`, and `*` on two integer literals into one literal, so
+//! `60 * 60 * 24` compiles to `86400`.
+//!
+//! Only rewrites that give the same value for every operand are made.  Identities like
+//! `e * 1` or `x ^ 2` as `x * x` are left alone, since they'd change what the expression
+//! gives for some types: a float times an integer is `nil`, a string times an integer
+//! repeats it, and `^` is a float unless the `bigint` feature makes it exact.  Integer
+//! results that would overflow aren't folded either, so they wrap or promote at runtime
+//! the same as without the pass.
+
+use alloc::{boxed::Box, vec::Vec};
+use crate::ast::{Statement, Expression, BinaryOperator, IntegerLiteral};
+
+/// Simplifies an expression and all of its children
+///
+/// # Example:
+/// ```rust
+/// # use lualite::parser::expression::expression;
+/// # use lualite::compiler::simplify::simplify;
+/// let (_, original) = expression("y * (60 * 60 * 24)").unwrap();
+/// let (_, expected) = expression("y * 86400").unwrap();
+/// assert_eq!(simplify(&original), expected);
+/// ```
+pub fn simplify(expression: &Expression) -> Expression {
+  match expression {
+    Expression::Binary { left, op, right } => {
+      simplify_binary(simplify(left), op.clone(), simplify(right))
+    }
+    Expression::Unary { op, right } => Expression::Unary {
+      op: op.clone(),
+      right: Box::new(simplify(right)),
+    },
+    Expression::FunctionCall { left, args } => Expression::FunctionCall {
+      left: Box::new(simplify(left)),
+      args: args.iter().map(simplify).collect(),
+    },
+    Expression::NamedCall { left, args } => Expression::NamedCall {
+      left: Box::new(simplify(left)),
+      args: args.iter().map(|(name, arg)| (name.clone(), simplify(arg))).collect(),
+    },
+    Expression::Index { left, index } => Expression::Index {
+      left: Box::new(simplify(left)),
+      index: Box::new(simplify(index)),
+    },
+    Expression::Slice { left, start, end } => Expression::Slice {
+      left: Box::new(simplify(left)),
+      start: start.as_deref().map(simplify).map(Box::new),
+      end: end.as_deref().map(simplify).map(Box::new),
+    },
+    Expression::Conditional { condition, then, else_ } => Expression::Conditional {
+      condition: Box::new(simplify(condition)),
+      then: Box::new(simplify(then)),
+      else_: Box::new(simplify(else_)),
+    },
+    Expression::Array(elements) => Expression::Array(elements.iter().map(simplify).collect()),
+    other => other.clone(),
+  }
+}
+
+/// Simplifies every expression in a list of statements
+pub fn simplify_body(body: &[Statement]) -> Vec<Statement> {
+  body.iter().map(simplify_statement).collect()
+}
+
+fn simplify_statement(statement: &Statement) -> Statement {
+  match statement {
+    Statement::SingleStatement(expression) => Statement::SingleStatement(simplify(expression)),
+    Statement::ExpressionList(expressions) => Statement::ExpressionList(expressions.iter().map(simplify).collect()),
+    Statement::AssignStatement(identifier, expression) =>
+      Statement::AssignStatement(identifier.clone(), simplify(expression)),
+    Statement::LocalStatement(identifier, expression) =>
+      Statement::LocalStatement(identifier.clone(), simplify(expression)),
+    Statement::IndexAssignStatement { table, index, value } => Statement::IndexAssignStatement {
+      table: simplify(table),
+      index: simplify(index),
+      value: simplify(value),
+    },
+    Statement::ReturnStatement(expression) => Statement::ReturnStatement(expression.as_ref().map(simplify)),
+    Statement::WhileStatement { condition, body } => Statement::WhileStatement {
+      condition: simplify(condition),
+      body: simplify_body(body),
+    },
+    Statement::ForStatement { variable, start, end, body } => Statement::ForStatement {
+      variable: variable.clone(),
+      start: simplify(start),
+      end: simplify(end),
+      body: simplify_body(body),
+    },
+    // simplified when it's compiled on its own
+    Statement::FunctionStatement(function) => Statement::FunctionStatement(function.clone()),
+    Statement::IfStatement { condition, body, else_body } => Statement::IfStatement {
+      condition: simplify(condition),
+      body: simplify_body(body),
+      else_body: else_body.as_deref().map(simplify_body),
+    },
+    Statement::BreakStatement => Statement::BreakStatement,
+    Statement::ContinueStatement => Statement::ContinueStatement,
+  }
+}
+
+fn simplify_binary(left: Expression, op: BinaryOperator, right: Expression) -> Expression {
+  use Expression::Integer;
+  let folded = match (&left, &op, &right) {
+    (Integer(IntegerLiteral(left)), BinaryOperator::Add, Integer(IntegerLiteral(right))) => left.checked_add(*right),
+    (Integer(IntegerLiteral(left)), BinaryOperator::Sub, Integer(IntegerLiteral(right))) => left.checked_sub(*right),
+    (Integer(IntegerLiteral(left)), BinaryOperator::Mul, Integer(IntegerLiteral(right))) => left.checked_mul(*right),
+    _ => None,
+  };
+  match folded {
+    Some(integer) => Integer(IntegerLiteral(integer)),
+    None => Expression::Binary {
+      left: Box::new(left),
+      op,
+      right: Box::new(right),
+    },
+  }
+}
@@ -0,0 +1,262 @@
+//! Compile-time evaluation of `static` declarations
+//!
+//! Static initializers are folded into [`ConstantValue`]s by the compiler.  Each static
+//! becomes a slot in a virtual machine's globals table, in declaration order.
+//!
+//! Initializers can use literals, previously declared statics, and any operator.  They
+//! fold to the same value the virtual machine would compute, so `1 + 1.5` is `nil` and
+//! `2 ^ 3` is `8.0`:
+//! ```text
+//! static WIDTH = 10
+//! static AREA = WIDTH * 20
+//! static SQUARE = WIDTH == 10 and AREA == 200
+//! ```
+//! Anything that needs a value only known at runtime (like a function call) is a
+//! [`CompileError::NonConstStatic`].
+
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use alloc::{string::String, vec::Vec};
+use crate::ast::{Declaration, Expression, BinaryOperator, UnaryOperator};
+use crate::bytecode::constant_value::ConstantValue;
+use super::CompileError;
+
+/// Evaluates the initializers of every static declaration in `declarations`
+///
+/// The result is in declaration order, which is also the order of the globals table
+/// that compiled functions expect.  Statics without an initializer are `nil`, and
+/// declaring the same name twice is a [`CompileError::DuplicateStatic`].
+pub fn compile_statics<I, D>(declarations: I) -> Result<Vec<(String, ConstantValue)>, CompileError>
+where
+  I: IntoIterator<Item=D>,
+  D: Borrow<Declaration>,
+{
+  let mut statics: Vec<(String, ConstantValue)> = Vec::new();
+  for declaration in declarations.into_iter() {
+    if let Declaration::Static(static_decl) = declaration.borrow() {
+      let name = static_decl.name.0.clone();
+      if statics.iter().any(|(declared, _)| *declared == name) {
+        return Err(CompileError::DuplicateStatic(name));
+      }
+      let value = match &static_decl.value {
+        Some(initializer) => match fold(initializer, &statics) {
+          Some(value) => value,
+          None => return Err(CompileError::NonConstStatic(name)),
+        },
+        None => ConstantValue::Nil,
+      };
+      if statics.len() > u8::MAX as usize {
+        return Err(CompileError::Unsupported { what: "more than 256 statics", line: None });
+      }
+      statics.push((name, value));
+    }
+  }
+  Ok(statics)
+}
+
+fn fold(expression: &Expression, statics: &[(String, ConstantValue)]) -> Option<ConstantValue> {
+  match expression {
+    Expression::Nil => Some(ConstantValue::Nil),
+    Expression::Integer(integer) => Some(ConstantValue::Integer(integer.0)),
+    #[cfg(feature = "bigint")]
+    Expression::BigInteger(integer) => Some(ConstantValue::BigInt(integer.0.clone())),
+    Expression::Float(float) => Some(ConstantValue::Float(float.0)),
+    Expression::Boolean(boolean) => Some(ConstantValue::Boolean(boolean.0)),
+    Expression::String(string) => Some(ConstantValue::String(string.0.clone())),
+    Expression::Bytes(bytes) => Some(ConstantValue::Bytes(bytes.0.clone())),
+    Expression::Array(elements) => elements.iter()
+      .map(|element| fold(element, statics))
+      .collect::<Option<Vec<_>>>()
+      .map(ConstantValue::Array),
+    Expression::Identifier(ident) => statics.iter()
+      .find(|(name, _)| *name == ident.0)
+      .map(|(_, value)| value.clone()),
+    Expression::Unary { op: UnaryOperator::Neg, right } => match fold(right, statics)? {
+      #[cfg(feature = "bigint")]
+      integer @ (ConstantValue::Integer(_) | ConstantValue::BigInt(_)) => big::neg(integer),
+      ConstantValue::Integer(integer) => integer.checked_neg().map(ConstantValue::Integer),
+      ConstantValue::Float(float) => Some(ConstantValue::Float(-float)),
+      _ => None,
+    },
+    Expression::Unary { op: UnaryOperator::Not, right } => match fold(right, statics)? {
+      ConstantValue::Nil | ConstantValue::Boolean(false) => Some(ConstantValue::Boolean(true)),
+      _ => Some(ConstantValue::Boolean(false)),
+    },
+    Expression::Binary { left, op, right } => fold_binary(fold(left, statics)?, op, fold(right, statics)?),
+    _ => None,
+  }
+}
+
+/// Folds a binary operator with the same result the virtual machine would give, or `None`
+/// if it can't be folded in this build
+fn fold_binary(left: ConstantValue, op: &BinaryOperator, right: ConstantValue) -> Option<ConstantValue> {
+  use ConstantValue::{Integer, Float, Boolean, Nil};
+  match op {
+    BinaryOperator::Eq => return Some(Boolean(equal(&left, &right))),
+    BinaryOperator::Ne => return Some(Boolean(!equal(&left, &right))),
+    // values that don't compare are neither less, greater, nor equal
+    BinaryOperator::Lt => return Some(Boolean(compare(&left, &right) == Some(Ordering::Less))),
+    BinaryOperator::Gt => return Some(Boolean(compare(&left, &right) == Some(Ordering::Greater))),
+    BinaryOperator::Le => return Some(Boolean(matches!(compare(&left, &right), Some(Ordering::Less | Ordering::Equal)))),
+    BinaryOperator::Ge => return Some(Boolean(matches!(compare(&left, &right), Some(Ordering::Greater | Ordering::Equal)))),
+    BinaryOperator::And => return Some(if is_truthy(&left) { right } else { left }),
+    BinaryOperator::Or => return Some(if is_truthy(&left) { left } else { right }),
+    _ => (),
+  }
+  #[cfg(feature = "bigint")]
+  if let Some(folded) = big::fold_binary(&left, op, &right) {
+    return Some(folded);
+  }
+  let folded = match (left, op, right) {
+    (Integer(left), BinaryOperator::Add, Integer(right)) => Integer(left.wrapping_add(right)),
+    (Integer(left), BinaryOperator::Sub, Integer(right)) => Integer(left.wrapping_sub(right)),
+    (Integer(left), BinaryOperator::Mul, Integer(right)) => Integer(left.wrapping_mul(right)),
+    (Integer(left), BinaryOperator::FloorDiv, Integer(right)) => match left.checked_div(right) {
+      Some(quotient) if left % right != 0 && (left < 0) != (right < 0) => Integer(quotient - 1),
+      Some(quotient) => Integer(quotient),
+      None => Nil,
+    },
+    (Integer(_), BinaryOperator::Rem, Integer(0)) => Nil,
+    (Integer(left), BinaryOperator::Rem, Integer(right)) => match left.wrapping_rem(right) {
+      remainder if remainder != 0 && (remainder < 0) != (right < 0) => Integer(remainder + right),
+      remainder => Integer(remainder),
+    },
+    (Float(left), BinaryOperator::Add, Float(right)) => Float(left + right),
+    (Float(left), BinaryOperator::Sub, Float(right)) => Float(left - right),
+    (Float(left), BinaryOperator::Mul, Float(right)) => Float(left * right),
+    (ConstantValue::String(string), BinaryOperator::Mul, Integer(count)) |
+    (Integer(count), BinaryOperator::Mul, ConstantValue::String(string)) => repeat(&string, count),
+    (left, BinaryOperator::Div, right) => match (as_f64(&left), as_f64(&right)) {
+      (Some(left), Some(right)) => Float(left / right),
+      _ => Nil,
+    },
+    // rounding and powers need `std`'s float functions
+    #[cfg(feature = "std")]
+    (left, op @ (BinaryOperator::FloorDiv | BinaryOperator::Rem | BinaryOperator::Pow), right) =>
+      match (as_f64(&left), as_f64(&right)) {
+        (Some(left), Some(right)) => Float(match op {
+          BinaryOperator::FloorDiv => (left / right).floor(),
+          BinaryOperator::Rem => left - (left / right).floor() * right,
+          _ => left.powf(right),
+        }),
+        _ => Nil,
+      },
+    #[cfg(not(feature = "std"))]
+    (_, BinaryOperator::FloorDiv | BinaryOperator::Rem | BinaryOperator::Pow, _) => return None,
+    // mixed integers and floats, or operands the operator doesn't apply to
+    _ => Nil,
+  };
+  Some(folded)
+}
+
+/// Equality of the values the constants load as, so floats compare by value
+fn equal(left: &ConstantValue, right: &ConstantValue) -> bool {
+  match (left, right) {
+    (ConstantValue::Float(left), ConstantValue::Float(right)) => left == right,
+    (ConstantValue::Array(left), ConstantValue::Array(right)) =>
+      left.len() == right.len() && left.iter().zip(right).all(|(left, right)| equal(left, right)),
+    (left, right) => left == right,
+  }
+}
+
+fn compare(left: &ConstantValue, right: &ConstantValue) -> Option<Ordering> {
+  #[cfg(feature = "bigint")]
+  if let Some(ordering) = big::compare(left, right) {
+    return Some(ordering);
+  }
+  match (left, right) {
+    (ConstantValue::Integer(left), ConstantValue::Integer(right)) => Some(left.cmp(right)),
+    (ConstantValue::Float(left), ConstantValue::Float(right)) => left.partial_cmp(right),
+    (ConstantValue::String(left), ConstantValue::String(right)) => Some(left.cmp(right)),
+    _ => None,
+  }
+}
+
+fn is_truthy(constant: &ConstantValue) -> bool {
+  !matches!(constant, ConstantValue::Nil | ConstantValue::Boolean(false))
+}
+
+/// A string repeated `count` times, `nil` if `count` is negative or the result would be too
+/// large to allocate
+fn repeat(string: &str, count: i64) -> ConstantValue {
+  let mut repeated = String::new();
+  let reserved = usize::try_from(count).ok()
+    .and_then(|count| string.len().checked_mul(count))
+    .is_some_and(|len| repeated.try_reserve_exact(len).is_ok());
+  if reserved {
+    (0..count).for_each(|_| repeated.push_str(string));
+    ConstantValue::String(repeated)
+  } else {
+    ConstantValue::Nil
+  }
+}
+
+fn as_f64(constant: &ConstantValue) -> Option<f64> {
+  match constant {
+    ConstantValue::Integer(integer) => Some(*integer as f64),
+    #[cfg(feature = "bigint")]
+    ConstantValue::BigInt(integer) => Some(num_traits::ToPrimitive::to_f64(integer).unwrap_or(f64::NAN)),
+    ConstantValue::Float(float) => Some(*float),
+    _ => None,
+  }
+}
+
+/// Integer arithmetic that promotes to a big integer instead of overflowing, the same as
+/// at runtime
+#[cfg(feature = "bigint")]
+mod big {
+  use core::cmp::Ordering;
+  use num_bigint::BigInt;
+  use num_traits::{Signed, Zero};
+  use crate::ast::BinaryOperator;
+  use crate::bytecode::constant_value::ConstantValue;
+
+  fn to_big(constant: &ConstantValue) -> Option<BigInt> {
+    match constant {
+      ConstantValue::Integer(integer) => Some(BigInt::from(*integer)),
+      ConstantValue::BigInt(integer) => Some(integer.clone()),
+      _ => None,
+    }
+  }
+
+  /// Integers that fit in an `i64` are always [`ConstantValue::Integer`]
+  fn from_big(integer: BigInt) -> ConstantValue {
+    match i64::try_from(&integer) {
+      Ok(small) => ConstantValue::Integer(small),
+      Err(_) => ConstantValue::BigInt(integer),
+    }
+  }
+
+  pub(super) fn neg(integer: ConstantValue) -> Option<ConstantValue> {
+    Some(from_big(-to_big(&integer)?))
+  }
+
+  /// Ordering of two integers, or `None` if either isn't an integer
+  pub(super) fn compare(left: &ConstantValue, right: &ConstantValue) -> Option<Ordering> {
+    Some(to_big(left)?.cmp(&to_big(right)?))
+  }
+
+  /// Folded arithmetic on two integers, or `None` if either isn't an integer or the
+  /// operator isn't `+`, `-`, `*`, `//`, `%`, or `^` with an exponent that fits a `u32`
+  pub(super) fn fold_binary(left: &ConstantValue, op: &BinaryOperator, right: &ConstantValue) -> Option<ConstantValue> {
+    let (left, right) = (to_big(left)?, to_big(right)?);
+    let folded = match op {
+      BinaryOperator::Add => left + right,
+      BinaryOperator::Sub => left - right,
+      BinaryOperator::Mul => left * right,
+      BinaryOperator::FloorDiv | BinaryOperator::Rem if right.is_zero() => return Some(ConstantValue::Nil),
+      BinaryOperator::FloorDiv => {
+        let quotient = &left / &right;
+        let remainder = left % &right;
+        if !remainder.is_zero() && remainder.is_negative() != right.is_negative() { quotient - 1 } else { quotient }
+      }
+      BinaryOperator::Rem => {
+        let remainder = left % &right;
+        if !remainder.is_zero() && remainder.is_negative() != right.is_negative() { remainder + right } else { remainder }
+      }
+      BinaryOperator::Pow => left.pow(u32::try_from(&right).ok()?),
+      _ => return None,
+    };
+    Some(from_big(folded))
+  }
+}
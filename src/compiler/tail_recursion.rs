@@ -0,0 +1,171 @@
+//! Rewriting self tail recursion into loops
+//!
+//! The pass is opt-in with [`CompileOptions::tail_recursion`](super::CompileOptions::tail_recursion).
+//! A function that returns a call to itself, like
+//!
+//! ```text
+//! function gcd(a, b)
+//!   if b == 0 then
+//!     return a
+//!   end
+//!   return gcd(b, a % b)
+//! end
+//! ```
+//!
+//! is compiled as if it were written
+//!
+//! ```text
+//! function gcd(a, b)
+//!   while true do
+//!     if b == 0 then
+//!       return a
+//!     end
+//!     a.next = b
+//!     b.next = a % b
+//!     a = a.next
+//!     b = b.next
+//!     continue
+//!   end
+//! end
+//! ```
+//!
+//! so it runs without making any calls.  The hidden `.next` locals can't clash with
+//! identifiers from the source.  Every other local is set back to `nil` before the next
+//! iteration, the same as it would be at the start of a new call.
+//!
+//! Functions are left alone when the rewrite could change what they do: when a parameter
+//! has a default, when a self call is returned from inside a loop, when the body has a
+//! `break` or `continue` outside of a loop, when a nested function has the same name, or
+//! when a `local` shadows a parameter or the function's name.
+
+use alloc::{format, vec, vec::Vec};
+use alloc::collections::BTreeSet;
+use crate::ast::{FunctionDecl, Statement, Expression, Identifier, BooleanLiteral};
+use super::assigned_locals;
+
+/// Body of `function` with its self tail calls turned into a loop, or `None` if it has no
+/// self tail calls or can't be rewritten
+///
+/// `body` is used in place of `function.body` so the pass can run after other passes.
+/// Assignments to names in `globals` are left alone when locals are reset.
+///
+/// # Example:
+/// ```rust
+/// # use lualite::parser::declaration::function_decl;
+/// # use lualite::compiler::tail_recursion::loop_tail_recursion;
+/// let (_, countdown) = function_decl("function f(n) if n > 0 then return f(n - 1) end end").unwrap();
+/// assert!(loop_tail_recursion(&countdown, &countdown.body, &[]).is_some());
+/// let (_, fib) = function_decl("function fib(n) return fib(n - 1) + fib(n - 2) end").unwrap();
+/// assert!(loop_tail_recursion(&fib, &fib.body, &[]).is_none());
+/// ```
+pub fn loop_tail_recursion(function: &FunctionDecl, body: &[Statement], globals: &[Identifier]) -> Option<Vec<Statement>> {
+  if function.defaults.iter().any(Option::is_some) || !can_loop(function, body, false) {
+    return None;
+  }
+  let mut locals = BTreeSet::new();
+  assigned_locals(body, &mut locals);
+  locals.retain(|local| !function.params.contains(local) && !globals.contains(local));
+  let mut rewriter = Rewriter { function, locals, rewritten: false };
+  let (last, leading) = body.split_last()?;
+  let mut looped = rewriter.body(leading);
+  match last {
+    // a trailing expression is the return value
+    Statement::SingleStatement(expression) => match rewriter.tail_call(expression) {
+      Some(statements) => looped.extend(statements),
+      None => looped.push(Statement::ReturnStatement(Some(expression.clone()))),
+    },
+    statement @ Statement::ReturnStatement(_) => looped.extend(rewriter.statement(statement)),
+    statement => {
+      looped.extend(rewriter.statement(statement));
+      looped.push(Statement::ReturnStatement(None));
+    }
+  }
+  if !rewriter.rewritten {
+    return None;
+  }
+  Some(vec![Statement::WhileStatement { condition: Expression::Boolean(BooleanLiteral(true)), body: looped }])
+}
+
+/// Whether `body` can be rewritten without changing what it does
+fn can_loop(function: &FunctionDecl, body: &[Statement], in_loop: bool) -> bool {
+  let name = &function.name;
+  body.iter().all(|statement| match statement {
+    Statement::BreakStatement | Statement::ContinueStatement => in_loop,
+    Statement::ReturnStatement(Some(expression)) => !in_loop || !is_call_to(name, expression),
+    Statement::FunctionStatement(nested) => nested.name != *name,
+    // the rewrite assigns parameters by name, which would assign to the local instead
+    Statement::LocalStatement(local, _) => local != name && !function.params.contains(local),
+    Statement::WhileStatement { body, .. } | Statement::ForStatement { body, .. } => can_loop(function, body, true),
+    Statement::IfStatement { body, else_body, .. } =>
+      can_loop(function, body, in_loop) && else_body.iter().all(|else_body| can_loop(function, else_body, in_loop)),
+    _ => true,
+  })
+}
+
+fn is_call_to(name: &Identifier, expression: &Expression) -> bool {
+  matches!(expression, Expression::FunctionCall { left, .. } if matches!(&**left, Expression::Identifier(callee) if callee == name))
+}
+
+struct Rewriter<'a> {
+  function: &'a FunctionDecl,
+  /// Locals other than the parameters, reset before each iteration
+  locals: BTreeSet<Identifier>,
+  /// Whether any self tail call was rewritten
+  rewritten: bool,
+}
+
+impl Rewriter<'_> {
+  fn body(&mut self, body: &[Statement]) -> Vec<Statement> {
+    body.iter().flat_map(|statement| self.statement(statement)).collect()
+  }
+
+  fn statement(&mut self, statement: &Statement) -> Vec<Statement> {
+    match statement {
+      Statement::ReturnStatement(Some(expression)) => self.tail_call(expression)
+        .unwrap_or_else(|| vec![statement.clone()]),
+      Statement::IfStatement { condition, body, else_body } => vec![Statement::IfStatement {
+        condition: condition.clone(),
+        body: self.body(body),
+        else_body: else_body.as_deref().map(|else_body| self.body(else_body)),
+      }],
+      // loops were checked to not return self calls
+      other => vec![other.clone()],
+    }
+  }
+
+  /// Statements that replace returning `expression`, if it's a self call
+  fn tail_call(&mut self, expression: &Expression) -> Option<Vec<Statement>> {
+    let params = &self.function.params;
+    let args = match expression {
+      Expression::FunctionCall { args, .. } if is_call_to(&self.function.name, expression) => args,
+      _ => return None,
+    };
+    // leave calls with too many arguments to fail at runtime as they would have
+    if args.len() > params.len() {
+      return None;
+    }
+    let changed: Vec<(&Identifier, Expression)> = params.iter().enumerate()
+      .map(|(index, param)| (param, args.get(index).cloned().unwrap_or(Expression::Nil)))
+      .filter(|(param, arg)| !matches!(arg, Expression::Identifier(same) if same == *param))
+      .collect();
+    let mut statements = Vec::new();
+    if let [(param, arg)] = &changed[..] {
+      statements.push(Statement::AssignStatement((*param).clone(), arg.clone()));
+    } else {
+      // every argument is evaluated before any parameter changes
+      let next = |param: &Identifier| Identifier(format!("{}.next", param.0));
+      for (param, arg) in changed.iter() {
+        statements.push(Statement::AssignStatement(next(param), arg.clone()));
+      }
+      for (param, _) in changed.iter() {
+        statements.push(Statement::AssignStatement((*param).clone(), Expression::Identifier(next(param))));
+      }
+    }
+    for local in self.locals.iter() {
+      statements.push(Statement::AssignStatement(local.clone(), Expression::Nil));
+    }
+    statements.push(Statement::ContinueStatement);
+    self.rewritten = true;
+    Some(statements)
+  }
+}
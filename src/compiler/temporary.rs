@@ -1,7 +1,8 @@
 
-use std::fmt;
-use std::cell::RefCell;
-use std::rc::Rc;
+use core::fmt;
+use core::cell::RefCell;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
 use crate::bytecode::operand::{self, AsDestination, AsSource};
 use crate::bytecode::Instruction;
 
@@ -130,45 +131,23 @@ impl TempManager {
   pub fn take_temp_range(&mut self, total: usize) -> Vec<Temporary> {
     let mut inner = self.inner.borrow_mut();
     let temps_use_count = &mut inner.temps_use_count;
+    // find the first run of `total` free temps, a run at the end can be extended
     let mut start = 0;
-    let mut end = 0;
     while start < temps_use_count.len() {
-      if temps_use_count[start] > 0 { // iterate until a free temp is found
-        start += 1;
-        continue;
+      match temps_use_count[start..].iter().take(total).position(|&use_count| use_count > 0) {
+        Some(in_use) => start += in_use + 1,
+        None => break,
       }
-      end = start + 1;
-      while end < temps_use_count.len() { // iterate until `total` free temps are found
-        if end - start + 1 == total { // end is inclusive, so add 1 for length
-          let mut temps = Vec::new();
-          for id in start..=end {
-            temps.push(Temporary {
-              manager: Rc::clone(&self.inner),
-              id,
-            });
-            temps_use_count[id] += 1;
-          }
-          return temps;
-        }
-        if temps_use_count[end] > 0 { // in use temp found, try again
-          start = end + 1;
-          continue;
-        }
-        end += 1;
-      }
-      start += 1;
     }
-    let subtotal = temps_use_count.len() - start;
-    let remaining_needed = total - subtotal;
-    temps_use_count.resize(temps_use_count.len() + remaining_needed, 1);
-    let mut temps = Vec::new();
-    for id in start..temps_use_count.len() {
-      temps.push(Temporary {
-        manager: Rc::clone(&self.inner),
-        id,
-      });
+    if temps_use_count.len() < start + total {
+      temps_use_count.resize(start + total, 0);
+    }
+    for use_count in temps_use_count[start..start + total].iter_mut() {
+      *use_count += 1;
     }
-    temps
+    (start..start + total)
+      .map(|id| Temporary { manager: Rc::clone(&self.inner), id })
+      .collect()
   }
   pub fn set_next_instruction_offset(&mut self, next_instruction_offset: usize) {
     self.inner.borrow_mut().next_instruction_offset = next_instruction_offset;
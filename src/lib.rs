@@ -1,9 +1,31 @@
 //! A basic scripting language with a Rust runtime
+//!
+//! # Features:
+//! - `std` (default) --- enables the [`runtime`] module and [`compile_program`].
+//! - `bigint` --- integers too large for an `i64` become arbitrary-precision instead of
+//!   overflowing, both as literals and as the results of integer arithmetic.
+//!
+//! Without `std` the crate is `no_std` and only needs `alloc`.  The [`parser`], [`ast`],
+//! [`bytecode`], and [`compiler`] modules are always available, so source code can be
+//! compiled on targets without `std`.  Check that the core still builds with:
+//! ```sh
+//! cargo test --no-default-features --test no_std_core
+//! ```
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
 pub mod parser;
 pub mod ast;
 #[allow(unused)] // TOOD: remove after compiler refactor
 pub mod compiler;
 pub mod bytecode;
+#[cfg(feature = "std")]
 pub mod runtime;
+#[cfg(feature = "std")]
+mod program;
+
+#[cfg(feature = "std")]
+pub use program::{compile_program, ProgramError};
 
@@ -1,5 +1,7 @@
 #![allow(unused)]
 
+extern crate alloc;
+
 mod parser;
 mod ast;
 mod compiler;
@@ -13,8 +15,12 @@ use std::cell::RefCell;
 use std::env;
 use std::path::Path;
 use std::fs::File;
+use std::fmt;
 use std::io::{self, prelude::*};
+use std::process;
 use crate::ast::Identifier;
+use crate::compiler::CompileError;
+use crate::parser::ParseError;
 use crate::runtime::{VirtualMachine, Value, RuntimeError};
 
 
@@ -22,9 +28,33 @@ use crate::runtime::{VirtualMachine, Value, RuntimeError};
 enum InterpreterError {
   NoFilesGiven,
   FileCouldNotBeOpened,
+  MissingEntry {
+    entry: &'static str,
+    available: Vec<String>,
+  },
   Io(io::Error),
+  Parse(ParseError),
+  Compile(CompileError),
+  Link(CompileError),
   Runtime(RuntimeError),
-  Unknown,
+}
+
+impl fmt::Display for InterpreterError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      InterpreterError::NoFilesGiven => write!(f, "error: no source files given"),
+      InterpreterError::FileCouldNotBeOpened => write!(f, "error: a source file could not be opened"),
+      InterpreterError::MissingEntry { entry, available } if available.is_empty() =>
+        write!(f, "error: no `{entry}` function found, the program does not define any functions"),
+      InterpreterError::MissingEntry { entry, available } =>
+        write!(f, "error: no `{entry}` function found, available functions are: {}", available.join(", ")),
+      InterpreterError::Io(error) => write!(f, "error: {error}"),
+      InterpreterError::Parse(error) => write!(f, "parse error: {error}"),
+      InterpreterError::Compile(error) => write!(f, "compile error: {error:?}"),
+      InterpreterError::Link(error) => write!(f, "link error: {error:?}"),
+      InterpreterError::Runtime(error) => write!(f, "runtime error: {error:?}"),
+    }
+  }
 }
 
 impl From<io::Error> for InterpreterError {
@@ -33,6 +63,12 @@ impl From<io::Error> for InterpreterError {
   }
 }
 
+impl From<CompileError> for InterpreterError {
+  fn from(compile_error: CompileError) -> Self {
+    Self::Compile(compile_error)
+  }
+}
+
 impl From<RuntimeError> for InterpreterError {
   fn from(runtime_error: RuntimeError) -> Self {
     Self::Runtime(runtime_error)
@@ -54,17 +90,29 @@ fn get_args_content() -> Result<String, InterpreterError> {
   Ok(ret)
 }
 
-fn main() -> Result<(), InterpreterError> {
+const ENTRY_NAME: &str = VirtualMachine::ENTRY_NAME;
+
+fn main() {
+  // errors are printed once, here, instead of also by the default `Termination` impl
+  if let Err(error) = run() {
+    eprintln!("{error}");
+    process::exit(1);
+  }
+}
+
+fn run() -> Result<(), InterpreterError> {
   let source_code = get_args_content()?;
-  let parse_result = parser::parse_file(&source_code);
-  let declarations = match parse_result {
-    Ok((_, declarations)) => declarations,
-    Err(error) => {
-      println!("parse error: {error:?}");
-      return Err(InterpreterError::Unknown);
-    }
-  };
-  let functions = compiler::compile_declarations(declarations.iter());
+  let declarations = parser::parse_program(&source_code).map_err(InterpreterError::Parse)?;
+  if declarations.is_empty() {
+    // nothing to run, an empty program is a no-op
+    return Ok(());
+  }
+  let statics = compiler::compile_statics(declarations.iter())?;
+  let functions = compiler::compile_declarations(declarations.iter())?;
+  let mut vm = VirtualMachine::new();
+  vm.set_globals(statics);
+  compiler::link(&functions, vm.native_names().into_iter().chain(VirtualMachine::BUILTIN_NAMES))
+    .map_err(InterpreterError::Link)?;
 
   /*
   // debug output
@@ -74,9 +122,14 @@ fn main() -> Result<(), InterpreterError> {
   }
   */
 
-  let mut vm = VirtualMachine::with_functions(functions);
-  let output = vm.run("main", [])?;
+  for (name, procedure) in functions {
+    vm.insert_function(name, procedure);
+  }
+  if !vm.has_function(ENTRY_NAME) {
+    let available: Vec<String> = vm.function_names().into_iter().map(str::to_owned).collect();
+    return Err(InterpreterError::MissingEntry { entry: ENTRY_NAME, available });
+  }
+  let output = vm.run_main()?;
   println!("lualite result: {output}");
   Ok(())
 }
-
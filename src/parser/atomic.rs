@@ -1,15 +1,15 @@
 //! Building blocks for expressions, statements, and declarations
 
 use nom::{
-  IResult,
   branch::alt,
-  bytes::complete::{tag, take_until, take_while1},
+  bytes::complete::{tag, take_till, take_while1},
   character::{self, complete::satisfy},
-  combinator::{map, not},
-  number,
-  sequence::delimited,
 };
-use crate::ast::{Identifier, IntegerLiteral, FloatLiteral, StringLiteral};
+use super::{IResult, NomError, ParseErrorKind};
+use alloc::borrow::ToOwned;
+use crate::ast::{Identifier, IntegerLiteral, FloatLiteral, StringLiteral, BytesLiteral};
+#[cfg(feature = "bigint")]
+use crate::ast::BigIntegerLiteral;
 
 /// Parser builder for making keyword parsers
 ///
@@ -42,7 +42,7 @@ pub fn keyword<'kw>(kw: &'kw str) -> impl Fn(&str) -> IResult<&str, &str> + 'kw
     if matched == kw {
       Ok((remaining, matched))
     } else {
-      Err(nom::Err::Error(nom::error::Error { input: s, code: nom::error::ErrorKind::Tag }))
+      Err(nom::Err::Error(NomError::new(s, ParseErrorKind::UnexpectedInput)))
     }
   }
 }
@@ -57,20 +57,74 @@ pub fn keyword<'kw>(kw: &'kw str) -> impl Fn(&str) -> IResult<&str, &str> + 'kw
 /// assert!(any_keyword("1234").is_err()); // fails, 1234 is not a word
 /// ```
 pub fn any_keyword(s: &str) -> IResult<&str, &str> {
-  alt((
-    keyword("end"), keyword("function"), keyword("return"), keyword("if"), keyword("then"),
-    keyword("elseif"), keyword("else"), keyword("while"), keyword("do"), keyword("for"),
-    keyword("in"), keyword("nil"), keyword("and"), keyword("or"), keyword("not"), 
-    keyword("true"), keyword("false")
-  ))(s)
+  let (remaining, matched) = word(s)?;
+  if KEYWORDS.contains(&matched) {
+    Ok((remaining, matched))
+  } else {
+    Err(nom::Err::Error(NomError::new(s, ParseErrorKind::UnexpectedInput)))
+  }
+}
+
+/// Every keyword, none of which can be used as an identifier
+pub const KEYWORDS: [&str; 20] = [
+  "end", "function", "return", "if", "then", "elseif", "else", "while", "do", "for", "in",
+  "nil", "and", "or", "not", "true", "false", "break", "continue", "local",
+];
+
+/// Which letters and digits can be used in words and identifiers
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum IdentifierMode {
+  /// Any Unicode letter or digit, as decided by [`char::is_alphabetic`] and
+  /// [`char::is_alphanumeric`]
+  #[default]
+  Unicode,
+  /// Only ASCII letters and digits
+  Ascii,
+}
+
+impl IdentifierMode {
+  fn is_start(self, c: char) -> bool {
+    c == '_' || match self {
+      IdentifierMode::Unicode => c.is_alphabetic(),
+      IdentifierMode::Ascii => c.is_ascii_alphabetic(),
+    }
+  }
+
+  fn is_continue(self, c: char) -> bool {
+    c == '_' || match self {
+      IdentifierMode::Unicode => c.is_alphanumeric(),
+      IdentifierMode::Ascii => c.is_ascii_alphanumeric(),
+    }
+  }
+}
+
+/// Parser builder for words made of the letters and digits allowed by `mode`
+///
+/// A word never ends in the middle of a run of letters.  In ASCII mode `café` is
+/// rejected instead of matching `caf`.
+/// # Example:
+/// ```rust
+/// # use lualite::parser::atomic::{word_with_mode, IdentifierMode};
+/// assert_eq!(word_with_mode(IdentifierMode::Unicode)("café = 1"), Ok((" = 1", "café")));
+/// assert!(word_with_mode(IdentifierMode::Ascii)("café = 1").is_err());
+/// ```
+pub fn word_with_mode(mode: IdentifierMode) -> impl Fn(&str) -> IResult<&str, &str> {
+  move |s| {
+    satisfy(|c: char| mode.is_start(c))(s)?;
+    let (remaining, matched) = take_while1(|c: char| mode.is_continue(c))(s)?;
+    if remaining.starts_with(char::is_alphanumeric) {
+      return Err(nom::Err::Error(NomError::new(remaining, ParseErrorKind::UnexpectedInput)));
+    }
+    Ok((remaining, matched))
+  }
 }
 
 /// Parses a string consisting of only letters, numbers, and underscores
 ///
-/// First character cannot be a digit.
+/// First character cannot be a digit.  Letters and digits can be any Unicode letter or
+/// digit, see [`word_with_mode`] to restrict them to ASCII.
 pub fn word(s: &str) -> IResult<&str, &str> {
-  satisfy(|c: char| c.is_alphabetic() || c == '_')(s)?;
-  take_while1(|c: char| c.is_alphanumeric() || c == '_')(s)
+  word_with_mode(IdentifierMode::Unicode)(s)
 }
 
 /// Parser for identifiers
@@ -86,12 +140,33 @@ pub fn word(s: &str) -> IResult<&str, &str> {
 /// assert!(identifier("7hello").is_err()); // fails (treated as integer literal)
 /// ```
 pub fn identifier(s: &str) -> IResult<&str, Identifier> {
-  not(any_keyword)(s)?;
-  map(word, |ident: &str| Identifier(ident.to_owned()))(s)
+  identifier_with_mode(IdentifierMode::Unicode)(s)
+}
+
+/// Parser builder for identifiers made of the letters and digits allowed by `mode`
+/// # Example:
+/// ```rust
+/// # use lualite::parser::atomic::{identifier_with_mode, IdentifierMode};
+/// # use lualite::ast::Identifier;
+/// let (_, ident) = identifier_with_mode(IdentifierMode::Unicode)("café").unwrap();
+/// assert_eq!(ident, Identifier("café".to_owned()));
+/// assert!(identifier_with_mode(IdentifierMode::Ascii)("café").is_err());
+/// assert!(identifier_with_mode(IdentifierMode::Ascii)("cafe").is_ok());
+/// ```
+pub fn identifier_with_mode(mode: IdentifierMode) -> impl Fn(&str) -> IResult<&str, Identifier> {
+  move |s| {
+    let (remaining, matched) = word_with_mode(mode)(s)?;
+    if KEYWORDS.contains(&matched) {
+      return Err(nom::Err::Error(NomError::new(s, ParseErrorKind::UnexpectedInput)));
+    }
+    Ok((remaining, Identifier(matched.to_owned())))
+  }
 }
 
 /// Parser for signed 64-bit integer literals
 ///
+/// An `i` suffix, as in `5i`, is allowed to make it clear a literal is an integer.
+///
 /// # Example:
 /// ```rust
 /// # use lualite::parser::atomic::integer;
@@ -100,27 +175,124 @@ pub fn identifier(s: &str) -> IResult<&str, Identifier> {
 /// assert_eq!(integer("537"), Ok(("", IntegerLiteral(537_i64))));
 /// assert!(integer("abcd").is_err());
 /// assert_eq!(integer("-11"), Ok(("", IntegerLiteral(-11_i64))));
+/// assert_eq!(integer("5i + 1"), Ok((" + 1", IntegerLiteral(5_i64))));
 /// ```
 pub fn integer(s: &str) -> IResult<&str, IntegerLiteral> {
-  map(character::complete::i64, |int| IntegerLiteral(int))(s)
+  let (remaining, int) = character::complete::i64(s)?;
+  Ok((strip_type_suffix(remaining, 'i'), IntegerLiteral(int)))
+}
+
+/// `s` without a leading type `suffix`, or all of `s` if the suffix is the start of a
+/// longer word
+fn strip_type_suffix(s: &str, suffix: char) -> &str {
+  match s.strip_prefix(suffix) {
+    Some(remaining) if !remaining.starts_with(|c: char| c.is_alphanumeric() || c == '_') => remaining,
+    _ => s,
+  }
+}
+
+/// Parser for integer literals of any size
+///
+/// Only the literals that [`integer`] rejects for being too large should end up as a
+/// `BigIntegerLiteral`, so try [`integer`] first.
+///
+/// # Example:
+/// ```rust
+/// # use lualite::parser::atomic::big_integer;
+/// let (_, literal) = big_integer("-100000000000000000000").unwrap();
+/// assert_eq!(literal.0.to_string(), "-100000000000000000000");
+/// ```
+#[cfg(feature = "bigint")]
+pub fn big_integer(s: &str) -> IResult<&str, BigIntegerLiteral> {
+  use nom::{character::complete::{digit1, one_of}, combinator::{opt, recognize, map_res}, sequence::pair};
+  let (remaining, literal) = map_res(recognize(pair(opt(one_of("+-")), digit1)), |digits: &str| digits.parse().map(BigIntegerLiteral))(s)?;
+  Ok((strip_type_suffix(remaining, 'i'), literal))
 }
 
 /// Parser for double-precision floating-point literals
 ///
 /// Input must contain a `.` for this parser to succeed.  Doing so prevents it from
-/// matching integers.
+/// matching integers.  Digits are optional on one side of the `.`, so `.5` and `5.` are
+/// both floats, but `5.` followed by a letter is not since that would be a field or method
+/// access once those exist.
+///
+/// Digits without a `.` are also a float when they end in an `f` suffix, as in `5f`.
+/// # Example:
+/// ```rust
+/// # use lualite::parser::atomic::float;
+/// # use lualite::ast::FloatLiteral;
+/// assert_eq!(float(".5"), Ok(("", FloatLiteral(0.5))));
+/// assert_eq!(float("5. "), Ok((" ", FloatLiteral(5.0))));
+/// assert_eq!(float("2.5e3"), Ok(("", FloatLiteral(2500.0))));
+/// assert_eq!(float("5f / 2"), Ok((" / 2", FloatLiteral(5.0))));
+/// assert!(float("5").is_err());
+/// assert!(float("5.max").is_err());
+/// assert!(float("5fold").is_err());
+/// ```
 pub fn float(s: &str) -> IResult<&str, FloatLiteral> {
-  let (_remaining, flt_str) = number::complete::recognize_float(s)?;
-  // without this check, float would match integers as well
-  if flt_str.contains('.') {
-    map(number::complete::double, |flt| FloatLiteral(flt))(s)
-  } else {
-    Err(nom::Err::Error(nom::error::Error { input: s, code: nom::error::ErrorKind::Float }))
+  use nom::{character::complete::{char, digit0, digit1, one_of}, combinator::{opt, recognize}, sequence::{pair, tuple}};
+  let float_error = || nom::Err::Error(NomError::new(s, ParseErrorKind::UnexpectedInput));
+  if let Ok((remaining, digits)) = recognize(pair(opt(one_of::<_, _, NomError<&str>>("+-")), digit1))(s) {
+    let after_suffix = strip_type_suffix(remaining, 'f');
+    if after_suffix.len() < remaining.len() {
+      return digits.parse().map(|flt| (after_suffix, FloatLiteral(flt))).map_err(|_| float_error());
+    }
+  }
+  let mantissa = alt((recognize(tuple((digit1, char('.'), digit0))), recognize(pair(char('.'), digit1))));
+  let exponent = tuple((one_of("eE"), opt(one_of("+-")), digit1));
+  let (remaining, flt_str) = recognize(tuple((opt(one_of("+-")), mantissa, opt(exponent))))(s)?;
+  // the start of a range like `1..10` isn't a float
+  let starts_range = flt_str.ends_with('.') && remaining.starts_with('.');
+  let starts_word = flt_str.ends_with('.') && remaining.starts_with(|c: char| c.is_alphabetic() || c == '_');
+  match flt_str.parse() {
+    Ok(flt) if !starts_range && !starts_word => Ok((remaining, FloatLiteral(flt))),
+    _ => Err(float_error()),
   }
 }
 
 /// Parser for string literals
+///
+/// String literals can't span multiple lines.  A string without a closing quote on the
+/// same line is a [`nom::Err::Failure`] with [`ParseErrorKind::UnterminatedString`] as the
+/// error kind and the input starting at the opening quote.
+/// # Example:
+/// ```rust
+/// # use lualite::parser::{atomic::string, ParseErrorKind};
+/// # use lualite::ast::StringLiteral;
+/// assert_eq!(string("\"hello\""), Ok(("", StringLiteral("hello".to_owned()))));
+///
+/// let source = "x = \"oops\nreturn \"x\"";
+/// match string(&source[4..]) {
+///   Err(nom::Err::Failure(error)) => {
+///     assert_eq!(error.kind, ParseErrorKind::UnterminatedString);
+///     assert_eq!(source.len() - error.input.len(), 4);
+///   }
+///   other => panic!("expected an unterminated string, got {other:?}"),
+/// }
+/// ```
 pub fn string(s: &str) -> IResult<&str, StringLiteral> {
-  map(delimited(tag("\""), take_until("\""), tag("\"")), |string: &str| StringLiteral(string.to_owned()))(s)
+  let (after_quote, _) = tag("\"")(s)?;
+  let (remaining, contents) = take_till(|c| c == '"' || c == '\n')(after_quote)?;
+  match tag::<_, _, NomError<&str>>("\"")(remaining) {
+    Ok((remaining, _)) => Ok((remaining, StringLiteral(contents.to_owned()))),
+    Err(_) => Err(nom::Err::Failure(NomError::new(s, ParseErrorKind::UnterminatedString))),
+  }
+}
+
+/// Parser for byte string literals
+///
+/// A byte string is a string literal prefixed with `b`, and holds the UTF-8 bytes of its
+/// contents.  It follows the same rules as [`string`].
+/// # Example:
+/// ```rust
+/// # use lualite::parser::atomic::byte_string;
+/// # use lualite::ast::BytesLiteral;
+/// assert_eq!(byte_string("b\"abc\""), Ok(("", BytesLiteral(vec![97, 98, 99]))));
+/// assert!(byte_string("\"abc\"").is_err());
+/// ```
+pub fn byte_string(s: &str) -> IResult<&str, BytesLiteral> {
+  let (after_prefix, _) = tag("b")(s)?;
+  let (remaining, StringLiteral(contents)) = string(after_prefix)?;
+  Ok((remaining, BytesLiteral(contents.into_bytes())))
 }
 
@@ -12,10 +12,11 @@
 //! # use lualite::parser::declaration::function_decl;
 //! let (_remaining, parsed_ast) = function_decl(source_code).expect("parse error");
 //!
-//! # use lualite::ast::{FunctionDecl, Identifier, Statement, Expression, BinaryOperator, IntegerLiteral};
+//! # use lualite::ast::{FunctionDecl, Identifier, Statement, Expression, BinaryOperator, IntegerLiteral, Span};
 //! let expected_ast = FunctionDecl {
 //!   name: Identifier("f".to_owned()),
 //!   params: vec![Identifier("a".to_owned()), Identifier("b".to_owned())],
+//!   defaults: vec![None, None],
 //!   body: vec![
 //!     Statement::ReturnStatement(Some(Expression::Binary {
 //!       left: Box::new(Expression::Binary {
@@ -27,6 +28,7 @@
 //!       right: Box::new(Expression::Identifier(Identifier("b".to_owned()))),
 //!     })),
 //!   ],
+//!   span: Span::default(),
 //! };
 //!
 //! assert_eq!(parsed_ast, expected_ast);
@@ -40,10 +42,11 @@
 //! # use lualite::parser::declaration::static_decl;
 //! let (_remaining, parsed_ast) = static_decl(source_code).expect("parse error");
 //!
-//! # use lualite::ast::{StaticDecl, Identifier, Expression, IntegerLiteral};
+//! # use lualite::ast::{StaticDecl, Identifier, Expression, IntegerLiteral, Span};
 //! let expected_ast = StaticDecl {
 //!   name: Identifier("SIZE".to_owned()),
 //!   value: Some(Expression::Integer(IntegerLiteral(512_i64))),
+//!   span: Span::default(),
 //! };
 //!
 //! assert_eq!(parsed_ast, expected_ast);
@@ -54,14 +57,16 @@
 //! either a function declaration or a static declaration.
 
 use nom::{
-  IResult,
   branch::alt,
   bytes::complete::tag,
-  combinator::map,
+  character::complete::space0,
+  combinator::{map, opt},
   multi::separated_list0,
-  sequence::{delimited, tuple},
+  sequence::{delimited, pair, preceded, terminated, tuple},
 };
-use crate::ast::{Identifier, FunctionDecl, StaticDecl, Declaration};
+use super::IResult;
+use alloc::{vec, vec::Vec};
+use crate::ast::{Identifier, Expression, Statement, FunctionDecl, StaticDecl, Declaration, Span};
 use super::atomic::{identifier, keyword};
 use super::statement::body;
 use super::expression::expression;
@@ -76,6 +81,17 @@ pub fn declaration(s: &str) -> IResult<&str, Declaration> {
 }
 
 /// Parses a function declaration
+///
+/// A function that only returns an expression can be written as `= expression` in place
+/// of its body and `end`.
+///
+/// # Example:
+/// ```rust
+/// # use lualite::parser::declaration::function_decl;
+/// let (_, short) = function_decl("function double(x) = x * 2").unwrap();
+/// let (_, long) = function_decl("function double(x) return x * 2 end").unwrap();
+/// assert_eq!(short, long);
+/// ```
 pub fn function_decl(s: &str) -> IResult<&str, FunctionDecl> {
   map(
     tuple((
@@ -85,14 +101,24 @@ pub fn function_decl(s: &str) -> IResult<&str, FunctionDecl> {
       identifier,
       whitespace,
       params_list,
-      body,
-      whitespace,
-      keyword("end"),
+      function_body,
     )),
-    |(_, _function, _, name, _, params, body, _, _end)| FunctionDecl { name, params, body },
+    |(_, _function, _, name, _, (params, defaults), body)| FunctionDecl { name, params, defaults, body, span: Span::default() },
   )(s)
 }
 
+/// Statements up to and including `end`, or `= expression` for a function that returns
+/// the expression
+fn function_body(s: &str) -> IResult<&str, Vec<Statement>> {
+  alt((
+    map(
+      preceded(tuple((space0, tag("="), space0)), expression),
+      |expression| vec![Statement::ReturnStatement(Some(expression))],
+    ),
+    terminated(body, pair(whitespace, keyword("end"))),
+  ))(s)
+}
+
 /// Parses a static declaration
 pub fn static_decl(s: &str) -> IResult<&str, StaticDecl> {
   map(
@@ -106,18 +132,29 @@ pub fn static_decl(s: &str) -> IResult<&str, StaticDecl> {
       whitespace,
       expression,
     )),
-    |(_, _static, _, name, _, _equals, _, value)| StaticDecl { name, value: Some(value) },
+    |(_, _static, _, name, _, _equals, _, value)| StaticDecl { name, value: Some(value), span: Span::default() },
   )(s)
 }
 
-fn params_list(s: &str) -> IResult<&str, Vec<Identifier>> {
-  delimited(
-    tag("("),
-    separated_list0(
-      tag(","),
-      delimited(whitespace, identifier, whitespace),
+/// Parameter names and their optional default values, such as `(a, b = 10)`
+fn params_list(s: &str) -> IResult<&str, (Vec<Identifier>, Vec<Option<Expression>>)> {
+  map(
+    delimited(
+      tag("("),
+      separated_list0(
+        tag(","),
+        delimited(whitespace, param, whitespace),
+      ),
+      tag(")"),
     ),
-    tag(")"),
+    |params| params.into_iter().unzip(),
+  )(s)
+}
+
+fn param(s: &str) -> IResult<&str, (Identifier, Option<Expression>)> {
+  pair(
+    identifier,
+    opt(preceded(tuple((whitespace, tag("="), whitespace)), expression)),
   )(s)
 }
 
@@ -0,0 +1,99 @@
+use core::fmt;
+use nom::error::{ErrorKind, FromExternalError};
+
+/// Parse failure with its position in the source code
+///
+/// Returned by [`parse_program`](super::parse_program) and the other parsers that consume
+/// their whole input, so callers don't depend on nom's error types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+  /// Byte offset into the source code
+  pub offset: usize,
+  /// Line number, counting from 1
+  pub line: usize,
+  /// Character position within the line, counting from 1
+  pub column: usize,
+  pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+  /// A string literal without a closing quote on the same line
+  UnterminatedString,
+  /// A non-ASCII character outside of string literals and comments while parsing with
+  /// [`IdentifierMode::Ascii`](super::atomic::IdentifierMode::Ascii)
+  NonAsciiCharacter,
+  /// Source code that doesn't form a valid declaration or expression
+  UnexpectedInput,
+}
+
+/// Error returned by the nom parsers in this crate, see [`IResult`](super::IResult)
+///
+/// A parser that knows why the input is wrong (like [`string`](super::atomic::string) for a
+/// missing closing quote) says so in `kind`, anything else is
+/// [`ParseErrorKind::UnexpectedInput`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NomError<I> {
+  /// Input where the error happened
+  pub input: I,
+  pub kind: ParseErrorKind,
+}
+
+impl<I> NomError<I> {
+  pub fn new(input: I, kind: ParseErrorKind) -> Self {
+    Self { input, kind }
+  }
+}
+
+impl<I> nom::error::ParseError<I> for NomError<I> {
+  fn from_error_kind(input: I, _kind: ErrorKind) -> Self {
+    Self::new(input, ParseErrorKind::UnexpectedInput)
+  }
+
+  fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+    other
+  }
+}
+
+impl<I, E> FromExternalError<I, E> for NomError<I> {
+  fn from_external_error(input: I, _kind: ErrorKind, _error: E) -> Self {
+    Self::new(input, ParseErrorKind::UnexpectedInput)
+  }
+}
+
+impl ParseError {
+  /// Converts a nom error whose input is a suffix of `source`
+  pub(crate) fn from_nom(source: &str, error: nom::Err<NomError<&str>>) -> Self {
+    match error {
+      nom::Err::Error(error) | nom::Err::Failure(error) => Self::at(source, error.input, error.kind),
+      nom::Err::Incomplete(_) => Self::at(source, "", ParseErrorKind::UnexpectedInput),
+    }
+  }
+
+  /// Error for parsing stopping at `remaining`, which is a suffix of `source`
+  pub(crate) fn at(source: &str, remaining: &str, kind: ParseErrorKind) -> Self {
+    // point at what didn't parse, not the whitespace before it
+    let remaining = super::whitespace(remaining).map_or(remaining, |(rest, _)| rest);
+    let offset = source.len() - remaining.len();
+    let (line, column) = line_and_column(source, offset);
+    ParseError { offset, line, column, kind }
+  }
+}
+
+/// Line and column of the byte `offset` into `source`, both counting from 1
+fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+  let before = &source[..offset];
+  let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+  (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let description = match self.kind {
+      ParseErrorKind::UnterminatedString => "unterminated string",
+      ParseErrorKind::NonAsciiCharacter => "non-ASCII character",
+      ParseErrorKind::UnexpectedInput => "unexpected input",
+    };
+    write!(f, "{description} on line {}, column {}", self.line, self.column)
+  }
+}
@@ -44,16 +44,19 @@
 //! ```
 
 use nom::{
-  IResult,
   branch::alt,
   bytes::complete::tag,
   character::complete::space0,
-  combinator::{map, opt},
-  sequence::{delimited, pair, tuple},
-  multi::{many0, separated_list0},
+  combinator::{map, not, opt, verify},
+  sequence::{delimited, pair, preceded, separated_pair, terminated},
+  multi::{many0, separated_list0, separated_list1},
 };
-use crate::ast::{Expression, BooleanLiteral};
-use super::atomic::{identifier, integer, float, string, keyword};
+use super::IResult;
+use alloc::{boxed::Box, vec::Vec};
+use crate::ast::{Expression, Identifier, BooleanLiteral};
+use super::atomic::{identifier, integer, float, string, byte_string, keyword};
+#[cfg(feature = "bigint")]
+use super::atomic::big_integer;
 use super::{operator, whitespace};
 
 /// Any possible expression with arbitrary nesting
@@ -65,9 +68,10 @@ use super::{operator, whitespace};
 /// let (_, ast) = expression("array[i - 1] * 2").expect("parse error");
 /// let (_, ast) = expression("(a / 2) + (b / 3)").expect("parse error");
 /// let (_, ast) = expression("a < b and b < c").expect("parse error");
+/// let (_, ast) = expression("a > b ? a : b").expect("parse error");
 /// ```
 pub fn expression(s: &str) -> IResult<&str, Expression> {
-  comparison_expression(s)
+  conditional_expression(s)
 }
 
 /// Expressions with the highest precedence
@@ -75,49 +79,139 @@ pub fn expression(s: &str) -> IResult<&str, Expression> {
 /// Consists of literals, identifiers, and parenthesized expressions.
 pub fn leaf_expression(s: &str) -> IResult<&str, Expression> {
   alt((
+    // before identifiers, otherwise the `b` prefix is read as one
+    map(byte_string, Expression::Bytes),
     map(identifier, |ident| Expression::Identifier(ident)),
     map(float, |flt| Expression::Float(flt)),
-    map(integer, |int| Expression::Integer(int)),
+    integer_literal,
     map(string, |s| Expression::String(s)),
     map(keyword("true"), |_| Expression::Boolean(BooleanLiteral(true))),
     map(keyword("false"), |_| Expression::Boolean(BooleanLiteral(false))),
+    map(keyword("nil"), |_| Expression::Nil),
+    array_literal,
     parenthesized,
   ))(s)
 }
 
+/// Integer literal, falling back to a big integer when it doesn't fit in an `i64`
+fn integer_literal(s: &str) -> IResult<&str, Expression> {
+  #[cfg(feature = "bigint")]
+  return alt((
+    map(integer, |int| Expression::Integer(int)),
+    map(big_integer, Expression::BigInteger),
+  ))(s);
+  #[cfg(not(feature = "bigint"))]
+  map(integer, |int| Expression::Integer(int))(s)
+}
+
 /// Post-fix operators (call and index)
+///
+/// Any number of calls and indexes can follow a leaf expression, and they nest from left
+/// to right.
+/// # Example:
+/// ```rust
+/// # use lualite::parser::expression::postfix_expression;
+/// # use lualite::ast::{Expression, Identifier};
+/// let (_, parsed_ast) = postfix_expression("a[i][j]").unwrap();
+/// let ident = |name: &str| Box::new(Expression::Identifier(Identifier(name.to_owned())));
+/// let expected_ast = Expression::Index {
+///   left: Box::new(Expression::Index { left: ident("a"), index: ident("i") }),
+///   index: ident("j"),
+/// };
+/// assert_eq!(parsed_ast, expected_ast);
+/// ```
 pub fn postfix_expression(s: &str) -> IResult<&str, Expression> {
+  let (s, leaf) = leaf_expression(s)?;
+  // there are no fields to read, so `a.5` or `5.method` is an error instead of two
+  // expressions; `..` still starts a range
+  let (s, _) = match leaf {
+    Expression::Identifier(_) | Expression::Integer(_) => not(pair(tag("."), not(tag("."))))(s)?,
+    #[cfg(feature = "bigint")]
+    Expression::BigInteger(_) => not(pair(tag("."), not(tag("."))))(s)?,
+    _ => (s, ()),
+  };
+  let (s, operators) = many0(preceded(space0, postfix_operator))(s)?;
+  let nested = operators.into_iter().fold(leaf, |left, operator| match operator {
+    Postfix::Call(args) => Expression::FunctionCall {
+      left: Box::new(left),
+      args,
+    },
+    Postfix::NamedCall(args) => Expression::NamedCall {
+      left: Box::new(left),
+      args,
+    },
+    Postfix::Index(index) => Expression::Index {
+      left: Box::new(left),
+      index: Box::new(index),
+    },
+    Postfix::Slice(start, end) => Expression::Slice {
+      left: Box::new(left),
+      start: start.map(Box::new),
+      end: end.map(Box::new),
+    },
+  });
+  Ok((s, nested))
+}
+
+/// A single post-fix operator, applied to the expression before it
+enum Postfix {
+  Call(Vec<Expression>),
+  NamedCall(Vec<(Identifier, Expression)>),
+  Index(Expression),
+  Slice(Option<Expression>, Option<Expression>),
+}
+
+fn postfix_operator(s: &str) -> IResult<&str, Postfix> {
   alt((
-    function_call,
-    index_expression,
-    leaf_expression,
+    map(named_arg_list, Postfix::NamedCall),
+    map(arg_list, Postfix::Call),
+    map(index, Postfix::Index),
+    map(slice, |(start, end)| Postfix::Slice(start, end)),
   ))(s)
 }
 
 /// Function call expression
+///
+/// The call is the root of the expression tree, so `f(x)[0]` is not a function call.
 pub fn function_call(s: &str) -> IResult<&str, Expression> {
-  map(
-    tuple((
-      leaf_expression,
-      space0,
-      arg_list,
-    )),
-    |(function, _, args)| Expression::FunctionCall {
-      left: Box::new(function),
-      args,
-    },
-  )(s)
+  verify(postfix_expression, |expr| matches!(expr, Expression::FunctionCall { .. }))(s)
 }
 
 /// Parenthesized expression to modify operator precedence order
 pub fn parenthesized(s: &str) -> IResult<&str, Expression> {
   delimited(
     tag("("),
-    expression,
+    delimited(whitespace, expression, whitespace),
     tag(")"),
   )(s)
 }
 
+/// Array literal, such as `[1, 2, 3]`
+/// # Example:
+/// ```rust
+/// # use lualite::parser::expression::array_literal;
+/// # use lualite::ast::{Expression, IntegerLiteral};
+/// let (_, parsed_ast) = array_literal("[1, 2]").unwrap();
+/// let expected_ast = Expression::Array(vec![
+///   Expression::Integer(IntegerLiteral(1)),
+///   Expression::Integer(IntegerLiteral(2)),
+/// ]);
+/// assert_eq!(parsed_ast, expected_ast);
+/// ```
+pub fn array_literal(s: &str) -> IResult<&str, Expression> {
+  map(
+    delimited(
+      tag("["),
+      separated_list0(
+        tag(","),
+        delimited(whitespace, expression, whitespace),
+      ),
+      pair(whitespace, tag("]")),
+    ),
+    Expression::Array,
+  )(s)
+}
+
 /// Argument list for a function call
 pub fn arg_list(s: &str) -> IResult<&str, Vec<Expression>> {
   delimited(
@@ -130,27 +224,91 @@ pub fn arg_list(s: &str) -> IResult<&str, Vec<Expression>> {
   )(s)
 }
 
+/// Named argument list for a function call, such as `(x = 1, y = 2)`
+///
+/// Every argument has to be named.
+///
+/// # Example:
+/// ```rust
+/// # use lualite::parser::expression::named_arg_list;
+/// # use lualite::ast::{Expression, Identifier, IntegerLiteral};
+/// let (_, args) = named_arg_list("(y = 2, x = 1)").unwrap();
+/// assert_eq!(args, [
+///   (Identifier("y".to_owned()), Expression::Integer(IntegerLiteral(2))),
+///   (Identifier("x".to_owned()), Expression::Integer(IntegerLiteral(1))),
+/// ]);
+/// assert!(named_arg_list("(x == 1)").is_err());
+/// ```
+pub fn named_arg_list(s: &str) -> IResult<&str, Vec<(Identifier, Expression)>> {
+  delimited(
+    tag("("),
+    separated_list1(
+      tag(","),
+      delimited(
+        whitespace,
+        separated_pair(identifier, delimited(space0, named_arg_equals, space0), expression),
+        whitespace,
+      ),
+    ),
+    tag(")"),
+  )(s)
+}
+
+/// A single `=`, so `x == 1` isn't read as naming `x`
+fn named_arg_equals(s: &str) -> IResult<&str, &str> {
+  terminated(tag("="), not(tag("=")))(s)
+}
+
 /// Indexed container as an r-value
+///
+/// The index is the root of the expression tree, so `a[i](x)` is not an index expression.
 pub fn index_expression(s: &str) -> IResult<&str, Expression> {
-  map(
-    tuple((
-      leaf_expression,
-      space0,
-      tag("["),
-      expression,
-      tag("]"),
-    )),
-    |(array, _, _, index, _)| Expression::Index {
-      left: Box::new(array),
-      index: Box::new(index),
-    },
+  verify(postfix_expression, |expr| matches!(expr, Expression::Index { .. }))(s)
+}
+
+/// Bracketed index following a container, such as `[i + 1]`
+fn index(s: &str) -> IResult<&str, Expression> {
+  delimited(
+    tag("["),
+    delimited(whitespace, expression, whitespace),
+    tag("]"),
+  )(s)
+}
+
+/// Bracketed slice bounds following a container, such as `[1:n]`, `[:n]` or `[1:]`
+fn slice(s: &str) -> IResult<&str, (Option<Expression>, Option<Expression>)> {
+  delimited(
+    tag("["),
+    separated_pair(
+      delimited(whitespace, opt(expression), whitespace),
+      tag(":"),
+      delimited(whitespace, opt(expression), whitespace),
+    ),
+    tag("]"),
   )(s)
 }
 
 /// An expression raised to the power of another expression
+///
+/// `^` is right-associative, so `2 ^ 3 ^ 2` is parsed as `2 ^ (3 ^ 2)`.  It binds tighter
+/// than a unary operator on its left, so `-2 ^ 2` is `-(2 ^ 2)`, but the exponent can
+/// start with one, so `2 ^ -x ^ 2` is `2 ^ -(x ^ 2)`.
+/// # Example:
+/// ```rust
+/// # use lualite::parser::expression::power_expression;
+/// # use lualite::ast::{Expression, BinaryOperator, IntegerLiteral};
+/// let (_, parsed_ast) = power_expression("2 ^ 3 ^ 2").unwrap();
+/// let int = |i| Box::new(Expression::Integer(IntegerLiteral(i)));
+/// let expected_ast = Expression::Binary {
+///   left: int(2),
+///   op: BinaryOperator::Pow,
+///   right: Box::new(Expression::Binary { left: int(3), op: BinaryOperator::Pow, right: int(2) }),
+/// };
+/// assert_eq!(parsed_ast, expected_ast);
+/// ```
 pub fn power_expression(s: &str) -> IResult<&str, Expression> {
   map(
-    pair(postfix_expression, opt(pair(operator::power, leaf_expression))),
+    pair(postfix_expression, opt(pair(operator::power, unary_expression))),
     |(base, maybe_exponent)| {
       match maybe_exponent {
         Some((op, exponent)) => Expression::Binary {
@@ -181,10 +339,13 @@ pub fn unary_expression(s: &str) -> IResult<&str, Expression> {
   )(s)
 }
 
-/// Multiplicative binary operator expressions (*, /, %)
+/// Multiplicative binary operator expressions (*, /, //, %)
+///
+/// Every operand is a [`unary_expression`], so `^` binds tighter than these operators on
+/// either side: `2 * 3 ^ 2` is `2 * (3 ^ 2)` and `2 ^ 2 * 3` is `(2 ^ 2) * 3`.
 pub fn multiplicative_expression(s: &str) -> IResult<&str, Expression> {
   map(
-    pair(power_expression, many0(pair(operator::multiplicative, unary_expression))),
+    pair(unary_expression, many0(pair(operator::multiplicative, unary_expression))),
     |(first, remaining)| {
       let mut expr = first;
       for (op, right) in remaining {
@@ -234,3 +395,76 @@ pub fn comparison_expression(s: &str) -> IResult<&str, Expression> {
   )(s)
 }
 
+
+/// Logical and expressions
+pub fn and_expression(s: &str) -> IResult<&str, Expression> {
+  map(
+    pair(comparison_expression, many0(pair(operator::logical_and, comparison_expression))),
+    |(first, remaining)| {
+      let mut expr = first;
+      for (op, right) in remaining {
+        expr = Expression::Binary {
+          left: Box::new(expr),
+          op,
+          right: Box::new(right),
+        };
+      }
+      expr
+    }
+  )(s)
+}
+
+/// Logical or expressions (lowest precedence)
+pub fn or_expression(s: &str) -> IResult<&str, Expression> {
+  map(
+    pair(and_expression, many0(pair(operator::logical_or, and_expression))),
+    |(first, remaining)| {
+      let mut expr = first;
+      for (op, right) in remaining {
+        expr = Expression::Binary {
+          left: Box::new(expr),
+          op,
+          right: Box::new(right),
+        };
+      }
+      expr
+    }
+  )(s)
+}
+
+/// Conditional expression, which has the lowest precedence
+///
+/// Conditionals are right-associative, so `a ? b : c ? d : e` is parsed as
+/// `a ? b : (c ? d : e)`.
+/// # Example:
+/// ```rust
+/// # use lualite::parser::expression::conditional_expression;
+/// # use lualite::ast::{Expression, BinaryOperator, Identifier};
+/// let (_, parsed_ast) = conditional_expression("a or b ? a : b").unwrap();
+/// let ident = |name: &str| Box::new(Expression::Identifier(Identifier(name.to_owned())));
+/// let expected_ast = Expression::Conditional {
+///   condition: Box::new(Expression::Binary { left: ident("a"), op: BinaryOperator::Or, right: ident("b") }),
+///   then: ident("a"),
+///   else_: ident("b"),
+/// };
+/// assert_eq!(parsed_ast, expected_ast);
+/// ```
+pub fn conditional_expression(s: &str) -> IResult<&str, Expression> {
+  map(
+    pair(
+      or_expression,
+      opt(pair(
+        preceded(delimited(space0, tag("?"), whitespace), expression),
+        preceded(delimited(space0, tag(":"), whitespace), conditional_expression),
+      )),
+    ),
+    |(condition, branches)| match branches {
+      Some((then, else_)) => Expression::Conditional {
+        condition: Box::new(condition),
+        then: Box::new(then),
+        else_: Box::new(else_),
+      },
+      None => condition,
+    }
+  )(s)
+}
@@ -5,30 +5,260 @@ pub mod statement;
 pub mod expression;
 pub mod operator;
 pub mod atomic;
+mod error;
+
+pub use error::{ParseError, ParseErrorKind, NomError};
 
 use nom::{
-  IResult,
-  branch::alt,
-  bytes::complete::{tag, take_until},
-  combinator::map,
-  multi::many0,
+  bytes::complete::{tag, take_till},
   sequence::{preceded, terminated},
 };
-use crate::ast::Declaration;
+use alloc::{format, string::String, vec::Vec};
+use crate::ast::{Declaration, Expression, Span};
 use self::declaration::declaration;
+use self::atomic::{IdentifierMode, keyword};
+
+/// Result of the nom parsers in this crate, with a [`NomError`] on failure
+pub type IResult<I, O> = nom::IResult<I, O, NomError<I>>;
 
 /// Parses line comments prefixed with `#`
+///
+/// A comment ends at a newline or at the end of the input.
 pub fn comment(s: &str) -> IResult<&str, &str> {
-  preceded(tag("#"), take_until("\n"))(s)
+  preceded(tag("#"), take_till(|c| c == '\n'))(s)
 }
 
 /// Parses spaces, newlines, and comments allowed between statements and declarations
+///
+/// Runs of whitespace and comments are skipped in a single pass, since this is called
+/// between nearly every token of a file.
 pub fn whitespace(s: &str) -> IResult<&str, &str> {
-  map(many0(alt((tag(" "), tag("\t"), tag("\n"), comment))), |_| "")(s)
+  let mut rest = s;
+  loop {
+    rest = rest.trim_start_matches([' ', '\t', '\n']);
+    match rest.strip_prefix('#') {
+      Some(comment) => rest = &comment[comment.find('\n').unwrap_or(comment.len())..],
+      None => return Ok((rest, "")),
+    }
+  }
 }
 
 /// Parses the contents of a file into a list of top-level `Declaration`s
 pub fn parse_file(s: &str) -> IResult<&str, Vec<Declaration>> {
-  terminated(many0(declaration), whitespace)(s)
+  let mut cursor = Cursor::default();
+  let mut declarations = Vec::new();
+  let mut rest = s;
+  loop {
+    match located_declaration(s, rest, &mut cursor) {
+      Ok((remaining, parsed)) => {
+        declarations.push(parsed);
+        rest = remaining;
+      }
+      Err(nom::Err::Error(_)) => break,
+      Err(error) => return Err(error),
+    }
+  }
+  let (rest, _) = whitespace(rest)?;
+  Ok((rest, declarations))
+}
+
+/// Parses a declaration like [`declaration`], filling in its [`Span`] in `source`
+///
+/// `input` has to be a suffix of `source` that isn't before `cursor`.
+fn located_declaration<'a>(source: &str, input: &'a str, cursor: &mut Cursor) -> IResult<&'a str, Declaration> {
+  let (input, _) = whitespace(input)?;
+  let (rest, mut parsed) = declaration(input)?;
+  let span = cursor.advance(source, source.len() - input.len());
+  match &mut parsed {
+    Declaration::Function(function) => function.span = span,
+    Declaration::Static(static_decl) => static_decl.span = span,
+  }
+  Ok((rest, parsed))
+}
+
+/// Line and column of a byte offset into a source, which only moves forward
+///
+/// Advancing only scans the text since the previous offset, so finding the span of every
+/// declaration takes a single pass over the file.
+struct Cursor {
+  offset: usize,
+  line: usize,
+  column: usize,
+}
+
+impl Default for Cursor {
+  fn default() -> Self {
+    Self { offset: 0, line: 1, column: 1 }
+  }
+}
+
+impl Cursor {
+  /// Moves to the byte `offset` into `source`, returning the span there
+  fn advance(&mut self, source: &str, offset: usize) -> Span {
+    let skipped = &source[self.offset..offset];
+    match skipped.rfind('\n') {
+      Some(newline) => {
+        self.line += skipped.matches('\n').count();
+        self.column = skipped[newline + 1..].chars().count() + 1;
+      }
+      None => self.column += skipped.chars().count(),
+    }
+    self.offset = offset;
+    Span { line: self.line as u32, column: self.column as u32 }
+  }
+}
+
+/// Parses a whole program like [`parse_program`], skipping past bad declarations instead of
+/// stopping at the first one
+///
+/// After an error, parsing picks up again at the next line that starts with `function` or
+/// `static`, so every good declaration is returned along with an error for each bad stretch
+/// of the file in between.
+/// # Example:
+/// ```rust
+/// # use lualite::parser::parse_file_recovering;
+/// let (declarations, errors) = parse_file_recovering("function f(\nstatic y = 2");
+/// assert_eq!(declarations.len(), 1);
+/// assert_eq!(errors[0].line, 1);
+/// ```
+pub fn parse_file_recovering(s: &str) -> (Vec<Declaration>, Vec<ParseError>) {
+  let mut declarations = Vec::new();
+  let mut errors = Vec::new();
+  let mut cursor = Cursor::default();
+  let mut rest = s;
+  loop {
+    rest = whitespace(rest).map_or(rest, |(rest, _)| rest);
+    if rest.is_empty() {
+      return (declarations, errors);
+    }
+    match located_declaration(s, rest, &mut cursor) {
+      Ok((remaining, parsed)) => {
+        declarations.push(parsed);
+        rest = remaining;
+      }
+      Err(error) => {
+        errors.push(ParseError::from_nom(s, error));
+        rest = skip_to_declaration(rest);
+      }
+    }
+  }
+}
+
+/// Skips past the first line of `s` to the next line that starts with a declaration keyword
+fn skip_to_declaration(s: &str) -> &str {
+  let mut rest = s;
+  while let Some(newline) = rest.find('\n') {
+    rest = &rest[newline + 1..];
+    if keyword("function")(rest).is_ok() || keyword("static")(rest).is_ok() {
+      return rest;
+    }
+  }
+  ""
+}
+
+/// Parses a whole program, every part of `s` has to be a declaration
+///
+/// Unlike [`parse_file`], the error is an owned [`ParseError`] with its position in `s`.
+/// # Example:
+/// ```rust
+/// # use lualite::parser::{parse_program, ParseErrorKind};
+/// assert_eq!(parse_program("function f() return 1 end").unwrap().len(), 1);
+///
+/// let error = parse_program("function f()\n  return 1\nend\n\nfunction (x)\nend").unwrap_err();
+/// assert_eq!((error.line, error.column), (5, 1));
+/// assert_eq!(error.kind, ParseErrorKind::UnexpectedInput);
+/// ```
+pub fn parse_program(s: &str) -> Result<Vec<Declaration>, ParseError> {
+  parse_program_with_mode(s, IdentifierMode::Unicode)
+}
+
+/// Parses a whole program like [`parse_program`], with identifiers limited by `mode`
+pub fn parse_program_with_mode(s: &str, mode: IdentifierMode) -> Result<Vec<Declaration>, ParseError> {
+  match parse_file_with_mode(s, mode) {
+    Ok(("", declarations)) => Ok(declarations),
+    Ok((remaining, _)) => Err(ParseError::at(s, remaining, ParseErrorKind::UnexpectedInput)),
+    Err(error) => Err(ParseError::from_nom(s, error)),
+  }
+}
+
+/// Parses a single expression, surrounding whitespace is allowed but nothing else
+///
+/// # Example:
+/// ```rust
+/// # use lualite::parser::parse_expression;
+/// assert!(parse_expression(" x + 1 ").is_ok());
+/// assert_eq!(parse_expression("x + ").unwrap_err().column, 3);
+/// ```
+pub fn parse_expression(s: &str) -> Result<Expression, ParseError> {
+  match terminated(preceded(whitespace, expression::expression), whitespace)(s) {
+    Ok(("", expression)) => Ok(expression),
+    Ok((remaining, _)) => Err(ParseError::at(s, remaining, ParseErrorKind::UnexpectedInput)),
+    Err(error) => Err(ParseError::from_nom(s, error)),
+  }
 }
 
+/// Parses a file like [`parse_file`], only allowing identifiers made of the characters
+/// allowed by `mode`
+///
+/// With [`IdentifierMode::Ascii`], any non-ASCII character outside of string literals and
+/// comments is a [`nom::Err::Failure`] with the input starting at that character.
+/// # Example:
+/// ```rust
+/// # use lualite::parser::{parse_file_with_mode, atomic::IdentifierMode};
+/// let source_code = "static café = \"☕\" # ☕";
+/// assert!(parse_file_with_mode(source_code, IdentifierMode::Unicode).is_ok());
+/// match parse_file_with_mode(source_code, IdentifierMode::Ascii) {
+///   Err(nom::Err::Failure(error)) => assert!(error.input.starts_with("é")),
+///   other => panic!("expected a failure, got {other:?}"),
+/// }
+/// ```
+pub fn parse_file_with_mode(s: &str, mode: IdentifierMode) -> IResult<&str, Vec<Declaration>> {
+  if mode == IdentifierMode::Ascii {
+    if let Some(offset) = first_non_ascii_outside_literals(s) {
+      return Err(nom::Err::Failure(NomError::new(&s[offset..], ParseErrorKind::NonAsciiCharacter)));
+    }
+  }
+  parse_file(s)
+}
+
+fn first_non_ascii_outside_literals(s: &str) -> Option<usize> {
+  let mut in_string = false;
+  let mut in_comment = false;
+  for (offset, c) in s.char_indices() {
+    match c {
+      // neither string literals nor comments continue past the end of a line
+      '\n' => {
+        in_string = false;
+        in_comment = false;
+      }
+      '"' if !in_comment => in_string = !in_string,
+      '#' if !in_string => in_comment = true,
+      c if !c.is_ascii() && !in_string && !in_comment => return Some(offset),
+      _ => (),
+    }
+  }
+  None
+}
+
+/// Source code of `functions` functions, each with comments and blank lines between its
+/// statements
+///
+/// Shared by the parser tests and benchmarks, which need a large program to parse.
+#[doc(hidden)]
+pub fn generated_program(functions: usize) -> String {
+  (0..functions).map(|i| format!(r"
+# function number {i}
+function f{i}(a, b)
+  # loop until they're equal
+  while a != b do
+    if a > b then
+      a = a - b   # shrink a
+    else
+      b = b - a
+    end
+  end
+
+  return a
+end
+")).collect()
+}
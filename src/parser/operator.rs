@@ -1,20 +1,37 @@
 //! Operator Symbols grouped by precedence
+//!
+//! An operator has to be on the same line as the expression before it, but can be
+//! followed by newlines and comments:
+//! ```rust
+//! # use lualite::parser::expression::expression;
+//! let (_, split) = expression("x + # note\n  y").unwrap();
+//! let (_, joined) = expression("x + y").unwrap();
+//! assert_eq!(split, joined);
+//! ```
 
 use nom::{
-  IResult,
   branch::alt,
   bytes::complete::tag,
   character::complete::space0,
   combinator::map,
   sequence::delimited,
 };
+use super::IResult;
 use crate::ast::{UnaryOperator, BinaryOperator};
+use super::atomic::keyword;
+use super::whitespace;
 
+/// Prefix operators, which bind tighter than every binary operator except `^`
+///
+/// `not a and b` is `(not a) and b` and `not a == b` is `(not a) == b`.
 pub fn unary(s: &str) -> IResult<&str, UnaryOperator> {
   delimited(
     space0,
-    map(tag("-"), |_| UnaryOperator::Neg),
-    space0,
+    alt((
+      map(tag("-"), |_| UnaryOperator::Neg),
+      map(keyword("not"), |_| UnaryOperator::Not),
+    )),
+    whitespace,
   )(s)
 }
 
@@ -22,7 +39,7 @@ pub fn power(s: &str) -> IResult<&str, BinaryOperator> {
   delimited(
     space0,
     map(tag("^"), |_| BinaryOperator::Pow),
-    space0,
+    whitespace,
   )(s)
 }
 
@@ -31,10 +48,11 @@ pub fn multiplicative(s: &str) -> IResult<&str, BinaryOperator> {
     space0,
     alt((
       map(tag("*"), |_| BinaryOperator::Mul),
+      map(tag("//"), |_| BinaryOperator::FloorDiv),
       map(tag("/"), |_| BinaryOperator::Div),
       map(tag("%"), |_| BinaryOperator::Rem),
     )),
-    space0,
+    whitespace,
   )(s)
 }
 
@@ -45,7 +63,7 @@ pub fn additive(s: &str) -> IResult<&str, BinaryOperator> {
       map(tag("+"), |_| BinaryOperator::Add),
       map(tag("-"), |_| BinaryOperator::Sub),
     )),
-    space0,
+    whitespace,
   )(s)
 }
 
@@ -60,7 +78,23 @@ pub fn comparison(s: &str) -> IResult<&str, BinaryOperator> {
       map(tag("<"), |_| BinaryOperator::Lt),
       map(tag(">"), |_| BinaryOperator::Gt),
     )),
+    whitespace,
+  )(s)
+}
+
+
+pub fn logical_and(s: &str) -> IResult<&str, BinaryOperator> {
+  delimited(
     space0,
+    map(keyword("and"), |_| BinaryOperator::And),
+    whitespace,
   )(s)
 }
 
+pub fn logical_or(s: &str) -> IResult<&str, BinaryOperator> {
+  delimited(
+    space0,
+    map(keyword("or"), |_| BinaryOperator::Or),
+    whitespace,
+  )(s)
+}
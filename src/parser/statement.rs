@@ -43,17 +43,19 @@
 //! ```
 
 use nom::{
-  IResult,
   branch::alt,
   bytes::complete::tag,
-  character::complete::space0,
+  character::complete::{space0, space1},
   combinator::{map, opt},
-  multi::many0,
+  multi::{many0, separated_list1},
   sequence::{delimited, preceded, tuple},
 };
+use super::IResult;
+use alloc::{vec, vec::Vec};
 use crate::ast::{Statement, Expression};
 use super::atomic::{identifier, keyword};
 use super::expression::expression;
+use super::declaration::function_decl;
 use super::whitespace;
 
 /// Body of a loop, function, etc.
@@ -68,7 +70,12 @@ pub fn statement(s: &str) -> IResult<&str, Statement> {
     alt((
       if_statement,
       while_statement,
+      for_statement,
+      map(function_decl, Statement::FunctionStatement),
       return_statement,
+      map(keyword("break"), |_| Statement::BreakStatement),
+      map(keyword("continue"), |_| Statement::ContinueStatement),
+      local_statement,
       index_assign_statement,
       assign_statement,
       single_statement,
@@ -76,9 +83,23 @@ pub fn statement(s: &str) -> IResult<&str, Statement> {
   )(s)
 }
 
-/// An expression treated as a single statement
+/// An expression treated as a single statement, or several separated by commas
+/// # Example:
+/// ```rust
+/// # use lualite::parser::{statement::single_statement, expression::expression};
+/// # use lualite::ast::Statement;
+/// let (_, parsed_ast) = single_statement("f(a), g(b)").unwrap();
+/// let expected_ast = Statement::ExpressionList(vec![expression("f(a)").unwrap().1, expression("g(b)").unwrap().1]);
+/// assert_eq!(parsed_ast, expected_ast);
+/// ```
 pub fn single_statement(s: &str) -> IResult<&str, Statement> {
-  map(expression, |expr| Statement::SingleStatement(expr))(s)
+  map(
+    separated_list1(delimited(space0, tag(","), space0), expression),
+    |mut expressions| match expressions.len() {
+      1 => Statement::SingleStatement(expressions.remove(0)),
+      _ => Statement::ExpressionList(expressions),
+    },
+  )(s)
 }
 
 /// Assignment from an expression to an identifier
@@ -95,6 +116,29 @@ pub fn assign_statement(s: &str) -> IResult<&str, Statement> {
   )(s)
 }
 
+/// Declaration of a block-scoped local
+/// # Example:
+/// ```rust
+/// # use lualite::parser::statement::local_statement;
+/// # use lualite::ast::{Statement, Expression, Identifier, IntegerLiteral};
+/// let (_, parsed_ast) = local_statement("local x = 1").unwrap();
+/// let expected_ast = Statement::LocalStatement(Identifier("x".to_owned()), Expression::Integer(IntegerLiteral(1)));
+/// assert_eq!(parsed_ast, expected_ast);
+/// ```
+pub fn local_statement(s: &str) -> IResult<&str, Statement> {
+  map(
+    tuple((
+      keyword("local"),
+      preceded(space1, identifier),
+      delimited(space0, tag("="), space0),
+      expression,
+    )),
+    |(_local, identifier, _equals, value)| {
+      Statement::LocalStatement(identifier, value)
+    },
+  )(s)
+}
+
 /// Assignment from an expression into an indexed container
 pub fn index_assign_statement(s: &str) -> IResult<&str, Statement> {
   map(
@@ -143,6 +187,39 @@ pub fn while_statement(s: &str) -> IResult<&str, Statement> {
   )(s)
 }
 
+/// For loop over a range of integers
+/// # Example:
+/// ```rust
+/// # use lualite::parser::statement::for_statement;
+/// # use lualite::ast::{Statement, Expression, Identifier, IntegerLiteral};
+/// let (_, parsed_ast) = for_statement("for i in 0..3 do end").unwrap();
+/// let expected_ast = Statement::ForStatement {
+///   variable: Identifier("i".to_owned()),
+///   start: Expression::Integer(IntegerLiteral(0)),
+///   end: Expression::Integer(IntegerLiteral(3)),
+///   body: vec![],
+/// };
+/// assert_eq!(parsed_ast, expected_ast);
+/// ```
+pub fn for_statement(s: &str) -> IResult<&str, Statement> {
+  map(
+    tuple((
+      keyword("for"),
+      delimited(space0, identifier, space0),
+      keyword("in"),
+      delimited(space0, expression, space0),
+      tag(".."),
+      delimited(space0, expression, space0),
+      keyword("do"),
+      body,
+      preceded(whitespace, keyword("end")),
+    )),
+    |(_for, variable, _in, start, _range, end, _do, body, _end)| {
+      Statement::ForStatement { variable, start, end, body }
+    }
+  )(s)
+}
+
 /// If statement with optional else clause and elseif clauses
 pub fn if_statement(s: &str) -> IResult<&str, Statement> {
   map(
@@ -155,11 +232,7 @@ pub fn if_statement(s: &str) -> IResult<&str, Statement> {
       ),
       keyword("then"),
       body,
-      alt((
-        else_if_clause,
-        else_clause,
-        map(preceded(whitespace, keyword("end")), |_end| None),
-      )),
+      else_body,
     )),
     |(_if, condition, _then, body, else_body)| {
       Statement::IfStatement { condition, body, else_body }
@@ -167,6 +240,18 @@ pub fn if_statement(s: &str) -> IResult<&str, Statement> {
   )(s)
 }
 
+/// Everything after the body of an `if` or `elseif`, up to and including `end`
+///
+/// An `elseif` becomes an else body holding a single nested if statement, so a trailing
+/// `else` always belongs to the last `elseif` in the chain.
+fn else_body(s: &str) -> IResult<&str, Option<Vec<Statement>>> {
+  alt((
+    else_if_clause,
+    else_clause,
+    map(preceded(whitespace, keyword("end")), |_end| None),
+  ))(s)
+}
+
 fn else_if_clause(s: &str) -> IResult<&str, Option<Vec<Statement>>> {
   map(
     tuple((
@@ -178,11 +263,7 @@ fn else_if_clause(s: &str) -> IResult<&str, Option<Vec<Statement>>> {
       ),
       keyword("then"),
       body,
-      alt((
-        else_if_clause,
-        else_clause,
-        map(preceded(whitespace, keyword("end")), |_end| None),
-      )),
+      else_body,
     )),
     |(_elseif, condition, _then, body, else_body)| {
       Some(vec![Statement::IfStatement { condition, body, else_body }])
@@ -0,0 +1,59 @@
+//! Parsing, compiling, and loading a whole program in one step
+
+use crate::{parser, compiler};
+use crate::parser::ParseError;
+use crate::compiler::CompileError;
+use crate::runtime::VirtualMachine;
+
+/// Error from any step of [`compile_program`]
+#[derive(Debug)]
+pub enum ProgramError {
+  Parse(ParseError),
+  Compile(CompileError),
+}
+
+impl From<ParseError> for ProgramError {
+  fn from(parse_error: ParseError) -> Self {
+    Self::Parse(parse_error)
+  }
+}
+
+impl From<CompileError> for ProgramError {
+  fn from(compile_error: CompileError) -> Self {
+    Self::Compile(compile_error)
+  }
+}
+
+/// Parses and compiles `source`, returning a virtual machine with every function and
+/// static of the program loaded
+///
+/// Calls to functions that are neither in the program nor native are a
+/// [`CompileError::UnresolvedFunction`].
+///
+/// # Example:
+/// ```rust
+/// # use lualite::{compile_program, ProgramError, runtime::Value};
+/// let mut vm = compile_program(r"
+/// static OFFSET = 10
+///
+/// function add_offset(x)
+///   return x + OFFSET
+/// end
+/// ").unwrap();
+/// assert_eq!(vm.run("add_offset", [Value::Integer(5)]).unwrap(), Value::Integer(15));
+///
+/// let error = compile_program("function broken(\n").unwrap_err();
+/// assert!(matches!(error, ProgramError::Parse(parse_error) if parse_error.line == 1));
+/// ```
+pub fn compile_program(source: &str) -> Result<VirtualMachine, ProgramError> {
+  let declarations = parser::parse_program(source)?;
+  let statics = compiler::compile_statics(declarations.iter())?;
+  let functions = compiler::compile_declarations(declarations.iter())?;
+  let mut vm = VirtualMachine::new();
+  compiler::link(&functions, vm.native_names().into_iter().chain(VirtualMachine::BUILTIN_NAMES))?;
+  vm.set_globals(statics);
+  for (name, procedure) in functions {
+    vm.insert_function(name, procedure);
+  }
+  Ok(vm)
+}
@@ -0,0 +1,114 @@
+//! Integer arithmetic that promotes to arbitrary precision instead of overflowing
+//!
+//! Only compiled with the `bigint` feature.  Results that fit in an `i64` are always
+//! [`Value::Integer`], so a [`Value::BigInt`] never equals an integer.
+
+use std::rc::Rc;
+use std::cmp::Ordering;
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive, Zero};
+use super::Value;
+use super::value::{integer_floor_div, integer_rem};
+
+impl From<BigInt> for Value {
+  fn from(integer: BigInt) -> Self {
+    match i64::try_from(&integer) {
+      Ok(small) => Value::Integer(small),
+      Err(_) => Value::BigInt(Rc::new(integer)),
+    }
+  }
+}
+
+fn into_big(value: Value) -> BigInt {
+  match value {
+    Value::Integer(integer) => integer.into(),
+    Value::BigInt(integer) => Rc::try_unwrap(integer).unwrap_or_else(|shared| (*shared).clone()),
+    _ => unreachable!("only integers are promoted"),
+  }
+}
+
+/// Tries `small` when both operands are `i64`s, falling back to `big` when it overflows
+///
+/// Both functions return `None` for results that are `nil` (like dividing by zero).
+fn promoting(
+  left: Value,
+  right: Value,
+  small: fn(i64, i64) -> Option<i64>,
+  big: fn(BigInt, BigInt) -> Option<BigInt>,
+) -> Value {
+  if let (Value::Integer(left), Value::Integer(right)) = (&left, &right) {
+    if let Some(result) = small(*left, *right) {
+      return Value::Integer(result);
+    }
+  }
+  big(into_big(left), into_big(right)).map_or(Value::Nil, Value::from)
+}
+
+pub(super) fn add(left: Value, right: Value) -> Value {
+  promoting(left, right, i64::checked_add, |left, right| Some(left + right))
+}
+
+pub(super) fn sub(left: Value, right: Value) -> Value {
+  promoting(left, right, i64::checked_sub, |left, right| Some(left - right))
+}
+
+pub(super) fn mul(left: Value, right: Value) -> Value {
+  promoting(left, right, i64::checked_mul, |left, right| Some(left * right))
+}
+
+pub(super) fn floor_div(left: Value, right: Value) -> Value {
+  promoting(left, right, integer_floor_div, |left, right| {
+    if right.is_zero() {
+      return None;
+    }
+    let quotient = &left / &right;
+    let remainder = left % &right;
+    if !remainder.is_zero() && remainder.is_negative() != right.is_negative() {
+      Some(quotient - 1)
+    } else {
+      Some(quotient)
+    }
+  })
+}
+
+pub(super) fn rem(left: Value, right: Value) -> Value {
+  promoting(left, right, integer_rem, |left, right| {
+    if right.is_zero() {
+      return None;
+    }
+    let remainder = left % &right;
+    if !remainder.is_zero() && remainder.is_negative() != right.is_negative() {
+      Some(remainder + right)
+    } else {
+      Some(remainder)
+    }
+  })
+}
+
+pub(super) fn neg(value: Value) -> Value {
+  match value {
+    Value::Integer(integer) => integer.checked_neg().map_or_else(|| Value::from(-BigInt::from(integer)), Value::Integer),
+    other => Value::from(-into_big(other)),
+  }
+}
+
+/// Exact power of an integer base, or `None` for exponents that aren't a `u32`
+pub(super) fn pow(base: &Value, exponent: &Value) -> Option<Value> {
+  let exponent = match exponent {
+    Value::Integer(exponent) => u32::try_from(*exponent).ok()?,
+    _ => return None,
+  };
+  match base {
+    Value::Integer(base) => Some(base.checked_pow(exponent).map_or_else(|| Value::from(BigInt::from(*base).pow(exponent)), Value::Integer)),
+    Value::BigInt(base) => Some(Value::from(base.pow(exponent))),
+    _ => None,
+  }
+}
+
+pub(super) fn cmp(left: &Value, right: &Value) -> Ordering {
+  into_big(left.clone()).cmp(&into_big(right.clone()))
+}
+
+pub(super) fn to_f64(integer: &BigInt) -> f64 {
+  integer.to_f64().unwrap_or(f64::NAN)
+}
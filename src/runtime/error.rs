@@ -2,8 +2,74 @@
 #[derive(Debug)]
 pub enum RuntimeError {
   InvalidRegister,
+  InvalidGlobal,
   InvalidPc,
   EmptyCallStack,
   MissingFunction,
   MissingConstant,
+  /// An instruction with an opcode reserved for instructions that don't exist yet
+  InvalidInstruction(crate::bytecode::Instruction),
+  /// Entry procedure was given more arguments than it has parameters
+  TooManyArguments {
+    max_args: usize,
+    given: usize,
+  },
+  /// Indexed a value that isn't an array or a string
+  NotIndexable {
+    type_name: &'static str,
+  },
+  /// Called a value that isn't a function with `call`
+  NotCallable {
+    type_name: &'static str,
+  },
+  /// Ordered two values that can't be compared, such as an integer and a string
+  IncomparableTypes {
+    left: &'static str,
+    right: &'static str,
+  },
+  /// Used a value that can't be a map key, such as a float or an array, as one
+  UnhashableKey {
+    type_name: &'static str,
+  },
+  /// Converted a value into a Rust type that doesn't match its runtime type
+  WrongType {
+    expected: &'static str,
+    found: &'static str,
+  },
+  /// Stored a value in a byte buffer that isn't an integer from 0 to 255
+  InvalidByte,
+  /// [`run`](super::VirtualMachine::run) was called while an earlier call was still on the
+  /// call stack, for example after an unfinished
+  /// [`execution_loop`](super::VirtualMachine::execution_loop)
+  VmBusy,
+  /// Executing the next instruction would use more than the gas left, see
+  /// [`set_gas`](super::VirtualMachine::set_gas)
+  OutOfGas,
+  /// Writing to the [output](super::VirtualMachine::set_output) of `print` or `println`
+  /// failed
+  OutputFailed(std::io::ErrorKind),
+  /// An error raised by a native function, with whatever the host wants to report
+  Custom(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl RuntimeError {
+  /// Error for a native function to return when it fails for its own reasons
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::runtime::{RuntimeError, Value};
+  /// fn checked_sqrt(args: &[Value]) -> Result<Value, RuntimeError> {
+  ///   match args.first() {
+  ///     Some(&Value::Float(x)) if x >= 0.0 => Ok(Value::Float(x.sqrt())),
+  ///     _ => Err(RuntimeError::custom("sqrt needs a non-negative float")),
+  ///   }
+  /// }
+  /// match checked_sqrt(&[Value::Float(-1.0)]) {
+  ///   Err(RuntimeError::Custom(error)) => assert_eq!(error.to_string(), "sqrt needs a non-negative float"),
+  ///   other => panic!("expected a custom error, got {other:?}"),
+  /// }
+  /// ```
+  pub fn custom<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> Self {
+    RuntimeError::Custom(error.into())
+  }
 }
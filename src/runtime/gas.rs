@@ -0,0 +1,54 @@
+//! Instruction costs for limiting how much work a script can do
+
+use crate::bytecode::Instruction;
+use crate::bytecode::opcode::{SuperCode, misc};
+
+/// Gas charged for each category of instruction, see [`VirtualMachine::set_gas`]
+///
+/// Calls are part of the misc category but charged [`call`](Self::call) instead, since each
+/// one sets up a stack frame or runs a native function.
+///
+/// [`VirtualMachine::set_gas`]: super::VirtualMachine::set_gas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GasModel {
+  /// Jumps, moves, and returns
+  pub misc: u64,
+  pub call: u64,
+  pub index: u64,
+  pub comparison: u64,
+  pub arithmetic: u64,
+}
+
+impl Default for GasModel {
+  fn default() -> Self {
+    GasModel {
+      misc: 1,
+      call: 10,
+      index: 3,
+      comparison: 1,
+      arithmetic: 1,
+    }
+  }
+}
+
+impl GasModel {
+  /// Gas charged for executing `instruction`
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::{bytecode, runtime::GasModel};
+  /// let model = GasModel::default();
+  /// assert_eq!(model.cost(bytecode::ret()), model.misc);
+  /// ```
+  pub fn cost(&self, instruction: Instruction) -> u64 {
+    match SuperCode::from(instruction) {
+      SuperCode::Misc => match misc::Subcode::from(instruction) {
+        misc::Subcode::Call => self.call,
+        _ => self.misc,
+      },
+      SuperCode::Index => self.index,
+      SuperCode::Comparison => self.comparison,
+      SuperCode::Arithmetic => self.arithmetic,
+    }
+  }
+}
@@ -14,7 +14,7 @@
 //! # use lualite::{parser, compiler, runtime::{VirtualMachine, Value, InstructionCount}};
 //! let trisum_source_code = r"function trisum(a, b, c) return a + b + c end";
 //! let (_, trisum_fn_decl) = parser::declaration::function_decl(trisum_source_code).unwrap();
-//! let trisum_procedure = compiler::compile_function(&trisum_fn_decl);
+//! let trisum_procedure = compiler::compile_function(&trisum_fn_decl).unwrap();
 //!
 //! let mut vm = VirtualMachine::new();
 //!
@@ -26,11 +26,17 @@
 
 mod virtual_machine;
 mod value;
+#[cfg(feature = "bigint")]
+mod bigint;
 mod error;
+mod gas;
+pub mod native;
 
-pub use value::Value;
-pub use virtual_machine::VirtualMachine;
+pub use value::{Value, MapKey};
+pub use virtual_machine::{VirtualMachine, TraceHook};
 pub use error::RuntimeError;
+pub use gas::GasModel;
+pub use native::NativeFunction;
 
 #[derive(Debug)]
 pub enum InstructionCount {
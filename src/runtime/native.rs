@@ -0,0 +1,246 @@
+//! Native functions callable from bytecode
+//!
+//! A native function is a plain Rust function that takes its arguments as a slice of
+//! [`Value`]s, along with a [`Context`] for the parts of the virtual machine it can use.  Calls to natives use the same call instruction as compiled functions
+//! and are looked up by name when no compiled function with that name exists.
+//!
+//! Every [`VirtualMachine`](super::VirtualMachine) starts with the natives from
+//! [`standard`] attached.
+
+use std::cmp::Ordering;
+use std::io::{self, Write};
+use crate::bytecode::builtin;
+use super::{Value, RuntimeError};
+
+/// Signature of a native function
+pub type NativeFunction = fn(&mut Context, &[Value]) -> Result<Value, RuntimeError>;
+
+/// The parts of the virtual machine calling a native that the native can use
+pub struct Context<'a> {
+  output: &'a mut dyn Write,
+}
+
+impl<'a> Context<'a> {
+  pub(super) fn new(output: &'a mut dyn Write) -> Self {
+    Self { output }
+  }
+
+  /// Where `print` and `println` write, see
+  /// [`set_output`](super::VirtualMachine::set_output)
+  pub fn output(&mut self) -> &mut dyn Write {
+    self.output
+  }
+}
+
+/// Natives attached to every virtual machine by default
+pub fn standard() -> impl Iterator<Item=(&'static str, NativeFunction)> {
+  [
+    ("print", print as NativeFunction),
+    ("println", println),
+    ("type", type_of),
+    ("chars", chars),
+    ("slice", slice),
+    (builtin::SLICE, slice),
+    ("bytes", bytes),
+    ("get_byte", get_byte),
+    ("set_byte", set_byte),
+    ("sort", sort),
+    ("contains", contains),
+    ("index_of", index_of),
+    ("min", min),
+    ("max", max),
+    ("abs", abs),
+    ("map", map),
+    ("tonumber", tonumber),
+    ("tostring", tostring),
+  ].into_iter()
+}
+
+/// `print(...)` writes its arguments to the output separated by tabs, strings without
+/// their quotes
+pub fn print(context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  write_args(context.output(), args).map_err(|error| RuntimeError::OutputFailed(error.kind()))?;
+  Ok(Value::Nil)
+}
+
+/// `println(...)` is the same as `print(...)` followed by a newline
+pub fn println(context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  let output = context.output();
+  write_args(output, args).and_then(|()| output.write_all(b"\n"))
+    .map_err(|error| RuntimeError::OutputFailed(error.kind()))?;
+  Ok(Value::Nil)
+}
+
+fn write_args(output: &mut dyn Write, args: &[Value]) -> io::Result<()> {
+  for (i, arg) in args.iter().enumerate() {
+    if i > 0 {
+      output.write_all(b"\t")?;
+    }
+    match arg.as_str() {
+      Some(string) => output.write_all(string.as_bytes())?,
+      None => write!(output, "{arg}")?,
+    }
+  }
+  Ok(())
+}
+
+/// `type(x)` returns the name of the type of `x` as a string
+pub fn type_of(_context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  let value = args.first().unwrap_or(&Value::Nil);
+  Ok(Value::from(value.type_name()))
+}
+
+/// `chars(s)` returns an array of the single character strings in `s`
+///
+/// Arguments that aren't strings return `nil`.
+pub fn chars(_context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  match args.first() {
+    Some(string @ (Value::ShortStr { .. } | Value::LongStr(_))) => Ok(Value::from_iter(string.chars())),
+    _ => Ok(Value::Nil),
+  }
+}
+
+/// `slice(a, i, j)` returns a new array of the elements of `a` from `i` up to but not
+/// including `j`, see [`Value::slice`]
+///
+/// Slice expressions like `a[i:j]` compile to calls to this native under the name
+/// [`builtin::SLICE`], so they still slice when a script declares its own `slice`.
+pub fn slice(_context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  let arg = |index: usize| args.get(index).cloned().unwrap_or_default();
+  arg(0).slice(arg(1), arg(2))
+}
+
+/// `bytes(n)` returns a byte buffer of `n` zeroes
+///
+/// A size that isn't a non-negative integer, or that's too large to allocate, returns `nil`.
+pub fn bytes(_context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  match args.first() {
+    Some(&Value::Integer(len)) => Ok(usize::try_from(len).map(Value::bytes).unwrap_or_default()),
+    _ => Ok(Value::Nil),
+  }
+}
+
+/// `get_byte(b, i)` returns the byte at `i` in `b` as an integer, the same as `b[i]`
+pub fn get_byte(_context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  let arg = |index: usize| args.get(index).cloned().unwrap_or_default();
+  arg(0).get(arg(1))
+}
+
+/// `set_byte(b, i, v)` sets the byte at `i` in `b` to `v`, the same as `b[i] = v`
+pub fn set_byte(_context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  let arg = |index: usize| args.get(index).cloned().unwrap_or_default();
+  arg(0).set(arg(1), arg(2))?;
+  Ok(Value::Nil)
+}
+
+/// `sort(a)` sorts the array `a` in place from smallest to largest
+///
+/// Elements are compared the same way as `<`.  Sorting an array with elements that can't
+/// be compared with each other, such as an integer and a string, or with a float that's NaN
+/// is a [`RuntimeError::IncomparableTypes`] and leaves the array unchanged.  Arguments that
+/// aren't arrays are ignored.
+pub fn sort(_context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  let array = match args.first() {
+    Some(Value::Array(array)) => array,
+    _ => return Ok(Value::Nil),
+  };
+  let mut sorted = array.borrow().clone();
+  // every element has to compare with the first and with itself (which rules out NaN), so
+  // the comparisons below are a total order
+  if let Some(first) = sorted.first() {
+    let incomparable = sorted.iter()
+      .find(|element| element.partial_cmp(element).is_none() || element.partial_cmp(&first).is_none());
+    if let Some(element) = incomparable {
+      return Err(RuntimeError::IncomparableTypes { left: first.type_name(), right: element.type_name() });
+    }
+  }
+  sorted.sort_by(|left, right| left.partial_cmp(right).unwrap_or(Ordering::Equal));
+  *array.borrow_mut() = sorted;
+  Ok(Value::Nil)
+}
+
+/// `contains(c, v)` returns whether the array `c` has an element equal to `v`, or whether
+/// the string `c` has the string `v` in it
+///
+/// Anything else as `c` returns `nil`.
+pub fn contains(_context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  Ok(position(args).map(|index| Value::from(index.is_some())).unwrap_or_default())
+}
+
+/// `index_of(c, v)` returns the index of the first element of the array `c` equal to `v`, or
+/// the character index of the first place the string `v` appears in the string `c`
+///
+/// Returns `-1` when `v` isn't found, and `nil` for anything else as `c`.
+pub fn index_of(_context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  let index = |index: Option<usize>| index.map_or(Value::Integer(-1), |index| Value::try_from(index).unwrap_or_default());
+  Ok(position(args).map(index).unwrap_or_default())
+}
+
+/// Where the second argument is in the first, or `None` if the first can't be searched
+fn position(args: &[Value]) -> Option<Option<usize>> {
+  let needle = args.get(1).unwrap_or(&Value::Nil);
+  match args.first()? {
+    Value::Array(array) => Some(array.borrow().iter().position(|element| element == needle)),
+    string => {
+      let string = string.as_str()?;
+      let found = needle.as_str().and_then(|needle| string.find(needle));
+      Some(found.map(|byte_index| string[..byte_index].chars().count()))
+    }
+  }
+}
+
+/// `min(a, b)` returns the smaller of the numbers `a` and `b`
+///
+/// An integer and a float are compared by converting the integer to a float, but the
+/// result is the argument itself, so `min(2, 2.5)` is the integer `2`.  Returns `a` when
+/// they're equal, and `nil` when either isn't a number (or is NaN).
+pub fn min(_context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  Ok(pick_number(args, Ordering::Less))
+}
+
+/// `max(a, b)` returns the larger of the numbers `a` and `b`, see [`min`]
+pub fn max(_context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  Ok(pick_number(args, Ordering::Greater))
+}
+
+/// `abs(x)` returns the absolute value of the number `x`, or `nil` for anything else
+pub fn abs(_context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  match args.first() {
+    Some(Value::Float(float)) => Ok(Value::Float(float.abs())),
+    Some(int) if int.is_integer() && *int < Value::Integer(0) => Ok(-int.clone()),
+    Some(int) if int.is_integer() => Ok(int.clone()),
+    _ => Ok(Value::Nil),
+  }
+}
+
+/// The second argument if it compares as `wanted` to the first, otherwise the first
+fn pick_number(args: &[Value], wanted: Ordering) -> Value {
+  let (first, second) = match args {
+    [first, second, ..] if first.is_number() && second.is_number() => (first, second),
+    _ => return Value::Nil,
+  };
+  let ordering = second.partial_cmp(first)
+    .or_else(|| second.as_f64()?.partial_cmp(&first.as_f64()?));
+  match ordering {
+    Some(ordering) if ordering == wanted => second.clone(),
+    Some(_) => first.clone(),
+    None => Value::Nil,
+  }
+}
+
+/// `map()` returns a new empty map, see [`Value::hash_key`] for what can be a key
+pub fn map(_context: &mut Context, _args: &[Value]) -> Result<Value, RuntimeError> {
+  Ok(Value::map())
+}
+
+/// `tonumber(x)` returns the number `x` is or holds, or `nil` if it isn't one, see
+/// [`Value::to_number`]
+pub fn tonumber(_context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  Ok(args.first().and_then(Value::to_number).unwrap_or_default())
+}
+
+/// `tostring(x)` returns `x` as a string, without adding quotes to a string, see
+/// [`Value::to_display_string`]
+pub fn tostring(_context: &mut Context, args: &[Value]) -> Result<Value, RuntimeError> {
+  Ok(Value::from(args.first().unwrap_or(&Value::Nil).to_display_string()))
+}
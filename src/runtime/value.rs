@@ -1,24 +1,63 @@
 
-use std::ops::{Add, Sub, Mul, Div, Rem};
+use std::ops::{Add, Sub, Mul, Div, Rem, Neg};
 use std::cmp::Ordering;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
+use super::RuntimeError;
+#[cfg(feature = "bigint")]
+use super::bigint;
 
 //pub static NIL: Value = Value::Nil;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum Value {
   Nil,
   Boolean(bool),
   Integer(i64),
+  /// Integer that doesn't fit in an `i64`, only created by the `bigint` feature
+  #[cfg(feature = "bigint")]
+  BigInt(Rc<num_bigint::BigInt>),
   Float(f64),
   ShortStr {
-    buf: [u8; 14],
+    buf: [u8; SHORT_STR_CAPACITY],
     len: u8,
   },
   LongStr(Rc<String>),
   Array(Rc<RefCell<Vec<Value>>>),
+  /// Raw byte buffer, indexing it gives each byte as an integer
+  Bytes(Rc<RefCell<Vec<u8>>>),
+  /// Map from booleans, integers or strings to values, see [`Value::hash_key`]
+  Map(Rc<RefCell<HashMap<MapKey, Value>>>),
+  /// Function referred to by name, called with the `call` builtin
+  Function(Rc<str>),
+}
+
+/// Key of a [`Value::Map`]
+///
+/// Only values with an exact notion of equality can be keys.  Floats can't, since `0.1 + 0.2`
+/// would be a different key than `0.3`, and neither can arrays, whose contents can change
+/// after they're used as a key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MapKey {
+  Boolean(bool),
+  Integer(i64),
+  #[cfg(feature = "bigint")]
+  BigInt(Rc<num_bigint::BigInt>),
+  String(Rc<str>),
+}
+
+impl From<MapKey> for Value {
+  fn from(key: MapKey) -> Self {
+    match key {
+      MapKey::Boolean(boolean) => Value::Boolean(boolean),
+      MapKey::Integer(integer) => Value::Integer(integer),
+      #[cfg(feature = "bigint")]
+      MapKey::BigInt(integer) => Value::BigInt(integer),
+      MapKey::String(string) => Value::from(&*string),
+    }
+  }
 }
 
 impl Default for Value {
@@ -108,36 +147,123 @@ impl From<f32> for Value {
   }
 }
 
+/// Byte capacity of [`Value::ShortStr`]
+///
+/// Has to fit in its `u8` length and be able to hold any `char`.
+const SHORT_STR_CAPACITY: usize = 14;
+const _: () = assert!(SHORT_STR_CAPACITY <= u8::MAX as usize && SHORT_STR_CAPACITY >= 4);
+
+impl Value {
+  /// Stores `string` inline if all of its bytes fit
+  ///
+  /// The whole string is copied or none of it is, so a multibyte character is never
+  /// split at the end of the inline buffer.
+  fn short_str(string: &str) -> Option<Self> {
+    if string.len() > SHORT_STR_CAPACITY {
+      return None;
+    }
+    debug_assert!(u8::try_from(string.len()).is_ok(), "inline string length {} overflows u8", string.len());
+    let mut buf = [0; SHORT_STR_CAPACITY];
+    buf[..string.len()].copy_from_slice(string.as_bytes());
+    Some(Value::ShortStr {
+      buf,
+      len: string.len() as u8,
+    })
+  }
+}
+
 impl From<&str> for Value {
   fn from(string: &str) -> Self {
-    if string.len() <= 14 {
-      let mut buf = [0; 14];
-      buf[..string.len()].copy_from_slice(string.as_bytes());
-      Value::ShortStr {
-        buf,
-        len: string.len() as u8,
-      }
-    } else {
-      Self::LongStr(Rc::new(string.to_owned()))
+    Self::short_str(string).unwrap_or_else(|| Self::LongStr(Rc::new(string.to_owned())))
+  }
+}
+
+impl From<char> for Value {
+  fn from(character: char) -> Self {
+    let mut buf = [0; SHORT_STR_CAPACITY];
+    let len = character.encode_utf8(&mut buf).len();
+    Value::ShortStr {
+      buf,
+      len: len as u8,
     }
   }
 }
 
 impl From<String> for Value {
   fn from(string: String) -> Self {
-    if string.len() <= 14 {
-      let mut buf = [0; 14];
-      buf[..string.len()].copy_from_slice(string.as_bytes());
-      Value::ShortStr {
-        buf,
-        len: string.len() as u8,
-      }
-    } else {
-      Self::LongStr(Rc::new(string))
+    Self::short_str(&string).unwrap_or_else(|| Self::LongStr(Rc::new(string)))
+  }
+}
+
+impl From<&[u8]> for Value {
+  fn from(bytes: &[u8]) -> Self {
+    Value::Bytes(Rc::new(RefCell::new(bytes.to_vec())))
+  }
+}
+
+impl From<Vec<Value>> for Value {
+  fn from(array: Vec<Value>) -> Self {
+    Value::Array(Rc::new(RefCell::new(array)))
+  }
+}
+
+impl From<&[Value]> for Value {
+  fn from(array: &[Value]) -> Self {
+    array.to_vec().into()
+  }
+}
+
+impl<const N: usize> From<[Value; N]> for Value {
+  fn from(array: [Value; N]) -> Self {
+    Vec::from(array).into()
+  }
+}
+
+/// Extracts a boolean, erroring with [`RuntimeError::WrongType`] for any other type
+impl TryFrom<Value> for bool {
+  type Error = RuntimeError;
+  fn try_from(value: Value) -> Result<Self, Self::Error> {
+    match value {
+      Value::Boolean(b) => Ok(b),
+      other => Err(RuntimeError::WrongType { expected: "boolean", found: other.type_name() }),
     }
   }
 }
 
+/// Extracts an integer, erroring with [`RuntimeError::WrongType`] for any other type
+///
+/// Floats are not truncated, and a [`Value::BigInt`] is a wrong type even when its value
+/// would fit.
+impl TryFrom<Value> for i64 {
+  type Error = RuntimeError;
+  fn try_from(value: Value) -> Result<Self, Self::Error> {
+    match value {
+      Value::Integer(int) => Ok(int),
+      other => Err(RuntimeError::WrongType { expected: "integer", found: other.type_name() }),
+    }
+  }
+}
+
+/// Extracts a float, erroring with [`RuntimeError::WrongType`] for any other type
+impl TryFrom<Value> for f64 {
+  type Error = RuntimeError;
+  fn try_from(value: Value) -> Result<Self, Self::Error> {
+    match value {
+      Value::Float(flt) => Ok(flt),
+      other => Err(RuntimeError::WrongType { expected: "float", found: other.type_name() }),
+    }
+  }
+}
+
+/// Extracts the text of a string, like [`Value::into_string`]
+impl TryFrom<Value> for String {
+  type Error = RuntimeError;
+  fn try_from(value: Value) -> Result<Self, Self::Error> {
+    let found = value.type_name();
+    value.into_string().ok_or(RuntimeError::WrongType { expected: "string", found })
+  }
+}
+
 impl<V> FromIterator<V> for Value
   where V: Into<Value>
 {
@@ -149,38 +275,152 @@ impl<V> FromIterator<V> for Value
   }
 }
 
+/// Address of an array or map, to recognize one that contains itself
+type ContainerPtr = *const ();
+
 impl fmt::Display for Value {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    self.fmt_visiting(f, &mut Vec::new())
+  }
+}
+
+impl Value {
+  /// Formats `self`, printing `[...]` (or `{...}`) for any array (or map) that is already
+  /// being printed further up
+  fn fmt_visiting(&self, f: &mut fmt::Formatter, visiting: &mut Vec<ContainerPtr>) -> fmt::Result {
     match self {
       Value::Nil => write!(f, "nil"),
       Value::Integer(int) => write!(f, "{int}"),
+      #[cfg(feature = "bigint")]
+      Value::BigInt(int) => write!(f, "{int}"),
       Value::Float(float) => write!(f, "{float}"),
-      Value::ShortStr { buf, len } => write!(f, "\"{}\"", std::str::from_utf8(&buf[..*len as usize]).unwrap()),
+      // lossy so that a hand-built, malformed buffer can't panic
+      Value::ShortStr { buf, len } => {
+        let bytes = buf.get(..*len as usize).unwrap_or(buf);
+        write!(f, "\"{}\"", String::from_utf8_lossy(bytes))
+      }
       Value::LongStr(string) => write!(f, "\"{string}\""),
       Value::Array(array) => {
+        let ptr = Rc::as_ptr(array).cast();
+        if visiting.contains(&ptr) {
+          return write!(f, "[...]");
+        }
+        visiting.push(ptr);
         let array = array.borrow();
         write!(f, "[")?;
-        let mut iter = array.iter();
-        if let Some(first) = iter.next() {
-          write!(f, "{first}")?;
-        }
-        for element in iter {
-          write!(f, ", {element}")?;
+        for (i, element) in array.iter().enumerate() {
+          if i > 0 {
+            write!(f, ", ")?;
+          }
+          element.fmt_visiting(f, visiting)?;
         }
         write!(f, "]")?;
+        visiting.pop();
+        Ok(())
+      }
+      Value::Bytes(bytes) => {
+        write!(f, "bytes[")?;
+        for (i, byte) in bytes.borrow().iter().enumerate() {
+          if i > 0 {
+            write!(f, ", ")?;
+          }
+          write!(f, "{byte}")?;
+        }
+        write!(f, "]")
+      }
+      // sorted by key so the output doesn't depend on the hasher
+      Value::Map(map) => {
+        let ptr = Rc::as_ptr(map).cast();
+        if visiting.contains(&ptr) {
+          return write!(f, "{{...}}");
+        }
+        visiting.push(ptr);
+        let map = map.borrow();
+        let mut entries: Vec<(&MapKey, &Value)> = map.iter().collect();
+        entries.sort_unstable_by_key(|(key, _)| *key);
+        write!(f, "{{")?;
+        for (i, (key, value)) in entries.into_iter().enumerate() {
+          if i > 0 {
+            write!(f, ", ")?;
+          }
+          write!(f, "{}: ", Value::from(key.clone()))?;
+          value.fmt_visiting(f, visiting)?;
+        }
+        write!(f, "}}")?;
+        visiting.pop();
         Ok(())
       }
       Value::Boolean(true) => write!(f, "true"),
       Value::Boolean(false) => write!(f, "false"),
+      Value::Function(name) => write!(f, "function {name}"),
+    }
+  }
+
+  /// Compares `self` and `other`, treating a pair of arrays (or maps) that is already being
+  /// compared further up as equal so cyclic containers don't recurse forever
+  fn eq_visiting(&self, other: &Self, visiting: &mut Vec<(ContainerPtr, ContainerPtr)>) -> bool {
+    match (self, other) {
+      (Value::Nil, Value::Nil) => true,
+      (Value::Boolean(left), Value::Boolean(right)) => left == right,
+      (Value::Integer(left), Value::Integer(right)) => left == right,
+      #[cfg(feature = "bigint")]
+      (Value::BigInt(left), Value::BigInt(right)) => left == right,
+      (Value::Float(left), Value::Float(right)) => left == right,
+      (Value::ShortStr { buf: left_buf, len: left_len }, Value::ShortStr { buf: right_buf, len: right_len }) =>
+        left_len == right_len && left_buf == right_buf,
+      (Value::LongStr(left), Value::LongStr(right)) => left == right,
+      // the same array is equal to itself without comparing its elements
+      (Value::Array(left), Value::Array(right)) if Rc::ptr_eq(left, right) => true,
+      (Value::Array(left), Value::Array(right)) => {
+        let pair = (Rc::as_ptr(left).cast(), Rc::as_ptr(right).cast());
+        if visiting.contains(&pair) {
+          return true;
+        }
+        visiting.push(pair);
+        let (left, right) = (left.borrow(), right.borrow());
+        let equal = left.len() == right.len()
+          && left.iter().zip(right.iter()).all(|(l, r)| l.eq_visiting(r, visiting));
+        visiting.pop();
+        equal
+      }
+      (Value::Bytes(left), Value::Bytes(right)) => left == right,
+      (Value::Map(left), Value::Map(right)) => {
+        let pair = (Rc::as_ptr(left).cast(), Rc::as_ptr(right).cast());
+        if visiting.contains(&pair) {
+          return true;
+        }
+        visiting.push(pair);
+        let (left, right) = (left.borrow(), right.borrow());
+        let equal = left.len() == right.len() && left.iter().all(|(key, l)| {
+          right.get(key).is_some_and(|r| l.eq_visiting(r, visiting))
+        });
+        visiting.pop();
+        equal
+      }
+      (Value::Function(left), Value::Function(right)) => left == right,
+      _ => false,
     }
   }
 }
 
+impl PartialEq for Value {
+  fn eq(&self, other: &Self) -> bool {
+    self.eq_visiting(other, &mut Vec::new())
+  }
+}
+
 impl PartialOrd for Value {
   fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
     match (self, other) {
       (Value::Integer(left), Value::Integer(right)) => left.partial_cmp(&right),
-      _ => None,
+      #[cfg(feature = "bigint")]
+      (left, right) if both_integers(left, right) => Some(bigint::cmp(left, right)),
+      (Value::Float(left), Value::Float(right)) => left.partial_cmp(right),
+      // strings compare by their bytes, anything else (including mixed types) doesn't compare
+      _ => match (self.as_str(), other.as_str()) {
+        (Some(left), Some(right)) => Some(left.cmp(right)),
+        _ => None,
+      },
     }
   }
 }
@@ -189,7 +429,9 @@ impl Add for Value {
   type Output = Value;
   fn add(self, other: Self) -> Self::Output {
     match (self, other) {
-      (Value::Integer(left), Value::Integer(right)) => Value::Integer(left + right),
+      #[cfg(feature = "bigint")]
+      (left, right) if both_integers(&left, &right) => bigint::add(left, right),
+      (Value::Integer(left), Value::Integer(right)) => Value::Integer(left.wrapping_add(right)),
       (Value::Float(left), Value::Float(right)) => Value::Float(left + right),
       _ => Value::Nil,
     }
@@ -200,76 +442,578 @@ impl Sub for Value {
   type Output = Value;
   fn sub(self, other: Self) -> Self::Output {
     match (self, other) {
-      (Value::Integer(left), Value::Integer(right)) => Value::Integer(left - right),
+      #[cfg(feature = "bigint")]
+      (left, right) if both_integers(&left, &right) => bigint::sub(left, right),
+      (Value::Integer(left), Value::Integer(right)) => Value::Integer(left.wrapping_sub(right)),
       (Value::Float(left), Value::Float(right)) => Value::Float(left - right),
       _ => Value::Nil,
     }
   }
 }
 
+/// A string multiplied by an integer (on either side) repeats the string
+///
+/// A negative count results in `nil`.
 impl Mul for Value {
   type Output = Value;
   fn mul(self, other: Self) -> Self::Output {
     match (self, other) {
-      (Value::Integer(left), Value::Integer(right)) => Value::Integer(left * right),
+      #[cfg(feature = "bigint")]
+      (left, right) if both_integers(&left, &right) => bigint::mul(left, right),
+      (Value::Integer(left), Value::Integer(right)) => Value::Integer(left.wrapping_mul(right)),
       (Value::Float(left), Value::Float(right)) => Value::Float(left * right),
+      (string @ (Value::ShortStr { .. } | Value::LongStr(_)), Value::Integer(count)) |
+      (Value::Integer(count), string @ (Value::ShortStr { .. } | Value::LongStr(_))) =>
+        repeat_str(&string, count),
       _ => Value::Nil,
     }
   }
 }
 
+/// Repeats a string value `count` times, `nil` if `count` is negative or the result would
+/// be too large to allocate
+fn repeat_str(string: &Value, count: i64) -> Value {
+  let count = match usize::try_from(count) {
+    Ok(count) => count,
+    Err(_) => return Value::Nil,
+  };
+  let string = match string.as_str() {
+    Some(string) => string,
+    None => return Value::Nil,
+  };
+  let mut repeated = String::new();
+  match string.len().checked_mul(count).map(|len| repeated.try_reserve_exact(len)) {
+    Some(Ok(())) => {
+      (0..count).for_each(|_| repeated.push_str(string));
+      repeated.into()
+    }
+    _ => Value::Nil,
+  }
+}
+
+/// Division always results in a float, even when both operands are integers
+///
+/// Use [`Value::floor_div`] (`//` in scripts) for integer division.
 impl Div for Value {
   type Output = Value;
   fn div(self, other: Self) -> Self::Output {
-    match (self, other) {
-      (Value::Integer(left), Value::Integer(right)) => Value::Integer(left / right),
-      (Value::Float(left), Value::Float(right)) => Value::Float(left / right),
+    match (self.as_f64(), other.as_f64()) {
+      (Some(left), Some(right)) => Value::Float(left / right),
       _ => Value::Nil,
     }
   }
 }
 
+/// Remainder matching [`Value::floor_div`], so `a == (a // b) * b + a % b`
+///
+/// The result has the same sign as the divisor.  Two integers result in an integer
+/// (or `nil` when dividing by zero), otherwise the result is a float.
 impl Rem for Value {
   type Output = Value;
   fn rem(self, other: Self) -> Self::Output {
     match (self, other) {
-      (Value::Integer(left), Value::Integer(right)) => Value::Integer(left % right),
+      #[cfg(feature = "bigint")]
+      (left, right) if both_integers(&left, &right) => bigint::rem(left, right),
+      (Value::Integer(left), Value::Integer(right)) => integer_rem(left, right).map_or(Value::Nil, Value::Integer),
+      (left, right) => match (left.as_f64(), right.as_f64()) {
+        (Some(left), Some(right)) => Value::Float(left - (left / right).floor() * right),
+        _ => Value::Nil,
+      },
+    }
+  }
+}
+
+/// Integer floor division, `None` when dividing by zero or overflowing
+pub(super) fn integer_floor_div(left: i64, right: i64) -> Option<i64> {
+  let quotient = left.checked_div(right)?;
+  if left % right != 0 && (left < 0) != (right < 0) {
+    Some(quotient - 1)
+  } else {
+    Some(quotient)
+  }
+}
+
+/// Integer remainder matching [`integer_floor_div`], `None` when dividing by zero
+///
+/// `i64::MIN % -1` overflows the division, but its remainder is still `0`.
+pub(super) fn integer_rem(left: i64, right: i64) -> Option<i64> {
+  if right == 0 {
+    return None;
+  }
+  let remainder = left.wrapping_rem(right);
+  if remainder != 0 && (remainder < 0) != (right < 0) {
+    Some(remainder + right)
+  } else {
+    Some(remainder)
+  }
+}
+
+#[cfg(feature = "bigint")]
+fn both_integers(left: &Value, right: &Value) -> bool {
+  let is_integer = |value: &Value| matches!(value, Value::Integer(_) | Value::BigInt(_));
+  is_integer(left) && is_integer(right)
+}
+
+impl Neg for Value {
+  type Output = Value;
+  fn neg(self) -> Self::Output {
+    match self {
+      #[cfg(feature = "bigint")]
+      int @ (Value::Integer(_) | Value::BigInt(_)) => bigint::neg(int),
+      #[cfg(not(feature = "bigint"))]
+      Value::Integer(int) => Value::Integer(int.wrapping_neg()),
+      Value::Float(flt) => Value::Float(-flt),
       _ => Value::Nil,
     }
   }
 }
 
 impl Value {
-  pub fn get(&self, key: Value) -> Value {
-    match (self, key) {
-      (Value::Array(array), Value::Integer(num)) => {
-        let index: usize = match num.try_into() {
-          Ok(as_usize) => as_usize,
-          _ => return Value::Nil,
-        };
-        let array = array.borrow();
-        array.get(index).unwrap_or(&Value::Nil).clone()
+  /// Floor division (`//` in scripts), rounds the quotient towards negative infinity
+  ///
+  /// Two integers result in an integer (or `nil` when dividing by zero), otherwise the
+  /// result is a float.
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::runtime::Value;
+  /// assert_eq!(Value::Integer(7).floor_div(Value::Integer(2)), Value::Integer(3));
+  /// assert_eq!(Value::Integer(-7).floor_div(Value::Integer(2)), Value::Integer(-4));
+  /// assert_eq!(Value::Float(7.0).floor_div(Value::Integer(2)), Value::Float(3.0));
+  /// ```
+  pub fn floor_div(self, other: Self) -> Value {
+    match (self, other) {
+      #[cfg(feature = "bigint")]
+      (left, right) if both_integers(&left, &right) => bigint::floor_div(left, right),
+      (Value::Integer(left), Value::Integer(right)) => integer_floor_div(left, right).map_or(Value::Nil, Value::Integer),
+      (left, right) => match (left.as_f64(), right.as_f64()) {
+        (Some(left), Some(right)) => Value::Float((left / right).floor()),
+        _ => Value::Nil,
+      },
+    }
+  }
+
+  /// Characters of a string value
+  ///
+  /// Values that aren't strings have no characters.
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::runtime::Value;
+  /// let characters: Vec<Value> = Value::from("abc").chars().map(Value::from).collect();
+  /// assert_eq!(characters, [Value::from('a'), Value::from('b'), Value::from('c')]);
+  /// assert_eq!(Value::Integer(12).chars().count(), 0);
+  /// ```
+  pub fn chars(&self) -> impl Iterator<Item=char> + '_ {
+    self.as_str().unwrap_or("").chars()
+  }
+
+  /// Elements of an array value, `None` for values that can't be iterated
+  ///
+  /// The elements are copied out of the array first, so the array can be changed while
+  /// iterating without affecting the iteration.
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::runtime::Value;
+  /// let array = Value::from_iter([1, 2, 3]);
+  /// let doubled: Vec<Value> = array.iter().unwrap().map(|element| element * Value::Integer(2)).collect();
+  /// assert_eq!(doubled, [Value::Integer(2), Value::Integer(4), Value::Integer(6)]);
+  /// assert!(Value::Integer(3).iter().is_none());
+  /// ```
+  pub fn iter(&self) -> Option<impl Iterator<Item=Value>> {
+    match self {
+      Value::Array(array) => Some(array.borrow().clone().into_iter()),
+      _ => None,
+    }
+  }
+
+  /// Text of a string value, without the quotes added by `Display`
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::runtime::Value;
+  /// assert_eq!(Value::from("short").as_str(), Some("short"));
+  /// assert_eq!(Value::from("a much longer string").as_str(), Some("a much longer string"));
+  /// assert_eq!(Value::Integer(5).as_str(), None);
+  /// ```
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Value::ShortStr { buf, len } => std::str::from_utf8(buf.get(..*len as usize)?).ok(),
+      Value::LongStr(string) => Some(string),
+      _ => None,
+    }
+  }
+
+  /// Converts a string value into its text
+  ///
+  /// A long string that isn't shared with any other value gives up its `String`
+  /// without copying it.
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::runtime::Value;
+  /// assert_eq!(Value::from("text").into_string(), Some("text".to_owned()));
+  /// assert_eq!(Value::Nil.into_string(), None);
+  /// ```
+  pub fn into_string(self) -> Option<String> {
+    match self {
+      Value::LongStr(string) => Some(Rc::try_unwrap(string).unwrap_or_else(|shared| (*shared).clone())),
+      other => other.as_str().map(str::to_owned),
+    }
+  }
+
+  /// Text of a string without quotes, or the [`Display`](fmt::Display) form of anything else
+  ///
+  /// This is how `print` writes each argument.  Strings inside arrays and maps are still
+  /// quoted.
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::runtime::Value;
+  /// assert_eq!(Value::from("hi").to_display_string(), "hi");
+  /// assert_eq!(Value::from("hi").to_string(), "\"hi\"");
+  /// assert_eq!(Value::from_iter(["a"]).to_display_string(), "[\"a\"]");
+  /// ```
+  pub fn to_display_string(&self) -> String {
+    match self.as_str() {
+      Some(string) => string.to_owned(),
+      None => self.to_string(),
+    }
+  }
+
+  /// The number the value is, or the number a string holds, like Lua's `tonumber`
+  ///
+  /// Numbers are returned unchanged.  A string of an integer gives an integer and a string
+  /// of any other decimal number gives a float, ignoring whitespace around it.  Anything
+  /// else gives `None`, including strings like `"abc"`, `"inf"` and `"nan"`.
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::runtime::Value;
+  /// assert_eq!(Value::from(" 42 ").to_number(), Some(Value::Integer(42)));
+  /// assert_eq!(Value::from("-2.5e1").to_number(), Some(Value::Float(-25.0)));
+  /// assert_eq!(Value::from("nan").to_number(), None);
+  /// assert_eq!(Value::Boolean(true).to_number(), None);
+  /// ```
+  pub fn to_number(&self) -> Option<Value> {
+    if self.is_number() {
+      return Some(self.clone());
+    }
+    let text = self.as_str()?.trim();
+    let unsigned = text.strip_prefix(['+', '-']).unwrap_or(text);
+    let is_decimal = unsigned.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+      && unsigned.chars().all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'));
+    if !is_decimal {
+      return None;
+    }
+    if let Ok(int) = text.parse() {
+      return Some(Value::Integer(int));
+    }
+    #[cfg(feature = "bigint")]
+    if let Ok(int) = text.parse::<num_bigint::BigInt>() {
+      return Some(Value::from(int));
+    }
+    text.parse().ok().map(Value::Float)
+  }
+
+  /// Raises the value to the power of `other`
+  ///
+  /// Like `/`, the result is always a float.  Non-numeric operands give `nil`.
+  ///
+  /// With the `bigint` feature, an integer raised to a non-negative integer is exact
+  /// instead, promoting to [`Value::BigInt`] when it doesn't fit in an `i64`.
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::runtime::Value;
+  /// assert_eq!(Value::Float(4.0).pow(Value::Float(0.5)), Value::Float(2.0));
+  /// # #[cfg(not(feature = "bigint"))]
+  /// assert_eq!(Value::Integer(2).pow(Value::Integer(9)), Value::Float(512.0));
+  /// ```
+  pub fn pow(self, other: Self) -> Value {
+    #[cfg(feature = "bigint")]
+    if let Some(exact) = bigint::pow(&self, &other) {
+      return exact;
+    }
+    match (self.as_f64(), other.as_f64()) {
+      (Some(base), Some(exponent)) => Value::Float(base.powf(exponent)),
+      _ => Value::Nil,
+    }
+  }
+
+  pub(crate) fn as_f64(&self) -> Option<f64> {
+    match self {
+      Value::Integer(int) => Some(*int as f64),
+      #[cfg(feature = "bigint")]
+      Value::BigInt(int) => Some(bigint::to_f64(int)),
+      Value::Float(flt) => Some(*flt),
+      _ => None,
+    }
+  }
+
+  /// Name of the value's runtime type
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::runtime::Value;
+  /// assert_eq!(Value::Nil.type_name(), "nil");
+  /// assert_eq!(Value::Boolean(true).type_name(), "boolean");
+  /// assert_eq!(Value::Integer(3).type_name(), "integer");
+  /// assert_eq!(Value::Float(0.5).type_name(), "float");
+  /// assert_eq!(Value::from("short").type_name(), "string");
+  /// assert_eq!(Value::from("a much longer string").type_name(), "string");
+  /// assert_eq!(Value::from_iter([1, 2, 3]).type_name(), "array");
+  /// ```
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Value::Nil => "nil",
+      Value::Boolean(_) => "boolean",
+      Value::Integer(_) => "integer",
+      #[cfg(feature = "bigint")]
+      Value::BigInt(_) => "integer",
+      Value::Float(_) => "float",
+      Value::ShortStr { .. } | Value::LongStr(_) => "string",
+      Value::Array(_) => "array",
+      Value::Bytes(_) => "bytes",
+      Value::Map(_) => "map",
+      Value::Function(_) => "function",
+    }
+  }
+
+  /// Whether the value is `nil`
+  pub fn is_nil(&self) -> bool {
+    matches!(self, Value::Nil)
+  }
+
+  /// Whether the value is an integer, including big integers
+  pub fn is_integer(&self) -> bool {
+    match self {
+      Value::Integer(_) => true,
+      #[cfg(feature = "bigint")]
+      Value::BigInt(_) => true,
+      _ => false,
+    }
+  }
+
+  /// Whether the value is a float
+  pub fn is_float(&self) -> bool {
+    matches!(self, Value::Float(_))
+  }
+
+  /// Whether the value is an integer or a float
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::runtime::Value;
+  /// assert!(Value::Integer(1).is_number());
+  /// assert!(Value::Float(1.5).is_number());
+  /// assert!(!Value::from("1").is_number());
+  /// ```
+  pub fn is_number(&self) -> bool {
+    self.is_integer() || self.is_float()
+  }
+
+  /// Whether the value is a string, short or long
+  pub fn is_string(&self) -> bool {
+    matches!(self, Value::ShortStr { .. } | Value::LongStr(_))
+  }
+
+  /// Whether the value is an array
+  pub fn is_array(&self) -> bool {
+    matches!(self, Value::Array(_))
+  }
+
+  /// Whether the value counts as true in a condition
+  ///
+  /// Only `nil` and `false` are falsy, every other value is truthy (including `0` and
+  /// empty strings).
+  pub fn is_truthy(&self) -> bool {
+    !matches!(self, Value::Nil | Value::Boolean(false))
+  }
+
+  /// Element of an array, character of a string, byte of a byte buffer, or value in a map
+  /// at `key`
+  ///
+  /// Keys that are out of bounds or not integers give `nil`, as do keys missing from a map.
+  /// Looking up a key that can't be in a map is a [`RuntimeError::UnhashableKey`].  Values
+  /// other than arrays, strings, byte buffers and maps are a [`RuntimeError::NotIndexable`].
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::runtime::{Value, RuntimeError};
+  /// assert_eq!(Value::from_iter([4, 5]).get(Value::Integer(1)).unwrap(), Value::Integer(5));
+  /// assert_eq!(Value::from("abc").get(Value::Integer(2)).unwrap(), Value::from('c'));
+  /// assert!(matches!(
+  ///   Value::Integer(5).get(Value::Integer(0)),
+  ///   Err(RuntimeError::NotIndexable { type_name: "integer" }),
+  /// ));
+  /// ```
+  pub fn get(&self, key: Value) -> Result<Value, RuntimeError> {
+    if let Value::Map(map) = self {
+      return Ok(map.borrow().get(&key.hash_key()?).cloned().unwrap_or_default());
+    }
+    let index: Option<usize> = match key {
+      Value::Integer(num) => num.try_into().ok(),
+      _ => None,
+    };
+    match (self, index) {
+      (Value::Array(array), Some(index)) => Ok(array.borrow().get(index).cloned().unwrap_or_default()),
+      (Value::ShortStr { .. } | Value::LongStr(_), Some(index)) =>
+        Ok(self.chars().nth(index).map(Value::from).unwrap_or_default()),
+      (Value::Bytes(bytes), Some(index)) => Ok(bytes.borrow().get(index).map(|&byte| Value::from(byte)).unwrap_or_default()),
+      (Value::Array(_) | Value::ShortStr { .. } | Value::LongStr(_) | Value::Bytes(_), None) => Ok(Value::Nil),
+      (other, _) => Err(RuntimeError::NotIndexable { type_name: other.type_name() }),
+    }
+  }
+
+  /// Copies the elements of an array (or characters of a string, or bytes of a byte
+  /// buffer) from `start` up to but not including `end`
+  ///
+  /// A `nil` bound is the start or end of the value, and bounds past the end are clamped to
+  /// it.  A `start` at or after `end` gives an empty array.  Like [`get`](Self::get),
+  /// negative or non-integer bounds give `nil`, and values other than arrays, strings and
+  /// byte buffers are a [`RuntimeError::NotIndexable`].
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::runtime::Value;
+  /// let array = Value::from_iter([10, 20, 30, 40]);
+  /// assert_eq!(array.slice(Value::Integer(1), Value::Integer(3)).unwrap(), Value::from_iter([20, 30]));
+  /// assert_eq!(array.slice(Value::Nil, Value::Integer(9)).unwrap(), array);
+  /// assert_eq!(Value::from("hello").slice(Value::Integer(1), Value::Nil).unwrap(), Value::from("ello"));
+  /// ```
+  pub fn slice(&self, start: Value, end: Value) -> Result<Value, RuntimeError> {
+    let len = match self {
+      Value::Array(array) => array.borrow().len(),
+      Value::Bytes(bytes) => bytes.borrow().len(),
+      Value::ShortStr { .. } | Value::LongStr(_) => self.chars().count(),
+      other => return Err(RuntimeError::NotIndexable { type_name: other.type_name() }),
+    };
+    let bound = |bound: Value, default: usize| -> Option<usize> {
+      match bound {
+        Value::Nil => Some(default),
+        Value::Integer(num) => usize::try_from(num).ok().map(|index| index.min(len)),
+        _ => None,
       }
-      _ => todo!(),
+    };
+    let (start, end) = match (bound(start, 0), bound(end, len)) {
+      (Some(start), Some(end)) => (start, end.max(start)),
+      _ => return Ok(Value::Nil),
+    };
+    match self {
+      Value::Array(array) => Ok(Value::from(&array.borrow()[start..end])),
+      Value::Bytes(bytes) => Ok(Value::Bytes(Rc::new(RefCell::new(bytes.borrow()[start..end].to_vec())))),
+      _ => Ok(self.chars().skip(start).take(end - start).collect::<String>().into()),
     }
   }
 
-  pub fn set(&mut self, key: Value, value: Value) {
-    match (self, key) {
-      (Value::Array(array), Value::Integer(num)) => {
-        let index: usize = match num.try_into() {
-          Ok(as_usize) => as_usize,
-          _ => return,
+  /// Sets the element of an array, byte of a byte buffer, or value in a map at `key`
+  ///
+  /// Setting the element one past the end appends it.  Other out of bounds or
+  /// non-integer keys are ignored.  Values other than arrays, byte buffers and maps
+  /// (including strings, which can't be modified) are a [`RuntimeError::NotIndexable`].
+  /// Storing anything other than an integer from 0 to 255 in a byte buffer is a
+  /// [`RuntimeError::InvalidByte`].
+  ///
+  /// Setting a map key to `nil` removes it.  A key that can't be in a map is a
+  /// [`RuntimeError::UnhashableKey`].
+  pub fn set(&mut self, key: Value, value: Value) -> Result<(), RuntimeError> {
+    if let Value::Map(map) = self {
+      let key = key.hash_key()?;
+      match value {
+        Value::Nil => map.borrow_mut().remove(&key),
+        value => map.borrow_mut().insert(key, value),
+      };
+      return Ok(());
+    }
+    let index: Option<usize> = match key {
+      Value::Integer(num) => num.try_into().ok(),
+      _ => None,
+    };
+    match self {
+      Value::Array(array) => {
+        if let Some(index) = index {
+          set_or_push(&mut array.borrow_mut(), index, value);
+        }
+      }
+      Value::Bytes(bytes) => {
+        let byte = match value {
+          Value::Integer(num) => u8::try_from(num).map_err(|_| RuntimeError::InvalidByte)?,
+          _ => return Err(RuntimeError::InvalidByte),
         };
-        let mut array = array.borrow_mut();
-        if let Some(element) = array.get_mut(index) {
-          *element = value;
-        } else if index == array.len() {
-          array.push(value);
+        if let Some(index) = index {
+          set_or_push(&mut bytes.borrow_mut(), index, byte);
         }
       }
-      _ => todo!(),
+      other => return Err(RuntimeError::NotIndexable { type_name: other.type_name() }),
+    }
+    Ok(())
+  }
+
+  /// Byte buffer of `len` zeroes, or `nil` if a buffer that large can't be allocated
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::runtime::Value;
+  /// let mut buffer = Value::bytes(2);
+  /// buffer.set(Value::Integer(1), Value::Integer(255)).unwrap();
+  /// assert_eq!(buffer.get(Value::Integer(1)).unwrap(), Value::Integer(255));
+  /// assert_eq!(buffer.to_string(), "bytes[0, 255]");
+  /// ```
+  pub fn bytes(len: usize) -> Value {
+    let mut buffer = Vec::new();
+    match buffer.try_reserve_exact(len) {
+      Ok(()) => {
+        buffer.extend(core::iter::repeat_n(0, len));
+        Value::Bytes(Rc::new(RefCell::new(buffer)))
+      }
+      Err(_) => Value::Nil,
     }
   }
+
+  /// New empty map
+  pub fn map() -> Value {
+    Value::Map(Rc::default())
+  }
+
+  /// Converts the value into a [`MapKey`]
+  ///
+  /// Only booleans, integers and strings can be map keys, anything else is a
+  /// [`RuntimeError::UnhashableKey`].
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::runtime::{Value, RuntimeError};
+  /// let mut map = Value::map();
+  /// map.set(Value::from("a"), Value::Integer(1)).unwrap();
+  /// assert_eq!(map.get(Value::from("a")).unwrap(), Value::Integer(1));
+  /// assert!(matches!(
+  ///   map.set(Value::Float(1.5), Value::Integer(1)),
+  ///   Err(RuntimeError::UnhashableKey { type_name: "float" }),
+  /// ));
+  /// ```
+  pub fn hash_key(&self) -> Result<MapKey, RuntimeError> {
+    match self {
+      Value::Boolean(boolean) => Ok(MapKey::Boolean(*boolean)),
+      Value::Integer(integer) => Ok(MapKey::Integer(*integer)),
+      #[cfg(feature = "bigint")]
+      Value::BigInt(integer) => Ok(MapKey::BigInt(Rc::clone(integer))),
+      string @ (Value::ShortStr { .. } | Value::LongStr(_)) => match string.as_str() {
+        Some(string) => Ok(MapKey::String(Rc::from(string))),
+        None => Err(RuntimeError::UnhashableKey { type_name: string.type_name() }),
+      },
+      other => Err(RuntimeError::UnhashableKey { type_name: other.type_name() }),
+    }
+  }
+}
+
+/// Replaces the item at `index`, or appends it when `index` is one past the end
+fn set_or_push<T>(items: &mut Vec<T>, index: usize, item: T) {
+  if let Some(slot) = items.get_mut(index) {
+    *slot = item;
+  } else if index == items.len() {
+    items.push(item);
+  }
 }
 
@@ -1,42 +1,112 @@
 
 use std::collections::HashMap;
 use std::rc::Rc;
+use std::fmt;
+use std::io::{self, Write};
 use crate::bytecode::Instruction;
 use crate::bytecode::opcode::{SuperCode, misc, index, comparison, arithmetic};
 use crate::bytecode::operand::{
   FromDestination, FromSource,
-  RawRegister, Immediate, ConstantKey,
+  RawRegister, Global, Immediate, ConstantKey,
   InstructionPointer, FunctionKey,
   WildDestination, WildSource,
 };
 use crate::bytecode::constant_value::ConstantValue;
 use crate::bytecode::Procedure;
+use crate::bytecode::builtin;
 use super::error::RuntimeError;
-use super::{Value, InstructionCount, ExecutionStatus};
+use super::{Value, InstructionCount, ExecutionStatus, GasModel};
+use super::native::{self, NativeFunction};
 
 #[derive(Debug)]
 struct StackFrame {
   procedure: Rc<Procedure>,
   pc: usize,
+  /// Index of the instruction fetched last, `None` until the first fetch
+  fetched: Option<usize>,
   register_start: usize,
   return_index: usize,
 }
 
+impl StackFrame {
+  /// Fetches the next instruction and moves the pc past it
+  fn fetch(&mut self) -> Result<Instruction, RuntimeError> {
+    if self.procedure.leading_nop {
+      self.pc += 1;
+      self.fetched = Some(self.pc);
+      self.procedure.bytecode.get(self.pc).copied().ok_or(RuntimeError::InvalidPc)
+    } else {
+      let instruction = self.procedure.bytecode.get(self.pc).copied().ok_or(RuntimeError::InvalidPc)?;
+      self.fetched = Some(self.pc);
+      self.pc += 1;
+      Ok(instruction)
+    }
+  }
+
+  /// Index in the bytecode of the instruction that was fetched last, or `None` if nothing
+  /// has been fetched from this frame yet
+  ///
+  /// A jump changes the pc but not this, so it's still the jump's index until the next
+  /// fetch.
+  fn fetched_index(&self) -> Option<usize> {
+    self.fetched
+  }
+}
+
+/// Callback run before each instruction, see [`VirtualMachine::set_trace_hook`]
+pub type TraceHook = Box<dyn FnMut(usize, Instruction)>;
+
+/// Wrapper so `VirtualMachine` can still derive `Debug`
+struct Tracer(TraceHook);
+
+impl fmt::Debug for Tracer {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Tracer")
+  }
+}
+
+/// Wrapper so `VirtualMachine` can still derive `Debug`
+struct Output(Box<dyn Write>);
+
+impl fmt::Debug for Output {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Output")
+  }
+}
+
 /// Bytecode evaluation engine
 ///
 /// Attach compiled functions to a `VirtualMachine` with [`insert_function`] or initialize it
 /// with a list of functions using [`with_functions`].
 ///
+/// Native (Rust) functions can be called from bytecode the same way as compiled functions.
+/// The [standard natives](super::native) are attached by default and more can be added
+/// with [`insert_native`].
+///
+/// Scripts write text with the `print` and `println` natives, which go to standard output
+/// unless redirected with [`set_output`].  The `call` builtin calls the [`Value::Function`] in its
+/// first argument, and calling a local variable compiles to it under the name
+/// [`builtin::CALL`].
+///
 /// Begin execution with [`run`].
 ///
 /// [`insert_function`]: Self::insert_function
+/// [`insert_native`]: Self::insert_native
 /// [`with_functions`]: Self::with_functions
+/// [`set_output`]: Self::set_output
 /// [`run`]: Self::run
 #[derive(Debug)]
 pub struct VirtualMachine {
   call_stack: Vec<StackFrame>,
   functions: HashMap<String, Rc<Procedure>>,
+  natives: HashMap<String, NativeFunction>,
+  globals: Vec<Value>,
+  global_names: Vec<String>,
   register_stack: Vec<Value>,
+  tracer: Option<Tracer>,
+  output: Output,
+  gas: Option<u64>,
+  gas_model: GasModel,
 }
 
 impl Default for VirtualMachine {
@@ -44,12 +114,30 @@ impl Default for VirtualMachine {
     Self {
       call_stack: Vec::default(),
       functions: HashMap::default(),
+      natives: native::standard().map(|(name, native)| (name.to_owned(), native)).collect(),
+      globals: Vec::default(),
+      global_names: Vec::default(),
       register_stack: vec![Value::Nil], // bottom of register stack is VM result
+      tracer: None,
+      output: Output(Box::new(io::stdout())),
+      gas: None,
+      gas_model: GasModel::default(),
     }
   }
 }
 
 impl VirtualMachine {
+  /// Name of the function [`run_main`](Self::run_main) calls, and that the `lualite`
+  /// binary runs
+  pub const ENTRY_NAME: &'static str = "main";
+
+  /// Names of the builtins, which the virtual machine calls itself instead of looking up
+  /// a function or native
+  ///
+  /// `call` calls the [`Value::Function`] in its first argument with the rest, and calling a
+  /// local compiles to it as [`builtin::CALL`].
+  pub const BUILTIN_NAMES: [&'static str; 2] = ["call", builtin::CALL];
+
   pub fn new() -> Self {
     Self::default()
   }
@@ -59,16 +147,52 @@ impl VirtualMachine {
   where
     I: IntoIterator<Item=(S, P)>,
     S: Into<String>,
-    P: Into<Procedure>,
+    P: Into<Rc<Procedure>>,
   {
     let functions = functions.into_iter()
-      .map(|(name, proc)| (name.into(), Rc::new(proc.into())))
+      .map(|(name, proc)| (name.into(), proc.into()))
       .collect();
     Self { functions, ..Self::default() }
   }
 
-  pub fn insert_function<S: Into<String>, P: Into<Procedure>>(&mut self, name: S, procedure: P) {
-    self.functions.insert(name.into(), Rc::new(procedure.into()));
+  /// Construct a `VirtualMachine` with functions and room for `register_capacity` registers
+  ///
+  /// Each call frame takes [`register_count`] registers from a shared register stack.
+  /// Pre-sizing the stack avoids regrowing it during deep recursion or when [`run`] is
+  /// called repeatedly.
+  ///
+  /// ```
+  /// # use lualite::runtime::VirtualMachine;
+  /// # use lualite::bytecode::Procedure;
+  /// let vm = VirtualMachine::with_capacity(Vec::<(String, Procedure)>::new(), 1024);
+  /// assert!(vm.register_capacity() >= 1024);
+  /// ```
+  ///
+  /// [`register_count`]: crate::bytecode::Code::register_count
+  /// [`run`]: Self::run
+  pub fn with_capacity<I, S, P>(functions: I, register_capacity: usize) -> Self
+  where
+    I: IntoIterator<Item=(S, P)>,
+    S: Into<String>,
+    P: Into<Rc<Procedure>>,
+  {
+    let mut vm = Self::with_functions(functions);
+    vm.reserve_registers(register_capacity);
+    vm
+  }
+
+  /// Reserves room for at least `additional` more registers on the register stack
+  pub fn reserve_registers(&mut self, additional: usize) {
+    self.register_stack.reserve(additional);
+  }
+
+  /// Number of registers the register stack can hold without reallocating
+  pub fn register_capacity(&self) -> usize {
+    self.register_stack.capacity()
+  }
+
+  pub fn insert_function<S: Into<String>, P: Into<Rc<Procedure>>>(&mut self, name: S, procedure: P) {
+    self.functions.insert(name.into(), procedure.into());
   }
 
   pub fn remove_function<S: AsRef<str>>(&mut self, name: S) -> Option<Rc<Procedure>> {
@@ -78,6 +202,129 @@ impl VirtualMachine {
   pub fn get_function<S: AsRef<str>>(&self, name: S) -> Option<Rc<Procedure>> {
     self.functions.get(name.as_ref()).cloned()
   }
+
+  /// Replaces the globals table with named `globals`
+  ///
+  /// Global operands in bytecode are indices into this table.  The statics of a program
+  /// from [`compile_statics`](crate::compiler::compile_statics) are in the order the
+  /// compiler expects.
+  pub fn set_globals<I, S, V>(&mut self, globals: I)
+  where
+    I: IntoIterator<Item=(S, V)>,
+    S: Into<String>,
+    V: Into<Value>,
+  {
+    (self.global_names, self.globals) = globals.into_iter()
+      .map(|(name, value)| (name.into(), value.into()))
+      .unzip();
+  }
+
+  /// Current value of the global named `name`
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::{parser, compiler, runtime::{VirtualMachine, Value}};
+  /// let (_, declarations) = parser::parse_file("static LIMIT = 10").unwrap();
+  /// let mut vm = VirtualMachine::new();
+  /// vm.set_globals(compiler::compile_statics(declarations.iter()).unwrap());
+  ///
+  /// assert_eq!(vm.get_global("LIMIT"), Some(Value::Integer(10)));
+  /// vm.set_global("LIMIT", Value::Integer(20)).unwrap();
+  /// assert_eq!(vm.get_global("LIMIT"), Some(Value::Integer(20)));
+  /// assert_eq!(vm.get_global("MISSING"), None);
+  /// ```
+  pub fn get_global(&self, name: &str) -> Option<Value> {
+    self.global_index(name).map(|index| self.globals[index].clone())
+  }
+
+  /// Sets the global named `name`
+  ///
+  /// Only globals from [`set_globals`](Self::set_globals) can be set, since compiled code
+  /// can't refer to any other globals.  Unknown names are a [`RuntimeError::InvalidGlobal`].
+  pub fn set_global(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
+    let index = self.global_index(name).ok_or(RuntimeError::InvalidGlobal)?;
+    self.globals[index] = value;
+    Ok(())
+  }
+
+  fn global_index(&self, name: &str) -> Option<usize> {
+    self.global_names.iter().position(|global_name| global_name == name)
+  }
+
+  /// Returns `true` if a function named `name` is attached to the virtual machine.
+  pub fn has_function<S: AsRef<str>>(&self, name: S) -> bool {
+    self.functions.contains_key(name.as_ref())
+  }
+
+  /// Attach a native function, replacing any existing native with the same name.
+  ///
+  /// Compiled functions take priority over natives when both have the same name.
+  pub fn insert_native<S: Into<String>>(&mut self, name: S, native: NativeFunction) {
+    self.natives.insert(name.into(), native);
+  }
+
+  pub fn remove_native<S: AsRef<str>>(&mut self, name: S) -> Option<NativeFunction> {
+    self.natives.remove(name.as_ref())
+  }
+
+  /// Names of all native functions attached to the virtual machine, sorted alphabetically.
+  ///
+  pub fn native_names(&self) -> Vec<&str> {
+    let mut names: Vec<&str> = self.natives.keys().map(String::as_str).collect();
+    names.sort_unstable();
+    names
+  }
+
+  /// Names of all functions attached to the virtual machine, sorted alphabetically.
+  pub fn function_names(&self) -> Vec<&str> {
+    let mut names: Vec<&str> = self.functions.keys().map(String::as_str).collect();
+    names.sort_unstable();
+    names
+  }
+
+  /// Redirects the output of `print` and `println`, returning the previous writer.
+  ///
+  /// The default output is [`io::stdout`].
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::compile_program;
+  /// let mut vm = compile_program("function main() print(1, 2) end").unwrap();
+  /// vm.set_output(Box::new(std::io::sink()));
+  /// vm.run_main().unwrap();
+  /// ```
+  pub fn set_output(&mut self, writer: Box<dyn Write>) -> Box<dyn Write> {
+    std::mem::replace(&mut self.output.0, writer)
+  }
+
+  /// Limits how much more gas executing instructions can use, or removes the limit with
+  /// `None`
+  ///
+  /// Each instruction is charged the cost the [gas model](Self::set_gas_model) gives it.
+  /// Executing an instruction that costs more than the remaining gas fails with
+  /// [`RuntimeError::OutOfGas`] instead.  There is no limit by default.
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::{compile_program, runtime::RuntimeError};
+  /// let mut vm = compile_program("function main() while true do end end").unwrap();
+  /// vm.set_gas(Some(100));
+  /// assert!(matches!(vm.run_main(), Err(RuntimeError::OutOfGas)));
+  /// ```
+  pub fn set_gas(&mut self, gas: Option<u64>) {
+    self.gas = gas;
+  }
+
+  /// Gas left before execution fails, or `None` if there is no limit
+  pub fn remaining_gas(&self) -> Option<u64> {
+    self.gas
+  }
+
+  /// Replaces the cost of each instruction category, which is [`GasModel::default`] unless
+  /// changed
+  pub fn set_gas_model(&mut self, model: GasModel) {
+    self.gas_model = model;
+  }
 }
 
 // Interacting with the current stack frame
@@ -94,9 +341,24 @@ impl VirtualMachine {
       .ok_or_else(|| RuntimeError::InvalidRegister)
   }
 
+  fn global(&self, global: Global) -> Result<&Value, RuntimeError> {
+    self.globals.get(global.0 as usize).ok_or_else(|| RuntimeError::InvalidGlobal)
+  }
+
+  fn global_mut(&mut self, global: Global) -> Result<&mut Value, RuntimeError> {
+    self.globals.get_mut(global.0 as usize).ok_or_else(|| RuntimeError::InvalidGlobal)
+  }
+
+  fn destination_mut(&mut self, destination: WildDestination<RawRegister>) -> Result<&mut Value, RuntimeError> {
+    match destination {
+      WildDestination::Register(register) => self.register_mut(register),
+      WildDestination::Global(global) => self.global_mut(global),
+    }
+  }
+
   fn constant(&self, constant: ConstantKey) -> Result<&ConstantValue, RuntimeError> {
     let top = self.call_stack.last().ok_or_else(|| RuntimeError::EmptyCallStack)?;
-    top.procedure.constants.get(constant.0 as usize).ok_or_else(|| RuntimeError::MissingConstant)
+    top.procedure.constant(constant).ok_or_else(|| RuntimeError::MissingConstant)
   }
 
   fn set_pc(&mut self, pc: InstructionPointer) -> Result<(), RuntimeError> {
@@ -122,6 +384,12 @@ impl VirtualMachine {
   /// [`execution_loop`](Self::execution_loop) for more fine-grained control over
   /// the number of instructions to allow the VM to execute.
   ///
+  /// # Errors:
+  ///
+  /// Calling `run` while an earlier computation is still on the call stack is a
+  /// [`RuntimeError::VmBusy`], use [`abort`](Self::abort) to discard it first.  When the
+  /// function itself fails, its frames are discarded so the virtual machine can run again.
+  ///
   /// # Example:
   /// ```rust
   /// # use lualite::{parser, compiler, runtime::{VirtualMachine, Value}};
@@ -131,20 +399,90 @@ impl VirtualMachine {
   /// end
   /// ";
   /// let (_, declarations) = parser::parse_file(source_code).unwrap();
-  /// let functions = compiler::compile_declarations(declarations.iter());
+  /// let functions = compiler::compile_declarations(declarations.iter()).unwrap();
   /// let mut vm = VirtualMachine::with_functions(functions);
   ///
   /// let result = vm.run("trisum", [1.into(), 2.into(), 3.into()]);
   /// assert!(matches!(result, Ok(Value::Integer(6))));
   /// ```
   pub fn run(&mut self, entry_name: &str, args: impl IntoIterator<Item=Value>) -> Result<Value, RuntimeError> {
+    self.run_with(entry_name, args, Self::execute)
+  }
+
+  /// [`run`](Self::run), dispatching each instruction by matching on the bit fields of its
+  /// opcode instead of looking up its handler in the dispatch table
+  ///
+  /// Only for comparing the two ways of dispatching in benchmarks and tests.
+  #[doc(hidden)]
+  pub fn run_with_nested_dispatch(&mut self, entry_name: &str, args: impl IntoIterator<Item=Value>) -> Result<Value, RuntimeError> {
+    self.run_with(entry_name, args, Self::execute_nested)
+  }
+
+  fn run_with<E>(&mut self, entry_name: &str, args: impl IntoIterator<Item=Value>, execute: E) -> Result<Value, RuntimeError>
+  where
+    E: Fn(&mut Self, Instruction) -> Result<(), RuntimeError>,
+  {
+    if !self.call_stack.is_empty() {
+      return Err(RuntimeError::VmBusy);
+    }
     let entry_procedure = self.functions.get(entry_name)
       .ok_or_else(|| RuntimeError::MissingFunction)?.clone();
     self.initialize_with_values(entry_procedure, args)?;
-    self.execution_loop_infinite()?;
+    if let Err(error) = self.execution_loop_infinite(execute) {
+      self.abort();
+      return Err(error);
+    }
     Ok(self.get_result())
   }
 
+  /// Discards an unfinished computation, leaving the virtual machine ready to run again
+  ///
+  /// Globals, functions and natives are kept.
+  pub fn abort(&mut self) {
+    self.call_stack.clear();
+    self.register_stack.truncate(1); // bottom of register stack is VM result
+  }
+
+  /// Sets a callback that is called before each instruction is executed, with the index of
+  /// the instruction in its procedure's bytecode and the instruction itself.
+  ///
+  /// Replaces any hook that was already set.  Nothing extra is done per instruction when no
+  /// hook is set.
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use std::{rc::Rc, cell::Cell};
+  /// # use lualite::compile_program;
+  /// let mut vm = compile_program("function main() x = 1 return x + 1 end").unwrap();
+  /// let executed = Rc::new(Cell::new(0));
+  /// let counter = Rc::clone(&executed);
+  /// vm.set_trace_hook(Box::new(move |_pc, _instruction| counter.set(counter.get() + 1)));
+  /// vm.run_main().unwrap();
+  /// assert!(executed.get() > 0);
+  /// ```
+  pub fn set_trace_hook(&mut self, hook: TraceHook) {
+    self.tracer = Some(Tracer(hook));
+  }
+
+  /// Removes the hook set by [`set_trace_hook`](Self::set_trace_hook), returning it
+  pub fn clear_trace_hook(&mut self) -> Option<TraceHook> {
+    self.tracer.take().map(|Tracer(hook)| hook)
+  }
+
+  /// Runs the `main` function with no arguments, the same way the `lualite` binary does.
+  ///
+  /// Equivalent to `run("main", [])`.
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::{compile_program, runtime::Value};
+  /// let mut vm = compile_program("function main() return 42 end").unwrap();
+  /// assert_eq!(vm.run_main().unwrap(), Value::Integer(42));
+  /// ```
+  pub fn run_main(&mut self) -> Result<Value, RuntimeError> {
+    self.run(Self::ENTRY_NAME, [])
+  }
+
   /// Gets the return value of the entry function.
   ///
   /// This will always be [`Value::Nil`] if the entry function has not finished.
@@ -152,17 +490,87 @@ impl VirtualMachine {
     self.register_stack.get(0).cloned().unwrap_or_else(|| Value::Nil)
   }
 
+  /// Gets the return value of the entry function converted to `T`
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::{compile_program, runtime::RuntimeError};
+  /// let mut vm = compile_program("function main() return 42 end").unwrap();
+  /// vm.run_main().unwrap();
+  /// assert_eq!(vm.result_as::<i64>().unwrap(), 42);
+  /// assert!(matches!(vm.result_as::<String>(), Err(RuntimeError::WrongType { expected: "string", found: "integer" })));
+  /// ```
+  pub fn result_as<T: TryFrom<Value>>(&self) -> Result<T, T::Error> {
+    self.get_result().try_into()
+  }
+
+  /// Human readable snapshot of the call stack, for diagnosing a [`RuntimeError`]
+  ///
+  /// Frames are listed innermost first.  Each frame shows its function's name, the last
+  /// instruction it fetched (the one that failed, for the innermost frame of an execution
+  /// that errored), and the values of its registers.  Nothing is cleared when
+  /// [`execution_loop`](Self::execution_loop) returns an error, so the state can be dumped
+  /// afterwards, but [`run`](Self::run) aborts and leaves an empty call stack.
+  ///
+  /// # Example:
+  /// ```rust
+  /// # use lualite::{compile_program, runtime::InstructionCount};
+  /// let mut vm = compile_program("function main() x = 7 return x end").unwrap();
+  /// vm.initialize_with_values(vm.get_function("main").unwrap(), []).unwrap();
+  /// vm.execution_loop(InstructionCount::Limited(1)).unwrap();
+  /// let state = vm.dump_state();
+  /// assert!(state.contains("main"));
+  /// assert!(state.contains("= 7"));
+  /// ```
+  pub fn dump_state(&self) -> String {
+    use std::fmt::Write;
+    let mut state = String::new();
+    if self.call_stack.is_empty() {
+      state.push_str("call stack: (empty)\n");
+    }
+    for (depth, frame) in self.call_stack.iter().rev().enumerate() {
+      let name = self.functions.iter()
+        .find(|(_, procedure)| Rc::ptr_eq(procedure, &frame.procedure))
+        .map_or("<unnamed>", |(name, _)| name.as_str());
+      // writing to a `String` can't fail
+      let _ = writeln!(state, "frame {depth}: {name}");
+      match frame.fetched_index() {
+        Some(index) => {
+          let _ = match frame.procedure.bytecode.get(index) {
+            Some(instruction) => writeln!(state, "  at {index}: {instruction}"),
+            None => writeln!(state, "  at {index}: (past the end)"),
+          };
+        }
+        None => state.push_str("  not started\n"),
+      }
+      let registers = self.register_stack.iter()
+        .skip(frame.register_start)
+        .take(frame.procedure.register_count);
+      for (register, value) in registers.enumerate() {
+        let _ = writeln!(state, "  {} = {value}", RawRegister(register as u8));
+      }
+    }
+    state
+  }
+
   /// Sets the entry procedure of the virtual machine and sets its argument registers
   /// to the [`Value`]s in `args`.
   ///
   /// Does **not** begin execution, unlike [`run`](Self::run).
+  ///
+  /// Missing arguments are `nil`.  More arguments than the procedure's `max_args` is a
+  /// [`RuntimeError::TooManyArguments`], and leaves the virtual machine unchanged.
   pub fn initialize_with_values(&mut self,
     entry_procedure: Rc<Procedure>,
     args: impl IntoIterator<Item=Value>,
   ) -> Result<(), RuntimeError> {
+    let args: Vec<Value> = args.into_iter().collect();
+    if args.len() > entry_procedure.max_args {
+      return Err(RuntimeError::TooManyArguments { max_args: entry_procedure.max_args, given: args.len() });
+    }
     let frame_base = self.register_stack.len();
     self.register_stack.resize(frame_base + entry_procedure.register_count, Value::Nil);
-    for (arg_index, arg_value) in (1..).into_iter().zip(args.into_iter()) {
+    for (arg_index, arg_value) in (1..).zip(args) {
       let register_slot = self.register_stack.get_mut(frame_base + arg_index)
         .ok_or_else(|| RuntimeError::InvalidRegister)?;
       *register_slot = arg_value;
@@ -171,6 +579,7 @@ impl VirtualMachine {
       procedure: entry_procedure,
       register_start: frame_base,
       pc: 0,
+      fetched: None,
       return_index: 0, // bottom of the register stack (index 0) is VM result
     });
     Ok(())
@@ -191,7 +600,7 @@ impl VirtualMachine {
   /// end
   /// ";
   /// let (_, fn_decl) = parser::declaration::function_decl(source_code).expect("parse failed");
-  /// let procedure = compiler::compile_function(&fn_decl);
+  /// let procedure = compiler::compile_function(&fn_decl).unwrap();
   /// 
   /// let mut vm = VirtualMachine::new();
   /// // Initialize a call to: forever(10)
@@ -207,7 +616,7 @@ impl VirtualMachine {
   /// ```
   pub fn execution_loop(&mut self, limit: InstructionCount) -> Result<ExecutionStatus, RuntimeError> {
     match limit {
-      InstructionCount::Unlimited => self.execution_loop_infinite(),
+      InstructionCount::Unlimited => self.execution_loop_infinite(Self::execute),
       InstructionCount::Limited(count) => self.execution_loop_finite(count),
     }
   }
@@ -215,8 +624,10 @@ impl VirtualMachine {
   fn execution_loop_finite(&mut self, count: usize) -> Result<ExecutionStatus, RuntimeError> {
     for _ in 0..count {
       if let Some(top) = self.call_stack.last_mut() {
-        top.pc += 1;
-        let instruction = *top.procedure.bytecode.get(top.pc).ok_or_else(|| RuntimeError::InvalidPc)?;
+        let instruction = top.fetch()?;
+        if let (Some(Tracer(hook)), Some(index)) = (&mut self.tracer, top.fetched_index()) {
+          hook(index, instruction);
+        }
         self.execute(instruction)?;
       } else {
         return Ok(ExecutionStatus::Finished);
@@ -225,74 +636,120 @@ impl VirtualMachine {
     Ok(ExecutionStatus::Unfinished)
   }
 
-  fn execution_loop_infinite(&mut self) -> Result<ExecutionStatus, RuntimeError> {
+  fn execution_loop_infinite<E>(&mut self, execute: E) -> Result<ExecutionStatus, RuntimeError>
+  where
+    E: Fn(&mut Self, Instruction) -> Result<(), RuntimeError>,
+  {
     while let Some(top) = self.call_stack.last_mut() {
-      top.pc += 1;
-      let instruction = *top.procedure.bytecode.get(top.pc).ok_or_else(|| RuntimeError::InvalidPc)?;
-      self.execute(instruction)?;
+      let instruction = top.fetch()?;
+      if let (Some(Tracer(hook)), Some(index)) = (&mut self.tracer, top.fetched_index()) {
+        hook(index, instruction);
+      }
+      execute(self, instruction)?;
     }
     Ok(ExecutionStatus::Finished)
   }
 }
 
+/// Executes one instruction whose opcode byte is already known
+type Handler = fn(&mut VirtualMachine, Instruction) -> Result<(), RuntimeError>;
+
+/// Handler for every possible opcode byte
+///
+/// Looking up the handler once replaces matching on the super code, then the subcode,
+/// then the jump reason for every executed instruction.
+static DISPATCH_TABLE: [Handler; 256] = build_dispatch_table();
+
+const fn build_dispatch_table() -> [Handler; 256] {
+  let mut table: [Handler; 256] = [VirtualMachine::execute_unimplemented; 256];
+  let mut opcode = 0;
+  while opcode < table.len() {
+    table[opcode] = handler_for(opcode as u32);
+    opcode += 1;
+  }
+  table
+}
+
+const fn handler_for(opcode: u32) -> Handler {
+  use misc::jump_subcode::{Reason, Special};
+  match opcode >> SuperCode::OFFSET & SuperCode::MASK {
+    0b_00 => match opcode >> misc::Subcode::OFFSET & misc::Subcode::MASK {
+      0b_00 => match opcode >> Reason::OFFSET & Reason::MASK {
+        0b_00 => match opcode >> Special::OFFSET & Special::MASK {
+          0b_00 => VirtualMachine::execute_misc_nop,
+          0b_01 => VirtualMachine::execute_misc_return,
+          _ => VirtualMachine::execute_unimplemented,
+        },
+        0b_01 => VirtualMachine::execute_misc_jump,
+        _ => VirtualMachine::execute_misc_conditional_jump,
+      },
+      0b_01 => VirtualMachine::execute_misc_move,
+      0b_10 => VirtualMachine::execute_misc_call,
+      _ => VirtualMachine::execute_unimplemented,
+    },
+    0b_01 => VirtualMachine::execute_index,
+    0b_10 => VirtualMachine::execute_comparison,
+    _ => VirtualMachine::execute_arithmetic,
+  }
+}
+
 // Execution instructions
 impl VirtualMachine {
   /// Execute a single bytecode instruction
   pub fn execute(&mut self, instruction: Instruction) -> Result<(), RuntimeError> {
-    match SuperCode::from(instruction) {
-      SuperCode::Misc => self.execute_misc(instruction),
-      SuperCode::Index => self.execute_index(instruction),
-      SuperCode::Comparison => self.execute_comparison(instruction),
-      SuperCode::Arithmetic => self.execute_arithmetic(instruction),
+    if let Some(gas) = &mut self.gas {
+      *gas = gas.checked_sub(self.gas_model.cost(instruction)).ok_or(RuntimeError::OutOfGas)?;
     }
+    DISPATCH_TABLE[(instruction.0 & 0xff) as usize](self, instruction)
   }
 
-  /// Execute an instruction from the misc category
-  ///
-  /// The 2 least significant bits have to match `bytecode::opcode::SuperCode::Misc`.
-  fn execute_misc(&mut self, instruction: Instruction) -> Result<(), RuntimeError> {
-    use misc::Subcode;
-    match Subcode::from(instruction) {
-      Subcode::Jump => self.execute_misc_jump(instruction)?,
-      Subcode::Move => self.execute_misc_move(instruction)?,
-      Subcode::Call => self.execute_misc_call(instruction)?,
-      Subcode::Interrupt => unimplemented!(),
+  /// [`execute`](Self::execute) without the dispatch table, the handler is found by
+  /// matching on the opcode for every instruction
+  fn execute_nested(&mut self, instruction: Instruction) -> Result<(), RuntimeError> {
+    if let Some(gas) = &mut self.gas {
+      *gas = gas.checked_sub(self.gas_model.cost(instruction)).ok_or(RuntimeError::OutOfGas)?;
     }
+    handler_for(instruction.0 & 0xff)(self, instruction)
+  }
+
+  /// Placeholder for opcodes reserved for future instructions (interrupts, `Xa`, `Xb`)
+  fn execute_unimplemented(&mut self, instruction: Instruction) -> Result<(), RuntimeError> {
+    Err(RuntimeError::InvalidInstruction(instruction))
+  }
+
+  /// Execute a no-op instruction from the misc category
+  fn execute_misc_nop(&mut self, _instruction: Instruction) -> Result<(), RuntimeError> {
     Ok(())
   }
 
-  /// Execute a jump instruction from the misc category
-  ///
-  /// The 2 least significant bits have to match `bytecode::opcode::SuperCode::Misc` and the next 2 bits
-  /// have to match `bytecode::opcode::misc::Subcode::Jump`.
+  /// Execute a return instruction from the misc category
+  fn execute_misc_return(&mut self, _instruction: Instruction) -> Result<(), RuntimeError> {
+    let stack_frame = self.call_stack.pop().ok_or_else(|| RuntimeError::EmptyCallStack)?;
+    let value = self.register_stack.get(stack_frame.register_start).cloned()
+      .ok_or(RuntimeError::InvalidRegister)?;
+    *self.register_stack.get_mut(stack_frame.return_index).ok_or(RuntimeError::InvalidRegister)? = value;
+    self.register_stack.resize(stack_frame.register_start, Value::Nil);
+    Ok(())
+  }
+
+  /// Execute an unconditional jump instruction from the misc category
   fn execute_misc_jump(&mut self, instruction: Instruction) -> Result<(), RuntimeError> {
+    self.set_pc(InstructionPointer::from_both_operands(instruction))
+  }
+
+  /// Execute a conditional jump instruction from the misc category
+  ///
+  /// The jump reason has to be either `bytecode::opcode::misc::jump_subcode::Reason::IfFalse`
+  /// or `Reason::IfTrue`.
+  fn execute_misc_conditional_jump(&mut self, instruction: Instruction) -> Result<(), RuntimeError> {
     use misc::jump_subcode::{Reason, ConditionType};
-    match Reason::from(instruction) {
-      Reason::Special => {
-        use misc::jump_subcode::Special;
-        match Special::from(instruction) {
-          Special::NoOp => (),
-          Special::Return => {
-            let stack_frame = self.call_stack.pop().ok_or_else(|| RuntimeError::EmptyCallStack)?;
-            self.register_stack[stack_frame.return_index]
-              = self.register_stack[stack_frame.register_start].clone();
-            self.register_stack.resize(stack_frame.register_start, Value::Nil);
-          }
-          Special::Xa => unimplemented!(),
-          Special::Xb => unimplemented!(),
-        }
-      }
-      Reason::Always => self.set_pc(InstructionPointer::from_both_operands(instruction))?,
-      reason @ (Reason::IfFalse | Reason::IfTrue) => {
-        let flag = match ConditionType::from(instruction) {
-          ConditionType::Register => self.register(RawRegister::from_destination(instruction))?.clone(),
-          ConditionType::Global => todo!(),
-        };
-        if (matches!(reason, Reason::IfFalse) && flag == Value::Boolean(false)) ||
-           (matches!(reason, Reason::IfTrue)  && flag == Value::Boolean(true)) {
-          self.set_pc(InstructionPointer::from_both_operands(instruction))?;
-        }
-      }
+    let flag = match ConditionType::from(instruction) {
+      ConditionType::Register => self.register(RawRegister::from_destination(instruction))?.is_truthy(),
+      ConditionType::Global => self.global(Global::from_destination(instruction))?.is_truthy(),
+    };
+    let jump_when = matches!(Reason::from(instruction), Reason::IfTrue);
+    if flag == jump_when {
+      self.set_pc(InstructionPointer::from_both_operands(instruction))?;
     }
     Ok(())
   }
@@ -302,16 +759,19 @@ impl VirtualMachine {
   /// The 2 least significant bits have to match `bytecode::opcode::SuperCode::Misc` and the next 2 bits
   /// have to match `bytecode::opcode::misc::Subcode::Move`.
   fn execute_misc_move(&mut self, instruction: Instruction) -> Result<(), RuntimeError> {
-    use misc::move_subcode::{DestinationType, SourceType};
+    use misc::move_subcode::{DestinationType, SourceType, ImmediateType};
     let source = match SourceType::from(instruction) {
       SourceType::Register => self.register(RawRegister::from_first(instruction))?.clone(),
-      SourceType::Global => todo!(),
-      SourceType::Immediate => Value::Integer(Immediate::from_first(instruction).0 as i64),
+      SourceType::Global => self.global(Global::from_first(instruction))?.clone(),
+      SourceType::Immediate => match ImmediateType::from(instruction) {
+        ImmediateType::Integer => Value::Integer(Immediate::from_first(instruction).0 as i64),
+        ImmediateType::Boolean => Value::Boolean(Immediate::from_first(instruction).0 != 0),
+      },
       SourceType::Constant => Value::from(self.constant(ConstantKey::from_first(instruction))?.clone()),
     };
     match DestinationType::from(instruction) {
       DestinationType::Register => *self.register_mut(RawRegister::from_destination(instruction))? = source,
-      DestinationType::Global => todo!(),
+      DestinationType::Global => *self.global_mut(Global::from_destination(instruction))? = source,
     }
     Ok(())
   }
@@ -326,11 +786,56 @@ impl VirtualMachine {
     let return_register = RawRegister::from_destination(instruction);
     let function_key = FunctionKey::from_first(instruction);
     let arg_start = RawRegister::from_second(instruction);
-    let arg_iter = (arg_start.0..(arg_start.0 + arg_count.0)).into_iter().map(|reg_num| RawRegister(reg_num));
     let stack_frame = self.call_stack.last().ok_or_else(|| RuntimeError::EmptyCallStack)?;
-    let procedure_name = stack_frame.procedure.functions.get(function_key.0 as usize)
+    let caller = Rc::clone(&stack_frame.procedure);
+    let procedure_name = caller.functions.get(function_key.0 as usize)
       .ok_or_else(|| RuntimeError::MissingFunction)?;
-    let procedure = Rc::clone(self.functions.get(procedure_name).ok_or_else(|| RuntimeError::MissingFunction)?);
+    self.call_by_name(procedure_name, return_register, arg_start, arg_count.0)
+  }
+
+  /// Calls the function, native, or builtin named `name` with the `arg_count` registers
+  /// starting at `arg_start` as its arguments
+  ///
+  /// The `call` builtin calls the [`Value::Function`] in its first argument with the rest.
+  fn call_by_name(&mut self, name: &str, return_register: RawRegister, arg_start: RawRegister, arg_count: u8) -> Result<(), RuntimeError> {
+    // the arguments can end with the last register, but can't wrap around past it
+    let arg_end = arg_start.0 as u16 + arg_count as u16;
+    if arg_end > u8::MAX as u16 + 1 {
+      return Err(RuntimeError::InvalidRegister);
+    }
+    let arg_iter = (arg_start.0 as u16..arg_end).map(|reg_num| RawRegister(reg_num as u8));
+    let stack_frame = self.call_stack.last().ok_or(RuntimeError::EmptyCallStack)?;
+    let return_index = stack_frame.register_start + return_register.0 as usize;
+    let procedure = match self.functions.get(name) {
+      Some(procedure) => Rc::clone(procedure),
+      None => {
+        let native = match (self.natives.get(name).copied(), name) {
+          (Some(native), _) => native,
+          (None, "call" | builtin::CALL) => {
+            let callee = match arg_count {
+              0 => Value::Nil,
+              _ => self.register(arg_start)?.clone(),
+            };
+            return match callee {
+              Value::Function(callee) =>
+                // with no arguments left the start is never read, so it's fine for it to stay put
+                self.call_by_name(&callee, return_register, RawRegister(arg_start.0.saturating_add(1)), arg_count - 1),
+              other => Err(RuntimeError::NotCallable { type_name: other.type_name() }),
+            };
+          }
+          (None, _) => return Err(RuntimeError::MissingFunction),
+        };
+        let args = arg_iter.map(|arg_register| self.register(arg_register).cloned())
+          .collect::<Result<Vec<Value>, RuntimeError>>()?;
+        let result = native(&mut native::Context::new(&mut *self.output.0), &args)?;
+        *self.register_stack.get_mut(return_index).ok_or_else(|| RuntimeError::InvalidRegister)? = result;
+        return Ok(());
+      }
+    };
+    // fewer arguments are fine, the rest of the parameters are `nil` (or their defaults)
+    if arg_count as usize > procedure.max_args {
+      return Err(RuntimeError::TooManyArguments { max_args: procedure.max_args, given: arg_count as usize });
+    }
     let frame_base = self.register_stack.len();
     self.register_stack.resize(frame_base + procedure.register_count, Value::Nil);
     for (arg_index, arg_register) in (1..).into_iter().zip(arg_iter.into_iter()) {
@@ -343,7 +848,8 @@ impl VirtualMachine {
       procedure,
       register_start: frame_base,
       pc: 0,
-      return_index: stack_frame.register_start + return_register.0 as usize,
+      fetched: None,
+      return_index,
     });
     Ok(())
   }
@@ -354,45 +860,79 @@ impl VirtualMachine {
   fn execute_index(&mut self, instruction: Instruction) -> Result<(), RuntimeError> {
     use index::On;
     let decoded = index::decode(instruction);
-    let destination_register = match decoded.destination {
-      WildDestination::Register(register) => register,
-      WildDestination::Global(_global) => todo!(),
-    };
     let source_value = match decoded.source {
       WildSource::Register(register) => self.register(register)?.clone(),
-      WildSource::Global(_global) => todo!(),
+      WildSource::Global(global) => self.global(global)?.clone(),
       WildSource::Immediate(immediate) => Value::Integer(immediate.0 as i64),
       WildSource::Constant(constant) => Value::from(self.constant(constant)?.clone()),
     };
     let index_value = match decoded.index {
       WildSource::Register(register) => self.register(register)?.clone(),
-      WildSource::Global(_global) => todo!(),
+      WildSource::Global(global) => self.global(global)?.clone(),
       WildSource::Immediate(immediate) => Value::Integer(immediate.0 as i64),
       WildSource::Constant(constant) => Value::from(self.constant(constant)?.clone()),
     };
     match decoded.index_on {
       On::Source => {
-        *self.register_mut(destination_register)? = source_value.get(index_value);
+        *self.destination_mut(decoded.destination)? = source_value.get(index_value)?;
       }
       On::Destination => {
-        let destination = self.register_mut(destination_register)?;
-        destination.set(index_value, source_value);
+        let destination = self.destination_mut(decoded.destination)?;
+        destination.set(index_value, source_value)?;
       }
     }
     Ok(())
   }
 
+  /// Both sources of a binary instruction when they're integers, without cloning them
+  ///
+  /// Returns the wild source first.  Only registers and immediates are checked, anything
+  /// else is left to the general path.
+  fn integer_sources(&self, wild: &WildSource<RawRegister>, register: RawRegister) -> Result<Option<(i64, i64)>, RuntimeError> {
+    let wild = match wild {
+      WildSource::Register(wild) => match self.register(*wild)? {
+        Value::Integer(int) => *int,
+        _ => return Ok(None),
+      },
+      WildSource::Immediate(immediate) => immediate.0 as i64,
+      _ => return Ok(None),
+    };
+    match self.register(register)? {
+      Value::Integer(int) => Ok(Some((wild, *int))),
+      _ => Ok(None),
+    }
+  }
+
   /// Execute an instruction from the comparison category
   ///
   /// The 2 least significant bits have to match `bytecode::opcode::SuperCode::Comparison`.
   fn execute_comparison(&mut self, instruction: Instruction) -> Result<(), RuntimeError> {
     use comparison::{Subcode, Sources};
     let decoded = comparison::decode(instruction);
+    let integers = match &decoded.sources {
+      Sources::FirstIsWild(first, second) => self.integer_sources(first, *second)?,
+      Sources::SecondIsWild(first, second) => self.integer_sources(second, *first)?.map(|(s, f)| (f, s)),
+    };
+    if let Some((first, second)) = integers {
+      let result = match decoded.subcode {
+        Subcode::Eq => Some(first == second),
+        Subcode::Ne => Some(first != second),
+        Subcode::Lt => Some(first < second),
+        Subcode::Gt => Some(first > second),
+        Subcode::Le => Some(first <= second),
+        Subcode::Ge => Some(first >= second),
+        Subcode::Xa | Subcode::Xb => None,
+      };
+      if let Some(result) = result {
+        *self.register_mut(decoded.destination)? = Value::Boolean(result);
+        return Ok(());
+      }
+    }
     let (first, second) = match decoded.sources {
       Sources::FirstIsWild(first, second) => {
         let first = match first {
           WildSource::Register(register) => self.register(register)?.clone(),
-          WildSource::Global(_global) => todo!(),
+          WildSource::Global(global) => self.global(global)?.clone(),
           WildSource::Immediate(immediate) => Value::Integer(immediate.0 as i64),
           WildSource::Constant(constant) => Value::from(self.constant(constant)?.clone()),
         };
@@ -402,7 +942,7 @@ impl VirtualMachine {
       Sources::SecondIsWild(first, second) => {
         let second = match second {
           WildSource::Register(register) => self.register(register)?.clone(),
-          WildSource::Global(_global) => todo!(),
+          WildSource::Global(global) => self.global(global)?.clone(),
           WildSource::Immediate(immediate) => Value::Integer(immediate.0 as i64),
           WildSource::Constant(constant) => Value::from(self.constant(constant)?.clone()),
         };
@@ -417,8 +957,7 @@ impl VirtualMachine {
       Subcode::Gt => Value::from(first > second),
       Subcode::Le => Value::from(first <= second),
       Subcode::Ge => Value::from(first >= second),
-      Subcode::Xa => unimplemented!(),
-      Subcode::Xb => unimplemented!(),
+      Subcode::Xa | Subcode::Xb => return Err(RuntimeError::InvalidInstruction(instruction)),
     };
     Ok(())
   }
@@ -429,11 +968,31 @@ impl VirtualMachine {
   fn execute_arithmetic(&mut self, instruction: Instruction) -> Result<(), RuntimeError> {
     use arithmetic::{Subcode, Sources};
     let decoded = arithmetic::decode(instruction);
+    let integers = match &decoded.sources {
+      Sources::FirstIsWild(first, second) => self.integer_sources(first, *second)?,
+      Sources::SecondIsWild(first, second) => self.integer_sources(second, *first)?.map(|(s, f)| (f, s)),
+    };
+    if let Some((first, second)) = integers {
+      // anything that doesn't give an integer, or overflows, takes the general path below
+      let result = match decoded.subcode {
+        Subcode::Add => first.checked_add(second),
+        Subcode::Sub => first.checked_sub(second),
+        Subcode::Mul => first.checked_mul(second),
+        Subcode::Rem => super::value::integer_rem(first, second),
+        Subcode::FloorDiv => super::value::integer_floor_div(first, second),
+        Subcode::Neg => first.checked_neg(),
+        Subcode::Div | Subcode::Pow => None,
+      };
+      if let Some(result) = result {
+        *self.register_mut(decoded.destination)? = Value::Integer(result);
+        return Ok(());
+      }
+    }
     let (first, second) = match decoded.sources {
       Sources::FirstIsWild(first, second) => {
         let first = match first {
           WildSource::Register(register) => self.register(register)?.clone(),
-          WildSource::Global(_global) => todo!(),
+          WildSource::Global(global) => self.global(global)?.clone(),
           WildSource::Immediate(immediate) => Value::Integer(immediate.0 as i64),
           WildSource::Constant(constant) => Value::from(self.constant(constant)?.clone()),
         };
@@ -443,7 +1002,7 @@ impl VirtualMachine {
       Sources::SecondIsWild(first, second) => {
         let second = match second {
           WildSource::Register(register) => self.register(register)?.clone(),
-          WildSource::Global(_global) => todo!(),
+          WildSource::Global(global) => self.global(global)?.clone(),
           WildSource::Immediate(immediate) => Value::Integer(immediate.0 as i64),
           WildSource::Constant(constant) => Value::from(self.constant(constant)?.clone()),
         };
@@ -457,9 +1016,9 @@ impl VirtualMachine {
       Subcode::Mul => first * second,
       Subcode::Div => first / second,
       Subcode::Rem => first % second,
-      Subcode::Pow => unimplemented!(),
-      Subcode::Rot => unimplemented!(),
-      Subcode::Log => unimplemented!(),
+      Subcode::Pow => first.pow(second),
+      Subcode::Neg => -first,
+      Subcode::FloorDiv => first.floor_div(second),
     };
     Ok(())
   }
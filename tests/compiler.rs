@@ -0,0 +1,513 @@
+use lualite::{parser, compiler};
+use lualite::compiler::CompileError;
+use lualite::bytecode::constant_value::ConstantValue;
+use lualite::bytecode::opcode::{SuperCode, arithmetic};
+
+#[test]
+fn negation_is_a_single_instruction() {
+  let (_, fn_decl) = parser::declaration::function_decl("function negate(x) return -x end").unwrap();
+  let procedure = compiler::compile_function(&fn_decl).unwrap();
+
+  // nop, neg, ret
+  assert_eq!(procedure.bytecode.len(), 3);
+  let neg = procedure.bytecode[1];
+  assert!(matches!(SuperCode::from(neg), SuperCode::Arithmetic));
+  assert!(matches!(arithmetic::Subcode::from(neg), arithmetic::Subcode::Neg));
+  assert_eq!(neg.to_string(), "neg   R0 = -R1");
+}
+
+#[test]
+fn link_reports_unresolved_function() {
+  let source_code = r"
+function main()
+  return helper(1) + later(2)
+end
+
+function later(x)
+  return x
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let functions = compiler::compile_declarations(declarations.iter()).unwrap();
+
+  let error = compiler::link(&functions, []).unwrap_err();
+  assert!(matches!(error, CompileError::UnresolvedFunction(name) if name == "helper"));
+  // functions registered outside of the compiled program also resolve
+  assert!(compiler::link(&functions, ["helper"]).is_ok());
+}
+
+#[test]
+fn unsupported_construct_is_an_error() {
+  let source_code = r"
+function main(a, b)
+  return (a + b)(1)
+end
+";
+  let (_, fn_decl) = parser::declaration::function_decl(source_code).unwrap();
+  let error = compiler::compile_function(&fn_decl).unwrap_err();
+  assert!(matches!(error, CompileError::Unsupported { line: None, .. }));
+}
+
+#[test]
+fn static_needing_runtime_value_is_an_error() {
+  let source_code = r"
+static X = 1.5 * 2.0
+static Y = compute()
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let error = compiler::compile_statics(declarations.iter()).unwrap_err();
+  assert!(matches!(error, CompileError::NonConstStatic(name) if name == "Y"));
+}
+
+#[test]
+fn small_calls_reuse_temporaries() {
+  let zero_arg_calls = "function main()\n".to_owned() + &"  x = f()\n".repeat(50) + "end";
+  let (_, fn_decl) = parser::declaration::function_decl(&zero_arg_calls).unwrap();
+  let procedure = compiler::compile_function(&fn_decl).unwrap();
+  // return register and `x`
+  assert_eq!(procedure.register_count, 2);
+
+  let one_arg_calls = "function main(y)\n".to_owned() + &"  x = f(y)\n".repeat(50) + "end";
+  let (_, fn_decl) = parser::declaration::function_decl(&one_arg_calls).unwrap();
+  let procedure = compiler::compile_function(&fn_decl).unwrap();
+  // return register, `y`, `x`, and a single argument temporary
+  assert_eq!(procedure.register_count, 4);
+}
+
+#[test]
+fn constant_array_is_a_single_load() {
+  use lualite::bytecode::constant_value::ConstantValue;
+  let (_, fn_decl) = parser::declaration::function_decl("function numbers() return [1, 2, 3] end").unwrap();
+  let procedure = compiler::compile_function(&fn_decl).unwrap();
+
+  // nop, mov, ret
+  assert_eq!(procedure.bytecode.len(), 3);
+  let expected = ConstantValue::Array(vec![
+    ConstantValue::Integer(1),
+    ConstantValue::Integer(2),
+    ConstantValue::Integer(3),
+  ]);
+  assert_eq!(procedure.constants, vec![expected].into());
+  assert_eq!(procedure.constant_values().next().unwrap().to_string(), "[1_i64, 2_i64, 3_i64]");
+}
+
+#[test]
+fn simplify_option_folds_integer_literals() {
+  use lualite::compiler::CompileOptions;
+  let options = CompileOptions { simplify: true, ..CompileOptions::default() };
+  let compile = |source_code: &str| {
+    let (_, fn_decl) = parser::declaration::function_decl(source_code).unwrap();
+    compiler::compile_function_with_options(&fn_decl, &[], &options).unwrap()
+  };
+
+  // nop, mul, ret
+  let procedure = compile("function f(x) return x * (60 * 60 * 24) end");
+  assert_eq!(procedure.bytecode.len(), 3);
+  assert_eq!(procedure.constants, vec![lualite::bytecode::constant_value::ConstantValue::Integer(86400)].into());
+
+  // identities that depend on the type of `x` are left alone
+  // as are overflowing results, which wrap or promote at runtime
+  let unchanged = [
+    "function f(x) return x * 1 end",
+    "function f(x) return x * 2 end",
+    "function f(x) return x ^ 2 end",
+    "function f(x) return x - 0 end",
+    "function f() return 9223372036854775807 + 1 end",
+  ];
+  for source_code in unchanged {
+    let (_, fn_decl) = parser::declaration::function_decl(source_code).unwrap();
+    assert_eq!(compile(source_code), compiler::compile_function(&fn_decl).unwrap(), "{source_code}");
+  }
+}
+
+#[test]
+fn decoded_gcd_instructions() {
+  use lualite::bytecode::opcode::{DecodedInstruction, comparison};
+  let source_code = r"
+function gcd(a, b)
+  while a != b do
+    if a > b then
+      a = a - b
+    else
+      b = b - a
+    end
+  end
+  return a
+end
+";
+  let (_, fn_decl) = parser::declaration::function_decl(source_code).unwrap();
+  let procedure = compiler::compile_function(&fn_decl).unwrap();
+
+  let (mut jumps, mut conditional_jumps, mut comparisons, mut subtractions, mut returns) = (0, 0, 0, 0, 0);
+  for decoded in procedure.decoded_instructions() {
+    match decoded {
+      DecodedInstruction::Jump(_) => jumps += 1,
+      DecodedInstruction::JumpIfFalse { target, .. } => {
+        assert!((target.0 as usize) < procedure.bytecode.len());
+        conditional_jumps += 1;
+      }
+      DecodedInstruction::Comparison(comparison) => {
+        assert!(matches!(comparison.subcode, comparison::Subcode::Ne | comparison::Subcode::Gt));
+        comparisons += 1;
+      }
+      DecodedInstruction::Arithmetic(arithmetic) => {
+        assert!(matches!(arithmetic.subcode, arithmetic::Subcode::Sub));
+        subtractions += 1;
+      }
+      DecodedInstruction::Return => returns += 1,
+      DecodedInstruction::NoOp | DecodedInstruction::Move(_) => (),
+      other => panic!("unexpected instruction {other:?}"),
+    }
+  }
+  assert_eq!((jumps, conditional_jumps, comparisons, subtractions, returns), (2, 2, 2, 2, 1));
+}
+
+#[test]
+fn gcd_disassembly() {
+  let source_code = r"
+function gcd(a, b)
+  while a != b do
+    if a > b then
+      a = a - b
+    else
+      b = b - a
+    end
+  end
+  return a
+end
+";
+  let (_, fn_decl) = parser::declaration::function_decl(source_code).unwrap();
+  let procedure = compiler::compile_function(&fn_decl).unwrap();
+  let disassembly = procedure.to_string();
+  // unconditional jumps keep the padding used to align conditions
+  let expected = [
+    "registers: 4",
+    "arg count: 2",
+    "constant table: (empty)",
+    "function table: (empty)",
+    "bytecode:",
+    "     0  nop",
+    "     1  ne    R3 = R1 != R2",
+    "     2  jmp   ip 8         if !R3",
+    "     3  gt    R3 = R1 > R2",
+    "     4  jmp   ip 6         if !R3",
+    "     5  sub   R1 = R1 - R2",
+    "     6  jmp   ip 7       ",
+    "     7  sub   R2 = R2 - R1",
+    "     8  jmp   ip 0       ",
+    "     9  mov   R0 = R1",
+    "    10  ret",
+  ];
+  assert_eq!(disassembly.lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn calling_too_many_distinct_functions_is_an_error() {
+  let calling = |count: usize| {
+    let calls: Vec<String> = (0..count).map(|i| format!("  f{i}()\n")).collect();
+    format!("function main()\n{}end", calls.concat())
+  };
+
+  let source_code = calling(256);
+  let (_, fn_decl) = parser::declaration::function_decl(&source_code).unwrap();
+  let procedure = compiler::compile_function(&fn_decl).unwrap();
+  assert_eq!(procedure.function_table().len(), 256);
+
+  let source_code = calling(257);
+  let (_, fn_decl) = parser::declaration::function_decl(&source_code).unwrap();
+  let error = compiler::compile_function(&fn_decl).unwrap_err();
+  assert!(matches!(error, CompileError::TooManyCalledFunctions));
+}
+
+#[test]
+fn using_too_many_distinct_constants_is_an_error() {
+  let summing = |count: usize| {
+    let additions: Vec<String> = (0..count).map(|i| format!("  s = s + {}\n", 1000 + i)).collect();
+    format!("function main()\n  s = 0\n{}  return s\nend", additions.concat())
+  };
+
+  let source_code = summing(256);
+  let (_, fn_decl) = parser::declaration::function_decl(&source_code).unwrap();
+  let procedure = compiler::compile_function(&fn_decl).unwrap();
+  assert_eq!(procedure.constants.len(), 256);
+
+  let source_code = summing(300);
+  let (_, fn_decl) = parser::declaration::function_decl(&source_code).unwrap();
+  let error = compiler::compile_function(&fn_decl).unwrap_err();
+  assert!(matches!(error, CompileError::TooManyConstants));
+}
+
+#[test]
+fn booleans_are_immediates() {
+  use lualite::bytecode::constant_value::ConstantValue;
+  use lualite::bytecode::opcode::{DecodedInstruction, misc::move_subcode::{DecodedMove, ImmediateType}};
+  use lualite::bytecode::operand::{Immediate, WildSource};
+  let source_code = r"
+function flags(x)
+  a = true
+  n = 1
+  if x == false then
+    a = false
+  end
+  return [a, x != true, n]
+end
+";
+  let (_, fn_decl) = parser::declaration::function_decl(source_code).unwrap();
+  let procedure = compiler::compile_function(&fn_decl).unwrap();
+  // the only constant is the template the array is built from
+  assert_eq!(procedure.constants, vec![ConstantValue::Array(vec![ConstantValue::Nil, ConstantValue::Nil, ConstantValue::Nil])].into());
+  let disassembly = procedure.to_string();
+  assert!(disassembly.contains("= true"), "{disassembly}");
+  assert!(disassembly.contains("= false"), "{disassembly}");
+
+  // `true` and `1` are both an immediate 1, told apart by their immediate type
+  let immediate_moves: Vec<(i8, ImmediateType)> = procedure.decoded_instructions()
+    .filter_map(|decoded| match decoded {
+      DecodedInstruction::Move(DecodedMove { source: WildSource::Immediate(Immediate(value)), immediate_type, .. }) =>
+        Some((value, immediate_type)),
+      _ => None,
+    })
+    .collect();
+  assert!(immediate_moves.contains(&(1, ImmediateType::Boolean)), "{immediate_moves:?}");
+  assert!(immediate_moves.contains(&(0, ImmediateType::Boolean)), "{immediate_moves:?}");
+  assert!(immediate_moves.contains(&(1, ImmediateType::Integer)), "{immediate_moves:?}");
+}
+
+#[test]
+fn operand_keys_honor_width_and_alignment() {
+  use lualite::bytecode::operand::{ConstantKey, FunctionKey};
+  assert_eq!(format!("{:>6}", ConstantKey(3)), "    &3");
+  assert_eq!(format!("{:<6}|", ConstantKey(42)), "&42   |");
+  assert_eq!(format!("{:^6}", ConstantKey(255)), " &255 ");
+  assert_eq!(format!("{}", ConstantKey(0)), "&0");
+  assert_eq!(format!("{:>5}", FunctionKey(10)), "  F10");
+  assert_eq!(format!("{:-<4}", FunctionKey(7)), "F7--");
+}
+
+#[test]
+fn float_constants_dedup_by_bits() {
+  use lualite::ast::FloatLiteral;
+  use lualite::bytecode::constant_value::ConstantValue;
+  use lualite::compiler::function::FunctionCompiler;
+  let mut fc = FunctionCompiler::with_parameters(&[]);
+  let negative_zero = fc.constant_for_float(&FloatLiteral(-0.0)).unwrap();
+  assert_eq!(fc.constant_for_float(&FloatLiteral(-0.0)).unwrap().0, negative_zero.0);
+  let zero = fc.constant_for_float(&FloatLiteral(0.0)).unwrap();
+  assert_ne!(zero.0, negative_zero.0);
+  let nan = fc.constant_for_float(&FloatLiteral(f64::NAN)).unwrap();
+  assert_eq!(fc.constant_for_float(&FloatLiteral(f64::NAN)).unwrap().0, nan.0);
+  let constants = fc.finish().constants.clone();
+  assert_eq!(constants.len(), 3);
+  assert_eq!(constants.get(nan), Some(&ConstantValue::Float(f64::NAN)));
+  assert_ne!(ConstantValue::Float(0.0), ConstantValue::Float(-0.0));
+  assert_eq!(ConstantValue::Array(vec![ConstantValue::Float(f64::NAN)]), ConstantValue::Array(vec![ConstantValue::Float(f64::NAN)]));
+}
+
+#[test]
+fn validate_rejects_out_of_range_operands() {
+  use lualite::bytecode::{self, Procedure, Code, ProcedureError, opcode::misc::call_subcode::ArgCount};
+  use lualite::bytecode::constant_value::ConstantValue;
+  use lualite::bytecode::operand::{RawRegister, ConstantKey, FunctionKey, WildSource};
+  let procedure = |bytecode: Vec<bytecode::Instruction>| Procedure::new("hand_built", Code {
+    bytecode,
+    register_count: 2,
+    max_args: 0,
+    constants: vec![ConstantValue::Integer(1000)].into(),
+    functions: vec!["f".to_owned()],
+    leading_nop: true,
+  });
+  let constant = |key| WildSource::<RawRegister>::Constant(ConstantKey(key));
+
+  let valid = procedure(vec![
+    bytecode::nop(),
+    bytecode::mov(RawRegister(1).into(), constant(0)),
+    bytecode::call(ArgCount(1), RawRegister(0), FunctionKey(0), RawRegister(1)),
+    bytecode::ret(),
+  ]);
+  assert_eq!(valid.validate(), Ok(()));
+
+  let bad_constant = procedure(vec![bytecode::nop(), bytecode::mov(RawRegister(0).into(), constant(1))]);
+  assert_eq!(bad_constant.validate(), Err(ProcedureError::ConstantOutOfRange { ip: 1, key: 1 }));
+
+  let bad_function = procedure(vec![bytecode::call(ArgCount(0), RawRegister(0), FunctionKey(1), RawRegister(0))]);
+  assert_eq!(bad_function.validate(), Err(ProcedureError::FunctionOutOfRange { ip: 0, key: 1 }));
+
+  let bad_register = procedure(vec![bytecode::neg(RawRegister(0), RawRegister(2))]);
+  assert_eq!(bad_register.validate(), Err(ProcedureError::RegisterOutOfRange { ip: 0, register: 2 }));
+
+  // the last argument of the call doesn't fit
+  let bad_argument = procedure(vec![bytecode::call(ArgCount(2), RawRegister(0), FunctionKey(0), RawRegister(1))]);
+  assert_eq!(bad_argument.validate(), Err(ProcedureError::RegisterOutOfRange { ip: 0, register: 2 }));
+}
+
+#[test]
+fn procedures_know_their_own_name() {
+  let source_code = r"
+function countdown(n)
+  if n > 0 then
+    return countdown(n - 1)
+  end
+  return n
+end
+
+function outer(x)
+  function inner(y)
+    return y > 0 ? inner(y - 1) : y
+  end
+  return inner(x) + countdown(x)
+end
+";
+  let (_, countdown) = parser::declaration::function_decl(source_code.trim_start()).unwrap();
+  let procedure = compiler::compile_function(&countdown).unwrap();
+  assert_eq!(procedure.name, "countdown");
+  assert!(procedure.is_recursive());
+
+  let declarations = parser::parse_program(source_code).unwrap();
+  let procedures = compiler::compile_declarations(&declarations).unwrap();
+  let recursive: Vec<(&str, bool)> = procedures.iter()
+    .map(|(name, procedure)| {
+      assert_eq!(*name, procedure.name);
+      (name.as_str(), procedure.is_recursive())
+    })
+    .collect();
+  assert_eq!(recursive, [("countdown", true), ("outer", false), ("outer.inner", true)]);
+}
+
+#[test]
+fn strict_mode_rejects_assigning_to_parameters() {
+  use lualite::ast::Identifier;
+  use lualite::compiler::CompileOptions;
+  let strict = CompileOptions { strict: true, ..CompileOptions::default() };
+  let (_, clamp) = parser::declaration::function_decl("function clamp(x, limit = 10) if x > limit then x = limit end return x end").unwrap();
+  assert!(compiler::compile_function(&clamp).is_ok());
+  let error = compiler::compile_function_with_options(&clamp, &[], &strict).unwrap_err();
+  assert!(matches!(error, CompileError::AssignToParameter(Identifier(name)) if name == "x"));
+  // so is using one as a `for` variable
+  let (_, reused) = parser::declaration::function_decl("function reused(i) for i in 1..3 do end return i end").unwrap();
+  let error = compiler::compile_function_with_options(&reused, &[], &strict).unwrap_err();
+  assert!(matches!(error, CompileError::AssignToParameter(Identifier(name)) if name == "i"));
+
+  // defaults and locals are still fine
+  let (_, capped) = parser::declaration::function_decl("function capped(x, limit = 10) y = x return y end").unwrap();
+  assert!(compiler::compile_function_with_options(&capped, &[], &strict).is_ok());
+  // even when the parameters are only assigned by looping tail calls
+  let (_, countdown) = parser::declaration::function_decl("function countdown(n) if n > 0 then return countdown(n - 1) end return n end").unwrap();
+  let strict_looped = CompileOptions { tail_recursion: true, ..strict.clone() };
+  assert!(compiler::compile_function_with_options(&countdown, &[], &strict_looped).is_ok());
+  // a `local` shadowing a parameter is its own variable
+  let (_, shadowed) = parser::declaration::function_decl("function shadowed(x) local x = 1 x = 2 return x end").unwrap();
+  assert!(compiler::compile_function_with_options(&shadowed, &[], &strict).is_ok());
+}
+
+#[test]
+fn tail_calls_are_not_looped_when_a_local_shadows_a_parameter() {
+  use lualite::compiler::tail_recursion::loop_tail_recursion;
+  let (_, shadowed) = parser::declaration::function_decl("function f(n) if n > 0 then local n = n - 1 return f(n) end return n end").unwrap();
+  assert!(loop_tail_recursion(&shadowed, &shadowed.body, &[]).is_none());
+  let (_, renamed) = parser::declaration::function_decl("function f(n) if n > 0 then local m = n - 1 return f(m) end return n end").unwrap();
+  assert!(loop_tail_recursion(&renamed, &renamed.body, &[]).is_some());
+}
+
+#[test]
+fn instructions_compare_equal_to_built_instructions() {
+  use std::collections::HashSet;
+  use lualite::bytecode::{self, operand::RawRegister};
+  let (_, fn_decl) = parser::declaration::function_decl("function negate(x) return -x end").unwrap();
+  let procedure = compiler::compile_function(&fn_decl).unwrap();
+  assert_eq!(procedure.bytecode, [bytecode::nop(), bytecode::neg(RawRegister(0), RawRegister(1)), bytecode::ret()]);
+  assert_ne!(procedure.bytecode[1], bytecode::neg(RawRegister(0), RawRegister(2)));
+
+  let distinct: HashSet<_> = [bytecode::nop(), bytecode::ret(), bytecode::nop()].into_iter().collect();
+  assert_eq!(distinct.len(), 2);
+}
+
+#[test]
+fn common_subexpressions_are_computed_once() {
+  use lualite::compiler::CompileOptions;
+  let options = CompileOptions { common_subexpressions: true, ..CompileOptions::default() };
+  let source_code = "function f(a, b) return (a + b) * (a + b) end";
+  let (_, fn_decl) = parser::declaration::function_decl(source_code).unwrap();
+  let arithmetic_count = |procedure: &lualite::bytecode::Procedure| procedure.bytecode.iter()
+    .filter(|&&instruction| matches!(SuperCode::from(instruction), SuperCode::Arithmetic))
+    .count();
+  let plain = compiler::compile_function(&fn_decl).unwrap();
+  let eliminated = compiler::compile_function_with_options(&fn_decl, &[], &options).unwrap();
+  assert_eq!(arithmetic_count(&plain), 3);
+  assert_eq!(arithmetic_count(&eliminated), 2);
+
+  // a call between the repeats could change what they read, so nothing is eliminated
+  let (_, with_call) = parser::declaration::function_decl("function g(a, b) return (a + b) * h() * (a + b) end").unwrap();
+  let plain = compiler::compile_function(&with_call).unwrap();
+  let eliminated = compiler::compile_function_with_options(&with_call, &[], &options).unwrap();
+  assert_eq!(arithmetic_count(&plain), arithmetic_count(&eliminated));
+}
+
+#[test]
+fn diff_marks_removed_instructions() {
+  use lualite::bytecode::disassemble;
+  let (_, fn_decl) = parser::declaration::function_decl("function f(x) y = x * 2 return y + 1 end").unwrap();
+  let before = compiler::compile_function(&fn_decl).unwrap();
+  let mut after = compiler::compile_function(&fn_decl).unwrap();
+  let removed = after.bytecode.remove(2);
+
+  let diff = disassemble::diff(&before, &after);
+  let changed: Vec<&str> = diff.lines().filter(|line| !line.starts_with(' ')).collect();
+  assert_eq!(changed, [format!("-    2  {removed}")]);
+  assert_eq!(diff.lines().count(), before.bytecode.len());
+  assert!(disassemble::diff(&before, &before).lines().all(|line| line.starts_with(' ')));
+}
+
+#[test]
+fn statics_fold_like_the_virtual_machine() {
+  let source_code = r#"
+static MIXED = 1 + 1.5
+static QUOTIENT = 7 // 0
+static SAME = 1 == 1.0
+static LESS = 1 < 2
+static INCOMPARABLE = 1 < "a"
+static CHOICE = nil or "default"
+static REPEATED = "ab" * 2
+static REMAINDER = (-9223372036854775807 - 1) % -1
+"#;
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let statics = compiler::compile_statics(declarations.iter()).unwrap();
+  let values: Vec<ConstantValue> = statics.into_iter().map(|(_, value)| value).collect();
+  assert_eq!(values, [
+    ConstantValue::Nil,
+    ConstantValue::Nil,
+    ConstantValue::Boolean(false),
+    ConstantValue::Boolean(true),
+    ConstantValue::Boolean(false),
+    ConstantValue::String("default".into()),
+    ConstantValue::String("abab".into()),
+    ConstantValue::Integer(0),
+  ]);
+}
+
+#[test]
+fn duplicate_statics_are_an_error() {
+  let (_, declarations) = parser::parse_file("static X = 1\nstatic X = 2\n").unwrap();
+  let error = compiler::compile_statics(declarations.iter()).unwrap_err();
+  assert!(matches!(error, CompileError::DuplicateStatic(name) if name == "X"));
+}
+
+#[test]
+fn functions_cannot_be_named_call() {
+  let source_code = r"
+function call(f, x)
+  return 0
+end
+
+function apply(f, x)
+  return f(x)
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let error = compiler::compile_declarations(declarations.iter()).unwrap_err();
+  assert!(matches!(error, CompileError::ReservedFunctionName(name) if name == "call"));
+
+  // calling a local goes through the reserved name, never a script's function
+  let (_, fn_decl) = parser::declaration::function_decl("function apply(f, x) return f(x) end").unwrap();
+  let procedure = compiler::compile_function(&fn_decl).unwrap();
+  assert_eq!(procedure.functions, [lualite::bytecode::builtin::CALL]);
+}
@@ -0,0 +1,26 @@
+//! Only uses the modules available without the `std` feature
+//!
+//! Run with `cargo test --no-default-features --test no_std_core` to check that the
+//! core of the crate builds as `no_std`.
+
+use lualite::{parser, compiler};
+use lualite::ast::Declaration;
+
+#[test]
+fn parse_and_compile_without_runtime() {
+  let source_code = r"
+function double(x)
+  return x * 2
+end
+";
+  let (remaining, declarations) = parser::parse_file(source_code).unwrap();
+  assert_eq!(remaining, "");
+  assert!(matches!(declarations.as_slice(), [Declaration::Function(_)]));
+
+  let functions = compiler::compile_declarations(declarations.iter()).unwrap();
+  assert!(compiler::link(&functions, []).is_ok());
+  let (name, procedure) = &functions[0];
+  assert_eq!(name, "double");
+  assert_eq!(procedure.max_args, 1);
+  assert!(procedure.to_string().contains("mul   R0 = R1 * #2"));
+}
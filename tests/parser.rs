@@ -0,0 +1,505 @@
+use lualite::parser;
+use lualite::parser::ParseErrorKind;
+
+#[test]
+fn unterminated_string_points_at_opening_quote() {
+  let source_code = "x = \"oops";
+  match parser::statement::statement(source_code) {
+    Err(nom::Err::Failure(error)) => {
+      assert_eq!(error.kind, ParseErrorKind::UnterminatedString);
+      assert_eq!(source_code.len() - error.input.len(), 4);
+      assert_eq!(error.input, "\"oops");
+    }
+    other => panic!("expected an unterminated string error, got {other:?}"),
+  }
+}
+
+#[test]
+fn unterminated_string_does_not_swallow_next_line() {
+  let source_code = "function main()\n  x = \"oops\n  return \"done\"\nend\n";
+  let error = parser::parse_file(source_code).unwrap_err();
+  assert!(matches!(error, nom::Err::Failure(error) if error.input.starts_with("\"oops\n")));
+}
+
+#[test]
+fn unterminated_string_reports_its_line() {
+  // a quote at the start of a line is on that line, not the one before it
+  let source_code = "function main()\n  f(1,\n\"oops\n  )\nend\n";
+  let error = parser::parse_program(source_code).unwrap_err();
+  assert_eq!(error.kind, ParseErrorKind::UnterminatedString);
+  assert_eq!((error.line, error.column), (3, 1));
+  assert_eq!(error.to_string(), "unterminated string on line 3, column 1");
+}
+
+#[test]
+fn comments_inside_expressions() {
+  use parser::expression::expression;
+  let (_, expected) = expression("x + y").unwrap();
+  assert_eq!(expression("x + # note\n y"), Ok(("", expected)));
+
+  let (_, expected) = expression("(a * b) - f(c, d)").unwrap();
+  let source_code = "( # product\n  a *\n  b\n) - # difference\n f(c, # first\n d)";
+  assert_eq!(expression(source_code), Ok(("", expected)));
+}
+
+#[test]
+fn operators_do_not_join_lines() {
+  let (_, declarations) = parser::parse_file("function main()\n  x = a\n  -b\nend\n").unwrap();
+  let lualite::ast::Declaration::Function(main) = &declarations[0] else { panic!("expected a function") };
+  assert_eq!(main.body.len(), 2);
+}
+
+#[test]
+fn tightly_packed_function_declarations() {
+  use parser::declaration::function_decl;
+  for source_code in [
+    "function f()return 1 end",
+    "function f() return 1 end",
+    "function f() end",
+    "function f()end",
+    "function f(a,b)return a end",
+    "function f()\nend",
+    "function f() x = 1 end",
+    "function f() if x then return 1 end end",
+    "function f() while x do x = x - 1 end end",
+    "function f() return end",
+    "function f()return(1)end",
+    "function f(x)return x[0]end",
+    "function f()return\"s\"end",
+    "function f()\n  # comment before end\nend",
+    "function f() # trailing comment\n  return 1 # another\nend",
+  ] {
+    let result = function_decl(source_code);
+    assert!(matches!(result, Ok(("", _))), "failed to parse {source_code:?}: {result:?}");
+  }
+  let (remaining, declarations) = parser::parse_file("function f() end # no newline after this comment").unwrap();
+  assert_eq!((remaining, declarations.len()), ("", 1));
+
+  let (_, empty) = function_decl("function f() end").unwrap();
+  assert!(empty.body.is_empty());
+  let (_, bare_return) = function_decl("function f() return end").unwrap();
+  assert_eq!(bare_return.body, [lualite::ast::Statement::ReturnStatement(None)]);
+}
+
+#[test]
+fn identifier_modes() {
+  use lualite::ast::Identifier;
+  use lualite::parser::atomic::{identifier, identifier_with_mode, IdentifierMode};
+  use lualite::parser::parse_file_with_mode;
+
+  assert_eq!(identifier("café"), Ok(("", Identifier("café".to_owned()))));
+  assert_eq!(identifier_with_mode(IdentifierMode::Unicode)("café"), Ok(("", Identifier("café".to_owned()))));
+  assert!(identifier_with_mode(IdentifierMode::Ascii)("café").is_err());
+
+  let source_code = "function main()\n  café = \"crème\" # déjà vu\n  return café\nend\n";
+  let (_, declarations) = parse_file_with_mode(source_code, IdentifierMode::Unicode).unwrap();
+  assert_eq!(declarations.len(), 1);
+  match parse_file_with_mode(source_code, IdentifierMode::Ascii) {
+    Err(nom::Err::Failure(error)) => assert_eq!(source_code.len() - error.input.len(), source_code.find('é').unwrap()),
+    other => panic!("expected a failure, got {other:?}"),
+  }
+  assert!(parse_file_with_mode("function main() return \"☕\" end", IdentifierMode::Ascii).is_ok());
+}
+
+#[test]
+fn keywords_are_not_identifiers() {
+  use lualite::parser::atomic::{any_keyword, identifier_with_mode, IdentifierMode, KEYWORDS};
+  for keyword in KEYWORDS {
+    assert_eq!(any_keyword(keyword), Ok(("", keyword)));
+    for mode in [IdentifierMode::Unicode, IdentifierMode::Ascii] {
+      assert!(identifier_with_mode(mode)(keyword).is_err(), "{keyword:?}");
+    }
+  }
+  // a keyword at the start of a longer word is still an identifier
+  assert!(identifier_with_mode(IdentifierMode::Ascii)("endless").is_ok());
+  assert!(any_keyword("endless").is_err());
+}
+
+#[test]
+fn whitespace_matches_combinator_implementation() {
+  use lualite::parser::IResult;
+  use nom::{branch::alt, bytes::complete::tag, combinator::map, multi::many0};
+  // the original char-by-char implementation
+  fn reference(s: &str) -> IResult<&str, &str> {
+    map(many0(alt((tag(" "), tag("\t"), tag("\n"), parser::comment))), |_| "")(s)
+  }
+  let cases = [
+    "", "x", "   x", "\t\n \n", "# comment", "#", "#\n#\n", "  # comment\n  x = 1",
+    "\n\n# a\n\t# b\nend", "\r\nx", " # ☕ unicode\n  y", "##\n #  # \nz",
+  ];
+  for case in cases {
+    assert_eq!(parser::whitespace(case), reference(case), "{case:?}");
+  }
+
+  let source_code = parser::generated_program(200);
+  let (remaining, declarations) = parser::parse_file(&source_code).unwrap();
+  assert_eq!(remaining, "");
+  assert_eq!(declarations.len(), 200);
+}
+
+#[test]
+fn operator_symbols_round_trip() {
+  use lualite::ast::{BinaryOperator, UnaryOperator};
+  use lualite::parser::operator;
+  let cases = [
+    (BinaryOperator::Pow, operator::power as fn(&str) -> parser::IResult<&str, BinaryOperator>),
+    (BinaryOperator::Mul, operator::multiplicative),
+    (BinaryOperator::Div, operator::multiplicative),
+    (BinaryOperator::FloorDiv, operator::multiplicative),
+    (BinaryOperator::Rem, operator::multiplicative),
+    (BinaryOperator::Add, operator::additive),
+    (BinaryOperator::Sub, operator::additive),
+    (BinaryOperator::Eq, operator::comparison),
+    (BinaryOperator::Ne, operator::comparison),
+    (BinaryOperator::Lt, operator::comparison),
+    (BinaryOperator::Gt, operator::comparison),
+    (BinaryOperator::Le, operator::comparison),
+    (BinaryOperator::Ge, operator::comparison),
+    (BinaryOperator::And, operator::logical_and),
+    (BinaryOperator::Or, operator::logical_or),
+  ];
+  for (op, parser) in cases {
+    let symbol = op.to_string();
+    assert_eq!(parser(&symbol), Ok(("", op)), "{symbol:?}");
+  }
+  assert_eq!(operator::unary(&UnaryOperator::Neg.to_string()), Ok(("", UnaryOperator::Neg)));
+}
+
+#[test]
+fn power_binds_tighter_than_multiplicative_operators() {
+  use lualite::parser::expression::expression;
+  let cases = [
+    ("2 * 3 ^ 2", "2 * (3 ^ 2)"),
+    ("2 ^ 2 * 3", "(2 ^ 2) * 3"),
+    ("2 ^ 3 % 5", "(2 ^ 3) % 5"),
+    ("7 % 2 ^ 2", "7 % (2 ^ 2)"),
+    ("8 // 2 ^ 2 / 2", "(8 // (2 ^ 2)) / 2"),
+    ("1 + 7 % 4 * 2", "1 + ((7 % 4) * 2)"),
+  ];
+  for (source_code, grouped) in cases {
+    assert_eq!(expression(source_code), expression(grouped), "{source_code:?}");
+  }
+}
+
+#[test]
+fn unary_operators_bind_looser_than_power() {
+  use lualite::parser::expression::expression;
+  let cases = [
+    ("-2 ^ 2", "-(2 ^ 2)"),
+    ("-x ^ 2", "-(x ^ 2)"),
+    ("not x ^ 2", "not (x ^ 2)"),
+    ("2 ^ -x", "2 ^ (-x)"),
+    ("2 ^ -x ^ 2", "2 ^ (-(x ^ 2))"),
+    ("2 ^ - -1", "2 ^ (-(-1))"),
+    ("-2 ^ -2 * 3", "(-(2 ^ (-2))) * 3"),
+  ];
+  for (source_code, grouped) in cases {
+    assert_eq!(expression(source_code), expression(grouped), "{source_code:?}");
+  }
+}
+
+#[test]
+fn structured_parse_errors() {
+  use lualite::parser::{ParseError, ParseErrorKind};
+  let source_code = "function main()\n  x = \"café\n  return x\nend\n";
+  let error = parser::parse_program(source_code).unwrap_err();
+  assert_eq!(error, ParseError { offset: 22, line: 2, column: 7, kind: ParseErrorKind::UnterminatedString });
+  assert_eq!(&source_code[error.offset..error.offset + 1], "\"");
+  assert_eq!(error.to_string(), "unterminated string on line 2, column 7");
+
+  // columns count characters, not bytes
+  let error = parser::parse_program("static é = 1\nstatic ü = é +\n").unwrap_err();
+  assert_eq!((error.line, error.column, error.kind), (2, 14, ParseErrorKind::UnexpectedInput));
+  let error = parser::parse_program_with_mode("static x = \"é\"\nstatic ü = 1", parser::atomic::IdentifierMode::Ascii).unwrap_err();
+  assert_eq!((error.offset, error.line, error.column, error.kind), (23, 2, 8, ParseErrorKind::NonAsciiCharacter));
+
+  assert!(parser::parse_expression("(1 + 2) * 3").is_ok());
+  // the missing `)` is reported where it was expected
+  let error = parser::parse_expression("(1 + 2 * 3").unwrap_err();
+  assert_eq!((error.offset, error.column), (10, 11));
+}
+
+#[test]
+fn floats_can_leave_out_digits_on_one_side_of_the_dot() {
+  use lualite::ast::{Expression, IntegerLiteral, FloatLiteral, BinaryOperator};
+  use parser::expression::expression;
+  assert_eq!(expression(".5"), Ok(("", Expression::Float(FloatLiteral(0.5)))));
+  assert_eq!(expression("5."), Ok(("", Expression::Float(FloatLiteral(5.0)))));
+  assert_eq!(expression("5.0"), Ok(("", Expression::Float(FloatLiteral(5.0)))));
+  assert_eq!(expression("5"), Ok(("", Expression::Integer(IntegerLiteral(5)))));
+  let expected = Expression::Binary {
+    left: Box::new(Expression::Float(FloatLiteral(1.0))),
+    op: BinaryOperator::Add,
+    right: Box::new(Expression::Float(FloatLiteral(0.5))),
+  };
+  assert_eq!(expression("1. + .5"), Ok(("", expected)));
+  // neither of these is a float, and there are no fields to read them as yet
+  assert!(expression("5.method").is_err());
+  assert!(expression("a.5").is_err());
+  for source_code in ["function f(a) x = a.5 return x end", "function f() x = 5.method return x end"] {
+    let error = parser::parse_program(source_code).unwrap_err();
+    assert_eq!(error.kind, parser::ParseErrorKind::UnexpectedInput, "{source_code}");
+  }
+  assert!(parser::parse_program("function f(a) for i in a..5 do end end").is_ok());
+}
+
+#[test]
+fn range_start_is_not_a_float() {
+  use lualite::ast::{Statement, Expression, Identifier, IntegerLiteral, FloatLiteral};
+  let (remaining, parsed) = parser::statement::statement("for i in 1..10 do end").unwrap();
+  assert_eq!(remaining, "");
+  assert_eq!(parsed, Statement::ForStatement {
+    variable: Identifier("i".to_owned()),
+    start: Expression::Integer(IntegerLiteral(1)),
+    end: Expression::Integer(IntegerLiteral(10)),
+    body: vec![],
+  });
+  let (remaining, parsed) = parser::expression::expression("1.5..2").unwrap();
+  assert_eq!(remaining, "..2");
+  assert_eq!(parsed, Expression::Float(FloatLiteral(1.5)));
+  assert_eq!(parser::expression::expression("1.").unwrap().1, Expression::Float(FloatLiteral(1.0)));
+}
+
+#[test]
+fn chained_postfix_operators() {
+  use lualite::parser::expression::{expression, function_call, index_expression};
+  use lualite::ast::{Expression, Identifier, IntegerLiteral, StringLiteral};
+  let ident = |name: &str| Box::new(Expression::Identifier(Identifier(name.to_owned())));
+  let string = |s: &str| Box::new(Expression::String(StringLiteral(s.to_owned())));
+
+  let expected = Expression::Index {
+    left: Box::new(Expression::Index { left: ident("a"), index: ident("i") }),
+    index: ident("j"),
+  };
+  assert_eq!(expression("a[i][j]"), Ok(("", expected.clone())));
+  assert_eq!(expression("a[ i ] [ j ]"), Ok(("", expected)));
+
+  let expected = Expression::Index {
+    left: Box::new(Expression::Index { left: ident("m"), index: string("a") }),
+    index: string("b"),
+  };
+  assert_eq!(expression(r#"m["a"]["b"]"#), Ok(("", expected)));
+
+  let expected = Expression::Index {
+    left: Box::new(Expression::FunctionCall { left: ident("f"), args: vec![*ident("x")] }),
+    index: Box::new(Expression::Integer(IntegerLiteral(0))),
+  };
+  assert_eq!(expression("f(x)[0]"), Ok(("", expected.clone())));
+  assert_eq!(index_expression("f(x)[0]"), Ok(("", expected)));
+  assert!(function_call("f(x)[0]").is_err());
+
+  let expected = Expression::FunctionCall {
+    left: Box::new(Expression::Index { left: ident("a"), index: ident("i") }),
+    args: vec![*ident("x")],
+  };
+  assert_eq!(expression("a[i](x)"), Ok(("", expected.clone())));
+  assert_eq!(function_call("a[i](x)"), Ok(("", expected)));
+  assert!(index_expression("a[i](x)").is_err());
+
+  let expected = Expression::FunctionCall {
+    left: Box::new(Expression::FunctionCall { left: ident("f"), args: vec![] }),
+    args: vec![*ident("y")],
+  };
+  assert_eq!(expression("f()(y)"), Ok(("", expected)));
+}
+
+#[test]
+fn trailing_else_belongs_to_last_elseif() {
+  use lualite::ast::{Statement, Expression, Identifier, IntegerLiteral};
+  let ident = |name: &str| Expression::Identifier(Identifier(name.to_owned()));
+  let ret = |i| vec![Statement::ReturnStatement(Some(Expression::Integer(IntegerLiteral(i))))];
+  let source_code = "if a then return 1 elseif b then return 2 elseif c then return 3 else return 4 end";
+  let expected = Statement::IfStatement {
+    condition: ident("a"),
+    body: ret(1),
+    else_body: Some(vec![Statement::IfStatement {
+      condition: ident("b"),
+      body: ret(2),
+      else_body: Some(vec![Statement::IfStatement {
+        condition: ident("c"),
+        body: ret(3),
+        else_body: Some(ret(4)),
+      }]),
+    }]),
+  };
+  assert_eq!(parser::statement::if_statement(source_code), Ok(("", expected)));
+
+  let source_code = "if a then return 1 elseif b then return 2 end";
+  let expected = Statement::IfStatement {
+    condition: ident("a"),
+    body: ret(1),
+    else_body: Some(vec![Statement::IfStatement {
+      condition: ident("b"),
+      body: ret(2),
+      else_body: None,
+    }]),
+  };
+  assert_eq!(parser::statement::if_statement(source_code), Ok(("", expected)));
+}
+
+#[test]
+fn slice_expressions() {
+  use lualite::parser::expression::expression;
+  use lualite::ast::{Expression, Identifier, IntegerLiteral};
+  let ident = |name: &str| Box::new(Expression::Identifier(Identifier(name.to_owned())));
+  let int = |i| Some(Box::new(Expression::Integer(IntegerLiteral(i))));
+  let cases = [
+    ("a[1:3]", int(1), int(3)),
+    ("a[ : 2 ]", None, int(2)),
+    ("a[2:]", int(2), None),
+    ("a[:]", None, None),
+  ];
+  for (source_code, start, end) in cases {
+    let expected = Expression::Slice { left: ident("a"), start, end };
+    assert_eq!(expression(source_code), Ok(("", expected)), "{source_code:?}");
+  }
+  let expected = Expression::Index {
+    left: Box::new(Expression::Slice { left: ident("a"), start: int(1), end: None }),
+    index: Box::new(Expression::Integer(IntegerLiteral(0))),
+  };
+  assert_eq!(expression("a[1:][0]"), Ok(("", expected)));
+}
+
+#[test]
+fn formatting_does_not_affect_ast_equality() {
+  let compact = "function f(a, b)\nif a < b then\nreturn [a, b]\nelse\nreturn f(b, a)\nend\nend\n";
+  let indented = r"
+    function f( a,b )
+        if a<b then
+            return [ a , b ]   # sorted
+        else
+            return f( b,a )
+        end
+    end
+  ";
+  assert_eq!(parser::parse_program(compact).unwrap(), parser::parse_program(indented).unwrap());
+  // even though the functions start in different places
+  let span = |source_code| match &parser::parse_program(source_code).unwrap()[0] {
+    lualite::ast::Declaration::Function(function) => (function.span.line, function.span.column),
+    other => panic!("expected a function, got {other:?}"),
+  };
+  assert_eq!((span(compact), span(indented)), ((1, 1), (2, 5)));
+  assert_ne!(parser::parse_program(compact).unwrap(), parser::parse_program("function f(a, b) end").unwrap());
+}
+
+#[test]
+fn byte_string_literals() {
+  use lualite::parser::expression::expression;
+  use lualite::ast::{Expression, BytesLiteral, Identifier, StringLiteral};
+  assert_eq!(expression("b\"abc\""), Ok(("", Expression::Bytes(BytesLiteral(vec![97, 98, 99])))));
+  assert_eq!(expression("b\"\""), Ok(("", Expression::Bytes(BytesLiteral(vec![])))));
+  // only a `b` directly against the quote makes a byte string
+  assert_eq!(expression("b"), Ok(("", Expression::Identifier(Identifier("b".to_owned())))));
+  assert_eq!(expression("\"b\""), Ok(("", Expression::String(StringLiteral("b".to_owned())))));
+  assert!(matches!(expression("b\"abc"), Err(nom::Err::Failure(_))));
+}
+
+#[test]
+fn conditional_expressions_have_the_lowest_precedence() {
+  use lualite::parser::expression::expression;
+  use lualite::ast::{Expression, BinaryOperator, Identifier, IntegerLiteral};
+  let ident = |name: &str| Box::new(Expression::Identifier(Identifier(name.to_owned())));
+  let int = |i| Box::new(Expression::Integer(IntegerLiteral(i)));
+  let expected = Expression::Conditional {
+    condition: Box::new(Expression::Binary { left: ident("a"), op: BinaryOperator::Gt, right: ident("b") }),
+    then: Box::new(Expression::Binary { left: ident("a"), op: BinaryOperator::Add, right: int(1) }),
+    else_: Box::new(Expression::Conditional { condition: ident("c"), then: int(2), else_: int(3) }),
+  };
+  assert_eq!(expression("a > b ? a + 1 : c ? 2 : 3"), Ok(("", expected)));
+  // a conditional inside brackets is an index, not a slice
+  let expected = Expression::Index {
+    left: ident("x"),
+    index: Box::new(Expression::Conditional { condition: ident("c"), then: int(0), else_: int(1) }),
+  };
+  assert_eq!(expression("x[c ? 0 : 1]"), Ok(("", expected)));
+}
+
+#[test]
+fn not_binds_tighter_than_and_which_binds_tighter_than_or() {
+  use lualite::parser::expression::expression;
+  use lualite::ast::{Expression, BinaryOperator, UnaryOperator, Identifier};
+  let ident = |name: &str| Box::new(Expression::Identifier(Identifier(name.to_owned())));
+  let not = |right| Box::new(Expression::Unary { op: UnaryOperator::Not, right });
+  let binary = |left, op, right| Box::new(Expression::Binary { left, op, right });
+
+  let expected = binary(not(ident("a")), BinaryOperator::And, ident("b"));
+  assert_eq!(expression("not a and b"), Ok(("", *expected)));
+  let expected = binary(ident("a"), BinaryOperator::Or, binary(ident("b"), BinaryOperator::And, ident("c")));
+  assert_eq!(expression("a or b and c"), Ok(("", *expected)));
+  let expected = not(binary(ident("a"), BinaryOperator::Or, ident("b")));
+  assert_eq!(expression("not (a or b)"), Ok(("", *expected)));
+  // `not` is a keyword, not the start of an identifier
+  assert_eq!(expression("nothing"), Ok(("", *ident("nothing"))));
+}
+
+#[test]
+fn break_and_continue_are_keywords() {
+  use lualite::parser::statement::statement;
+  use lualite::ast::Statement;
+  assert_eq!(statement("break"), Ok(("", Statement::BreakStatement)));
+  assert_eq!(statement("  continue"), Ok(("", Statement::ContinueStatement)));
+  assert!(parser::atomic::identifier("continue").is_err());
+  assert!(parser::atomic::identifier("breaker").is_ok());
+}
+
+#[test]
+fn recovering_parser_skips_bad_declarations() {
+  use lualite::ast::Declaration;
+  let source_code = "\
+function first()
+  return 1
+end
+
+function broken(
+  return 2
+end
+
+function last()
+  return 3
+end
+";
+  let (declarations, errors) = parser::parse_file_recovering(source_code);
+  let names: Vec<_> = declarations.iter().map(|declaration| match declaration {
+    Declaration::Function(function) => function.name.0.as_str(),
+    Declaration::Static(_) => panic!("expected only functions"),
+  }).collect();
+  assert_eq!(names, ["first", "last"]);
+  assert_eq!(errors.len(), 1);
+  assert_eq!(errors[0].line, 5);
+}
+
+#[test]
+fn type_suffixes_force_the_literal_type() {
+  use lualite::ast::{Expression, IntegerLiteral, FloatLiteral, Identifier, BinaryOperator};
+  use parser::expression::expression;
+  assert_eq!(expression("1f"), Ok(("", Expression::Float(FloatLiteral(1.0)))));
+  assert_eq!(expression("5i"), Ok(("", Expression::Integer(IntegerLiteral(5)))));
+  let expected = Expression::Binary {
+    left: Box::new(Expression::Identifier(Identifier("x".to_owned()))),
+    op: BinaryOperator::Div,
+    right: Box::new(Expression::Float(FloatLiteral(2.0))),
+  };
+  assert_eq!(expression("x / 2f"), Ok(("", expected)));
+  // a suffix that starts a longer word isn't a suffix
+  assert_eq!(expression("5if"), Ok(("if", Expression::Integer(IntegerLiteral(5)))));
+  assert_eq!(expression("5fx"), Ok(("fx", Expression::Integer(IntegerLiteral(5)))));
+}
+
+#[test]
+fn expression_bodied_functions_return_their_expression() {
+  let (_, short) = parser::parse_file("function hypot2(a, b = 0) = a * a + b * b\n").unwrap();
+  let (_, long) = parser::parse_file("function hypot2(a, b = 0)\n  return a * a + b * b\nend\n").unwrap();
+  assert_eq!(short, long);
+}
+
+#[test]
+fn expressions_with_float_literals_can_be_deduplicated() {
+  use std::collections::HashSet;
+  use parser::expression::expression;
+  let sources = ["x * 0.5 + 1", "x*0.5 + 1", "x * 0.5 + 1.0", "-0.0", "0.0", "(x * 0.5) + 1", "b and not true"];
+  let unique: HashSet<_> = sources.iter().map(|source| expression(source).unwrap().1).collect();
+  // formatting and parentheses don't make a different expression, but `1` vs `1.0` and
+  // `0.0` vs `-0.0` do
+  assert_eq!(unique.len(), 5);
+  assert!(unique.contains(&expression("x * .5 + 1").unwrap().1));
+}
@@ -0,0 +1,2211 @@
+use lualite::{parser, compiler};
+use lualite::runtime::{VirtualMachine, Value, RuntimeError};
+
+#[test]
+fn missing_entry_function() {
+  let source_code = r"
+function helper(x)
+  return x + 1
+end
+
+function other()
+  return 2
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let functions = compiler::compile_declarations(declarations.iter()).unwrap();
+  let mut vm = VirtualMachine::with_functions(functions);
+
+  assert!(!vm.has_function("main"));
+  assert_eq!(vm.function_names(), ["helper", "other"]);
+  assert!(matches!(vm.run("main", []), Err(RuntimeError::MissingFunction)));
+}
+
+#[test]
+fn empty_program() {
+  let (remaining, declarations) = parser::parse_file("\n# only a comment\n\n").unwrap();
+  assert_eq!(remaining, "");
+  assert!(declarations.is_empty());
+
+  let vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+  assert!(vm.function_names().is_empty());
+}
+
+#[test]
+fn unary_negation() {
+  let source_code = r"
+function negate(x)
+  return -x
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+
+  assert!(matches!(vm.run("negate", [Value::Integer(7)]), Ok(Value::Integer(-7))));
+  assert!(matches!(vm.run("negate", [Value::Integer(-7)]), Ok(Value::Integer(7))));
+  assert!(matches!(vm.run("negate", [Value::Float(2.5)]), Ok(Value::Float(flt)) if flt == -2.5));
+}
+
+#[test]
+fn type_native() {
+  let source_code = r#"
+function type_names(array)
+  names = array
+  names[0] = type(nil)
+  names[1] = type(1 == 1)
+  names[2] = type(12)
+  names[3] = type(1.5)
+  names[4] = type("text")
+  names[5] = type(array)
+  return names
+end
+"#;
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+
+  let result = vm.run("type_names", [Value::from_iter([0; 6])]).unwrap();
+  assert_eq!(result.to_string(), r#"["nil", "boolean", "integer", "float", "string", "array"]"#);
+}
+
+#[test]
+fn logical_operators_yield_operand_values() {
+  let source_code = r#"
+function nil_or()
+  return nil or 5
+end
+
+function and_values()
+  return 3 and 4
+end
+
+function false_or()
+  return false or "x"
+end
+
+function keeps_old_value(x, y)
+  x = y or x
+  return x
+end
+"#;
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+
+  assert!(matches!(vm.run("nil_or", []), Ok(Value::Integer(5))));
+  assert!(matches!(vm.run("and_values", []), Ok(Value::Integer(4))));
+  assert_eq!(vm.run("false_or", []).unwrap(), Value::from("x"));
+  assert!(matches!(vm.run("keeps_old_value", [Value::Integer(1), Value::Nil]), Ok(Value::Integer(1))));
+  assert!(matches!(vm.run("keeps_old_value", [Value::Integer(1), Value::Integer(2)]), Ok(Value::Integer(2))));
+}
+
+#[test]
+fn division_and_floor_division() {
+  let source_code = r"
+function divide(a, b)
+  return a / b
+end
+
+function floor_divide(a, b)
+  return a // b
+end
+
+function remainder(a, b)
+  return a % b
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+  let mut run = |name: &str, a: Value, b: Value| vm.run(name, [a, b]).unwrap();
+
+  assert_eq!(run("divide", 7.into(), 2.into()), Value::Float(3.5));
+  assert_eq!(run("floor_divide", 7.into(), 2.into()), Value::Integer(3));
+  assert_eq!(run("floor_divide", (-7).into(), 2.into()), Value::Integer(-4));
+  assert_eq!(run("floor_divide", 7.into(), 0.into()), Value::Nil);
+  assert_eq!(run("remainder", (-7).into(), 2.into()), Value::Integer(1));
+  assert_eq!(run("remainder", 7.into(), (-2).into()), Value::Integer(-1));
+  assert_eq!(run("remainder", 7.into(), 0.into()), Value::Nil);
+  // the division overflows, but the remainder doesn't
+  assert_eq!(run("remainder", i64::MIN.into(), (-1).into()), Value::Integer(0));
+
+  // mixing integers and floats promotes to float regardless of operand order
+  for (a, b) in [(Value::from(7), Value::from(2.0)), (7.0.into(), 2.into()), (7.0.into(), 2.0.into())] {
+    assert_eq!(run("divide", a.clone(), b.clone()), Value::Float(3.5));
+    assert_eq!(run("floor_divide", a.clone(), b.clone()), Value::Float(3.0));
+    assert_eq!(run("remainder", a, b), Value::Float(1.0));
+  }
+}
+
+#[test]
+fn statics_are_globals() {
+  let source_code = r"
+static SIZE = 512
+static AREA = SIZE * 2 + 10 // 3
+
+function size()
+  return SIZE
+end
+
+function grow(by)
+  AREA = AREA + by
+  return AREA
+end
+
+function shadowed(SIZE)
+  return SIZE
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let statics = compiler::compile_statics(declarations.iter()).unwrap();
+  let names: Vec<&str> = statics.iter().map(|(name, _)| name.as_str()).collect();
+  assert_eq!(names, ["SIZE", "AREA"]);
+
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+  vm.set_globals(statics);
+
+  assert_eq!(vm.run("size", []).unwrap(), Value::Integer(512));
+  assert_eq!(vm.run("grow", [Value::Integer(2)]).unwrap(), Value::Integer(1029));
+  assert_eq!(vm.get_global("AREA"), Some(Value::Integer(1029)));
+  assert_eq!(vm.run("shadowed", [Value::Integer(1)]).unwrap(), Value::Integer(1));
+}
+
+#[test]
+fn register_capacity_is_honored() {
+  let source_code = r"
+function sum_to(n)
+  if n == 0 then
+    return 0
+  end
+  return n + sum_to(n - 1)
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let compile = || compiler::compile_declarations(declarations.iter()).unwrap();
+  let mut plain = VirtualMachine::with_functions(compile());
+  let mut reserved = VirtualMachine::with_capacity(compile(), 4096);
+  let capacity = reserved.register_capacity();
+  assert!(capacity >= 4096);
+
+  let expected = plain.run("sum_to", [Value::Integer(200)]).unwrap();
+  assert_eq!(reserved.run("sum_to", [Value::Integer(200)]).unwrap(), expected);
+  assert_eq!(expected, Value::Integer(20100));
+  // the register stack never had to regrow
+  assert_eq!(reserved.register_capacity(), capacity);
+
+  reserved.reserve_registers(8192);
+  assert!(reserved.register_capacity() >= 8192);
+}
+
+#[test]
+fn array_literals() {
+  let source_code = r#"
+function numbers()
+  return [1, 2, 3]
+end
+
+function mixed(x)
+  x = [x, "two", [3]]
+  return x
+end
+
+function fresh_copies()
+  a = [1, 2]
+  b = [1, 2]
+  a[0] = 5
+  return b
+end
+"#;
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+
+  assert_eq!(vm.run("numbers", []).unwrap().to_string(), "[1, 2, 3]");
+  assert_eq!(vm.run("mixed", [Value::Integer(1)]).unwrap().to_string(), r#"[1, "two", [3]]"#);
+  assert_eq!(vm.run("fresh_copies", []).unwrap().to_string(), "[1, 2]");
+}
+
+#[test]
+fn program_battery() {
+  let source_code = r#"
+static STEP = 3
+
+function gcd(a, b)
+  while a != b do
+    if a > b then
+      a = a - b
+    else
+      b = b - a
+    end
+  end
+  return a
+end
+
+function fib(n)
+  if n < 2 then
+    return n
+  end
+  return fib(n - 1) + fib(n - 2)
+end
+
+function count_by_step(limit)
+  total = 0
+  while total < limit do
+    total = total + STEP
+  end
+  return total
+end
+
+function squares(n)
+  values = [0, 0, 0, 0, 0]
+  i = 0
+  while i < n do
+    values[i] = i * i
+    i = i + 1
+  end
+  return values
+end
+
+function classify(x)
+  if x == nil then
+    return "nothing"
+  end
+  if x >= 10 and x <= 20 then
+    return "teens"
+  else
+    return type(x)
+  end
+end
+
+function arithmetic(a, b)
+  return [a + b, a - b, a * b, a / b, a // b, a % b, -a, 1 - b]
+end
+"#;
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let statics = compiler::compile_statics(declarations.iter()).unwrap();
+  let with_nop = compiler::compile_declarations(declarations.iter()).unwrap();
+  let nop_free_options = compiler::CompileOptions { nop_free: true, ..Default::default() };
+  let nop_free = compiler::compile_declarations_with_options(declarations.iter(), &nop_free_options).unwrap();
+  for ((name, procedure), (_, nop_free_procedure)) in with_nop.iter().zip(nop_free.iter()) {
+    assert!(!nop_free_procedure.leading_nop);
+    assert_eq!(nop_free_procedure.bytecode.len() + 1, procedure.bytecode.len(), "compiling {name}");
+  }
+
+  // both modes give the same results, even when mixed in one virtual machine
+  let mut mixed = VirtualMachine::with_functions(with_nop.into_iter().step_by(2));
+  for (name, procedure) in nop_free.into_iter().skip(1).step_by(2) {
+    mixed.insert_function(name, procedure);
+  }
+  let vms = [
+    VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap()),
+    VirtualMachine::with_functions(compiler::compile_declarations_with_options(declarations.iter(), &nop_free_options).unwrap()),
+    mixed,
+  ];
+  for mut vm in vms {
+    vm.set_globals(statics.clone());
+    run_battery(&mut vm);
+  }
+}
+
+fn run_battery(vm: &mut VirtualMachine) {
+  let cases: [(&str, Vec<Value>, &str); 9] = [
+    ("gcd", vec![25000.into(), 135.into()], "5"),
+    ("fib", vec![15.into()], "610"),
+    ("count_by_step", vec![100.into()], "102"),
+    ("squares", vec![4.into()], "[0, 1, 4, 9, 0]"),
+    ("classify", vec![Value::Nil], r#""nothing""#),
+    ("classify", vec![15.into()], r#""teens""#),
+    ("classify", vec![2.5.into()], r#""float""#),
+    ("arithmetic", vec![7.into(), 2.into()], "[9, 5, 14, 3.5, 3, 1, -7, -1]"),
+    ("arithmetic", vec![(-7).into(), 2.into()], "[-5, -9, -14, -3.5, -4, 1, 7, -1]"),
+  ];
+  for (name, args, expected) in cases {
+    assert_eq!(vm.run(name, args.clone()).unwrap().to_string(), expected, "calling {name}");
+    // dispatching without the table gives the same results
+    assert_eq!(vm.run_with_nested_dispatch(name, args).unwrap().to_string(), expected, "calling {name} with nested dispatch");
+  }
+}
+
+#[test]
+fn characters() {
+  assert_eq!(Value::from('x'), Value::from("x"));
+  assert_eq!(Value::from('é').to_string(), "\"é\"");
+  let characters: Vec<Value> = Value::from("abc").chars().map(Value::from).collect();
+  assert_eq!(characters, ["a", "b", "c"].map(Value::from));
+
+  let source_code = r#"
+function split(s)
+  return chars(s)
+end
+"#;
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+  assert_eq!(vm.run("split", ["abc".into()]).unwrap().to_string(), r#"["a", "b", "c"]"#);
+  assert_eq!(vm.run("split", [Value::Integer(1)]).unwrap(), Value::Nil);
+}
+
+#[test]
+fn trailing_expression_is_returned() {
+  let source_code = r"
+function helper(x)
+  return x * 2
+end
+
+function wrapper(x)
+  helper(x)
+end
+
+function no_result(x)
+  y = helper(x)
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+
+  assert_eq!(vm.run("wrapper", [Value::Integer(21)]).unwrap(), Value::Integer(42));
+  assert_eq!(vm.run("no_result", [Value::Integer(21)]).unwrap(), Value::Nil);
+}
+
+#[test]
+fn power_is_right_associative() {
+  let source_code = r"
+function chained()
+  return 2 ^ 3 ^ 2
+end
+
+function grouped()
+  return (2 ^ 3) ^ 2
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+
+  // compared as text, since the `bigint` feature makes integer powers exact
+  assert_eq!(vm.run("chained", []).unwrap().to_string(), "512");
+  assert_eq!(vm.run("grouped", []).unwrap().to_string(), "64");
+}
+
+#[test]
+fn unary_minus_binds_looser_than_power() {
+  let source_code = r"
+function negated_power()
+  return -2 ^ 2
+end
+
+function power_of_negative()
+  return (-2) ^ 2
+end
+
+function negative_exponent(x)
+  return 2 ^ -x
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+
+  assert_eq!(vm.run("negated_power", []).unwrap().to_string(), "-4");
+  assert_eq!(vm.run("power_of_negative", []).unwrap().to_string(), "4");
+  assert_eq!(vm.run("negative_exponent", [Value::Integer(2)]).unwrap(), Value::Float(0.25));
+}
+
+#[test]
+fn indexing_a_non_indexable_value() {
+  let source_code = r#"
+function index_integer()
+  return (5)[0]
+end
+
+function assign_into_string(s)
+  s[0] = "x"
+  return s
+end
+
+function index_string(s)
+  return s[1]
+end
+"#;
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  // a runtime error leaves the failed call on the call stack, so each run gets a new machine
+  let new_vm = || VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+
+  let result = new_vm().run("index_integer", []);
+  assert!(matches!(result, Err(RuntimeError::NotIndexable { type_name: "integer" })));
+  let result = new_vm().run("assign_into_string", ["abc".into()]);
+  assert!(matches!(result, Err(RuntimeError::NotIndexable { type_name: "string" })));
+  assert_eq!(new_vm().run("index_string", ["abc".into()]).unwrap(), Value::from('b'));
+}
+
+#[test]
+fn string_accessors() {
+  let short = Value::from("short");
+  let long = Value::from("this string does not fit inline");
+  assert!(matches!(short, Value::ShortStr { .. }));
+  assert!(matches!(long, Value::LongStr(_)));
+
+  assert_eq!(short.as_str(), Some("short"));
+  assert_eq!(long.as_str(), Some("this string does not fit inline"));
+
+  let shared = long.clone();
+  assert_eq!(long.into_string().as_deref(), Some("this string does not fit inline"));
+  assert_eq!(shared.into_string().as_deref(), Some("this string does not fit inline"));
+  assert_eq!(short.into_string().as_deref(), Some("short"));
+  assert_eq!(Value::from_iter([1]).as_str(), None);
+}
+
+#[test]
+fn multibyte_strings_at_the_inline_boundary() {
+  let cases = [
+    ("ééééééé", true),     // 14 bytes, exactly fills the inline buffer
+    ("ééééééé!", false),   // 15 bytes
+    ("😀😀😀ab", true),    // 14 bytes ending in ASCII
+    ("ab😀😀😀", true),    // 14 bytes ending in a 4 byte character
+    ("abc😀😀😀", false),  // 15 bytes, the last character doesn't fit
+    ("😀😀😀😀", false),   // 16 bytes
+  ];
+  for (text, inline) in cases {
+    let value = Value::from(text);
+    assert_eq!(matches!(value, Value::ShortStr { .. }), inline, "{text:?}");
+    assert_eq!(value, Value::from(text.to_owned()));
+    assert_eq!(value.as_str(), Some(text));
+    assert_eq!(value.to_string(), format!("{text:?}"));
+    assert_eq!(value.chars().count(), text.chars().count());
+    let last = text.chars().last().unwrap();
+    assert_eq!(value.get(Value::Integer(text.chars().count() as i64 - 1)).unwrap(), Value::from(last));
+  }
+
+  // a hand-built buffer with invalid UTF-8 doesn't panic
+  let malformed = Value::ShortStr { buf: [0xff; 14], len: 20 };
+  assert_eq!(malformed.as_str(), None);
+  assert!(malformed.to_string().starts_with('"'));
+}
+
+#[test]
+fn comparing_two_literals_spills_one() {
+  let (_, fn_decl) = parser::declaration::function_decl("function main()\n  return 5 < 3\nend").unwrap();
+  let procedure = compiler::compile_function(&fn_decl).unwrap();
+  // return register and the spilled `3`
+  assert_eq!(procedure.register_count, 2);
+
+  let mut vm = VirtualMachine::with_functions([("main".to_owned(), procedure)]);
+  assert_eq!(vm.run("main", []).unwrap(), Value::Boolean(false));
+}
+
+#[cfg(feature = "bigint")]
+#[test]
+fn big_integers() {
+  let source_code = r"
+function power()
+  return 100 ^ 20
+end
+
+function product()
+  return 10000000000 * 10000000000 * 10000000000 * 10000000000
+end
+
+function literal()
+  return 99999999999999999999 + 1
+end
+
+function factorial(n)
+  result = 1
+  while n > 1 do
+    result = result * n
+    n = n - 1
+  end
+  return result
+end
+
+function back_to_small()
+  return (9223372036854775807 + 1) - 1
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+
+  let power = vm.run("power", []).unwrap();
+  assert_eq!(power.to_string(), format!("1{}", "0".repeat(40)));
+  assert_eq!(power.type_name(), "integer");
+  assert_eq!(vm.run("product", []).unwrap(), power);
+  assert_eq!(vm.run("literal", []).unwrap().to_string(), "100000000000000000000");
+  assert_eq!(vm.run("factorial", [Value::Integer(25)]).unwrap().to_string(), "15511210043330985984000000");
+  assert_eq!(vm.run("factorial", [Value::Integer(20)]).unwrap(), Value::Integer(2432902008176640000));
+  assert_eq!(vm.run("back_to_small", []).unwrap(), Value::Integer(i64::MAX));
+  assert!(vm.run("power", []).unwrap() > Value::Integer(i64::MAX));
+
+  // statics fold big integers the same way
+  let (_, declarations) = parser::parse_file("static BIG = 9223372036854775807 + 1\nstatic BACK = -(BIG - 1) // 99999999999999999999\n").unwrap();
+  let statics = compiler::compile_statics(declarations.iter()).unwrap();
+  assert_eq!(statics[0].1.to_string(), "9223372036854775808");
+  assert_eq!(statics[1].1, lualite::bytecode::constant_value::ConstantValue::Integer(-1));
+  let (_, declarations) = parser::parse_file("static POWER = 100 ^ 20\n").unwrap();
+  let statics = compiler::compile_statics(declarations.iter()).unwrap();
+  assert_eq!(statics[0].1.to_string(), power.to_string());
+}
+
+#[test]
+fn host_reads_and_writes_globals() {
+  let source_code = r#"
+static GREETING = nil
+static CALLS = 0
+
+function greet()
+  CALLS = CALLS + 1
+  return GREETING
+end
+"#;
+  let (remaining, declarations) = parser::parse_file(source_code).unwrap();
+  assert_eq!(remaining, "");
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+  vm.set_globals(compiler::compile_statics(declarations.iter()).unwrap());
+
+  vm.set_global("GREETING", Value::from("hello")).unwrap();
+  assert_eq!(vm.run("greet", []).unwrap(), Value::from("hello"));
+  assert_eq!(vm.run("greet", []).unwrap(), Value::from("hello"));
+  assert_eq!(vm.get_global("CALLS"), Some(Value::Integer(2)));
+
+  assert!(matches!(vm.set_global("UNDECLARED", Value::Nil), Err(RuntimeError::InvalidGlobal)));
+  assert_eq!(vm.get_global("UNDECLARED"), None);
+}
+
+#[test]
+fn expression_bodied_functions_return_their_expression() {
+  let mut vm = lualite::compile_program("function hypot2(a, b = 0) = a * a + b * b\nfunction twice(x) = hypot2(x, x) * 2").unwrap();
+  assert_eq!(vm.run("hypot2", [3.into(), 4.into()]).unwrap(), Value::Integer(25));
+  assert_eq!(vm.run("twice", [1.into()]).unwrap(), Value::Integer(4));
+}
+
+#[test]
+fn common_subexpressions_give_the_same_results() {
+  use lualite::compiler::CompileOptions;
+  let options = CompileOptions { common_subexpressions: true, ..CompileOptions::default() };
+  let (_, fn_decl) = parser::declaration::function_decl("function f(a, b) return (a + b) * (a + b) end").unwrap();
+  let mut vm = VirtualMachine::new();
+  vm.insert_function("f", compiler::compile_function_with_options(&fn_decl, &[], &options).unwrap());
+  assert_eq!(vm.run("f", [2.into(), 3.into()]).unwrap(), Value::Integer(25));
+}
+
+#[test]
+fn optimized_procedures_behave_like_unoptimized_ones() {
+  use lualite::bytecode::Program;
+  use lualite::compiler::CompileOptions;
+  let options = CompileOptions { simplify: true, common_subexpressions: true, ..CompileOptions::default() };
+  let (_, fn_decl) = parser::declaration::function_decl("function f(x) return x * 1 + (x + 3) * (x + 3) end").unwrap();
+  let plain = compiler::compile_function(&fn_decl).unwrap();
+  let optimized = compiler::compile_function_with_options(&fn_decl, &[], &options).unwrap();
+  assert_ne!(plain, optimized);
+  let inputs = [
+    vec![Value::Integer(0)],
+    vec![Value::Integer(-3)],
+    vec![Value::Integer(7)],
+    vec![Value::Float(1.5)],
+  ];
+  assert!(plain.behaviorally_equal_on(&optimized, &Program::new(), &inputs));
+
+  let (_, other) = parser::declaration::function_decl("function f(x) return x * 2 + (x + 3) * (x + 3) end").unwrap();
+  let other = compiler::compile_function(&other).unwrap();
+  assert!(plain.behaviorally_equal_on(&other, &Program::new(), &inputs[..1]));
+  assert!(!plain.behaviorally_equal_on(&other, &Program::new(), &inputs));
+
+  // helpers come from the program, instead of both sides failing to find them
+  let source_code = "function offset(x) return x + 100 end function g(x) return offset(x) * 2 end function h(x) return offset(x) + offset(x) end function k(x) return offset(x) * 3 end";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let program: Program = compiler::compile_declarations(declarations.iter()).unwrap().into_iter().collect();
+  let (g, h, k) = (program.get("g").unwrap(), program.get("h").unwrap(), program.get("k").unwrap());
+  assert!(g.behaviorally_equal_on(h, &program, &inputs[..3]));
+  assert!(!g.behaviorally_equal_on(k, &program, &inputs[..3]));
+}
+
+#[test]
+fn boolean_immediates_load_as_booleans() {
+  let source_code = r"
+function flags(x)
+  a = true
+  if x == false then
+    a = false
+  end
+  return [a, x != true]
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("flags", [false.into()]).unwrap().to_string(), "[false, true]");
+  assert_eq!(vm.run("flags", [true.into()]).unwrap().to_string(), "[true, false]");
+}
+
+#[test]
+fn comma_separated_calls_run_in_order() {
+  let source_code = r"
+static CALLS = 0
+static ORDER = 0
+
+function f(x)
+  CALLS = CALLS + 1
+  ORDER = ORDER * 10 + x
+end
+
+function g(x)
+  CALLS = CALLS + 1
+  ORDER = ORDER * 10 + x
+end
+
+function both()
+  f(1), g(2)
+end
+
+function both_then_order()
+  g(3), f(4), g(5)
+  return ORDER
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("both", []).unwrap(), Value::Nil);
+  assert_eq!(vm.get_global("ORDER"), Some(Value::Integer(12)));
+  assert_eq!(vm.run("both_then_order", []).unwrap(), Value::Integer(12345));
+  assert_eq!(vm.get_global("CALLS"), Some(Value::Integer(5)));
+}
+
+#[test]
+fn loop_conditions_keep_their_temporaries() {
+  let source_code = r"
+function pair(x, y)
+  return x + y
+end
+
+function count(a, b, c)
+  iterations = 0
+  while a + b < c do
+    t = pair(a * 2, b * 3) + pair(a + 1, pair(b, 1)) * (a - b)
+    a = a + 1
+    iterations = iterations + 1
+  end
+  return iterations
+end
+
+function nested(n)
+  total = 0
+  i = 0
+  while i * 2 < n * 2 do
+    j = 0
+    while j + i < n do
+      total = total + pair(i * j, pair(j, i) - i)
+      j = j + 1
+    end
+    i = i + 1
+  end
+  return total
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+  assert_eq!(vm.run("count", [1.into(), 2.into(), 10.into()]).unwrap(), Value::Integer(7));
+  assert_eq!(vm.run("count", [5.into(), 5.into(), 10.into()]).unwrap(), Value::Integer(0));
+  // sum of (i + 1) * j for every j < n - i
+  assert_eq!(vm.run("nested", [4.into()]).unwrap(), Value::Integer(15));
+}
+
+#[test]
+fn too_many_arguments() {
+  let (_, fn_decl) = parser::declaration::function_decl("function double(x) return x * 2 end").unwrap();
+  let procedure = compiler::compile_function(&fn_decl).unwrap();
+  let mut vm = VirtualMachine::with_functions([("double", procedure)]);
+
+  let result = vm.run("double", [1.into(), 2.into(), 3.into(), 4.into()]);
+  assert!(matches!(result, Err(RuntimeError::TooManyArguments { max_args: 1, given: 4 })));
+  // nothing was left on the call stack, so the same VM can still run
+  assert_eq!(vm.run("double", [21.into()]).unwrap(), Value::Integer(42));
+  assert_eq!(vm.run("double", []).unwrap(), Value::Nil);
+}
+
+#[test]
+fn compile_program_loads_everything() {
+  let source_code = r"
+static STEP = 1
+
+function gcd(a, b)
+  while a != b do
+    if a > b then
+      a = a - b
+    else
+      b = b - a
+    end
+  end
+  return a
+end
+
+function next(x)
+  return x + STEP
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("gcd", [25000.into(), 135.into()]).unwrap(), Value::Integer(5));
+  assert_eq!(vm.run("next", [1.into()]).unwrap(), Value::Integer(2));
+
+  let broken = "function ok()\n  return 1\nend\n\nfunction broken(a\n  return a\nend\n";
+  assert!(matches!(lualite::compile_program(broken), Err(lualite::ProgramError::Parse(error)) if error.line == 5));
+  let unresolved = "function main()\n  return missing()\nend\n";
+  assert!(matches!(
+    lualite::compile_program(unresolved),
+    Err(lualite::ProgramError::Compile(compiler::CompileError::UnresolvedFunction(name))) if name == "missing"
+  ));
+}
+
+#[test]
+fn default_parameters() {
+  let source_code = r#"
+function f(a, b = 10)
+  return a + b
+end
+
+function greeting(name = "world", punctuation = "!")
+  return [name, punctuation]
+end
+
+function call_f_with_one()
+  return f(5)
+end
+
+function call_f_with_three()
+  return f(1, 2, 3)
+end
+
+function call_f_with_nil(b)
+  return f(1, b)
+end
+
+function call_greeting_by_name()
+  return greeting(punctuation = "?")
+end
+
+function fresh(items = [])
+  items[0] = 1
+  return items
+end
+"#;
+  let (remaining, declarations) = parser::parse_file(source_code).unwrap();
+  assert_eq!(remaining, "");
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+
+  assert_eq!(vm.run("call_f_with_one", []).unwrap(), Value::Integer(15));
+  assert_eq!(vm.run("f", [5.into()]).unwrap(), Value::Integer(15));
+  assert_eq!(vm.run("f", [5.into(), 1.into()]).unwrap(), Value::Integer(6));
+  assert_eq!(vm.run("f", [5.into(), Value::Nil]).unwrap(), Value::Integer(15));
+  assert_eq!(vm.run("greeting", []).unwrap().to_string(), r#"["world", "!"]"#);
+  assert_eq!(vm.run("greeting", ["lualite".into()]).unwrap().to_string(), r#"["lualite", "!"]"#);
+  // a `nil` argument can't be told apart from a missing one, both get the default
+  assert_eq!(vm.run("call_f_with_nil", [Value::Nil]).unwrap(), Value::Integer(11));
+  assert_eq!(vm.run("call_f_with_nil", [2.into()]).unwrap(), Value::Integer(3));
+  assert_eq!(vm.run("call_greeting_by_name", []).unwrap().to_string(), r#"["world", "?"]"#);
+  // defaults are evaluated on each call
+  let first = vm.run("fresh", []).unwrap();
+  assert_eq!(vm.run("fresh", []).unwrap(), first);
+  first.clone().set(0.into(), 2.into()).unwrap();
+  assert_eq!(vm.run("fresh", []).unwrap().to_string(), "[1]");
+
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+  assert!(matches!(vm.run("call_f_with_three", []), Err(RuntimeError::TooManyArguments { max_args: 2, given: 3 })));
+}
+
+#[test]
+fn for_range_loops() {
+  let source_code = r"
+function digits(a, b)
+  total = 0
+  for i in a..b do
+    total = total * 10 + i
+  end
+  return total
+end
+
+function iterations(a, b)
+  count = 0
+  for i in a..b do
+    count = count + 1
+    i = 100
+  end
+  return count
+end
+
+function shrinking_end(n)
+  count = 0
+  for i in 0..n do
+    n = 0
+    count = count + 1
+  end
+  return count
+end
+";
+  let (remaining, declarations) = parser::parse_file(source_code).unwrap();
+  assert_eq!(remaining, "");
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+
+  // iterates 0, 1, 2
+  assert_eq!(vm.run("digits", [0.into(), 3.into()]).unwrap(), Value::Integer(12));
+  assert_eq!(vm.run("digits", [1.into(), 4.into()]).unwrap(), Value::Integer(123));
+  assert_eq!(vm.run("iterations", [1.into(), 1.into()]).unwrap(), Value::Integer(0));
+  assert_eq!(vm.run("iterations", [5.into(), 2.into()]).unwrap(), Value::Integer(0));
+  // assigning to the loop variable doesn't change the number of iterations
+  assert_eq!(vm.run("iterations", [0.into(), 4.into()]).unwrap(), Value::Integer(4));
+  // the end of the range is only evaluated once
+  assert_eq!(vm.run("shrinking_end", [3.into()]).unwrap(), Value::Integer(3));
+}
+
+#[test]
+fn self_referential_arrays() {
+  let source_code = r"
+function cycle(x)
+  a = [x, nil]
+  a[1] = a
+  return a
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+  let first = vm.run("cycle", [1.into()]).unwrap();
+  let second = vm.run("cycle", [1.into()]).unwrap();
+  let different = vm.run("cycle", [2.into()]).unwrap();
+
+  assert_eq!(first.to_string(), "[1, [...]]");
+  assert_eq!(first, first.clone());
+  assert_eq!(first, second);
+  assert_ne!(first, different);
+  // an array that appears twice without a cycle is printed in full both times
+  let shared = Value::from_iter([1, 2]);
+  assert_eq!(Value::from_iter([shared.clone(), shared]).to_string(), "[[1, 2], [1, 2]]");
+}
+
+#[test]
+fn arrays_equal_themselves_without_comparing_elements() {
+  let array = Value::from_iter([Value::Float(f64::NAN), 1.into()]);
+  let alias = array.clone();
+  // a NaN isn't equal to itself, but the same array is
+  assert_eq!(array, alias);
+  let Value::Array(elements) = &array else { panic!("expected an array") };
+  let _borrowed = elements.borrow_mut();
+  assert!(array == alias);
+
+  let first = Value::from_iter([Value::from("a"), Value::from_iter([1, 2])]);
+  let second = Value::from_iter([Value::from("a"), Value::from_iter([1, 2])]);
+  assert_eq!(first, second);
+}
+
+#[test]
+fn arrays_from_rust_collections() {
+  let expected = Value::from_iter([1, 2]);
+  assert_eq!(Value::from(vec![1.into(), 2.into()]), expected);
+  assert_eq!(Value::from(&[Value::from(1), 2.into()][..]), expected);
+  assert_eq!(Value::from([Value::from(1), Value::from(2)]), expected);
+  assert!(matches!(Value::from(Vec::new()), Value::Array(array) if array.borrow().is_empty()));
+}
+
+#[test]
+fn run_main_runs_the_entry_function() {
+  let source_code = r"
+function main()
+  return helper(20) + 1
+end
+
+function helper(x)
+  return x * 2
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+  assert_eq!(vm.run_main().unwrap(), Value::Integer(41));
+
+  let mut vm = VirtualMachine::new();
+  assert!(matches!(vm.run_main(), Err(RuntimeError::MissingFunction)));
+}
+
+#[test]
+fn trace_hook_sees_every_instruction() {
+  use std::{rc::Rc, cell::RefCell};
+  use lualite::runtime::{InstructionCount, ExecutionStatus};
+  let source_code = r"
+function gcd(a, b)
+  while a != b do
+    if a > b then
+      a = a - b
+    else
+      b = b - a
+    end
+  end
+  return a
+end
+";
+  let (_, fn_decl) = parser::declaration::function_decl(source_code.trim()).unwrap();
+  let procedure = Rc::new(compiler::compile_function(&fn_decl).unwrap());
+
+  // count the steps by running one instruction at a time
+  let mut vm = VirtualMachine::new();
+  vm.initialize_with_values(Rc::clone(&procedure), [48.into(), 18.into()]).unwrap();
+  let mut steps = 0;
+  while let ExecutionStatus::Unfinished = vm.execution_loop(InstructionCount::Limited(1)).unwrap() {
+    steps += 1;
+  }
+  assert_eq!(vm.get_result(), Value::Integer(6));
+
+  let traced = Rc::new(RefCell::new(Vec::new()));
+  let recorder = Rc::clone(&traced);
+  let mut vm = VirtualMachine::with_functions([("gcd", compiler::compile_function(&fn_decl).unwrap())]);
+  vm.set_trace_hook(Box::new(move |pc, instruction| recorder.borrow_mut().push((pc, instruction))));
+  assert_eq!(vm.run("gcd", [48.into(), 18.into()]).unwrap(), Value::Integer(6));
+  let traced = traced.borrow();
+  assert_eq!(traced.len(), steps);
+  assert!(traced.iter().all(|&(pc, instruction)| procedure.bytecode[pc].0 == instruction.0));
+
+  assert!(vm.clear_trace_hook().is_some());
+  assert_eq!(vm.run("gcd", [48.into(), 18.into()]).unwrap(), Value::Integer(6));
+  assert_eq!(traced.len(), steps);
+}
+
+#[test]
+fn string_repetition() {
+  assert_eq!(Value::from("ab") * Value::Integer(3), Value::from("ababab"));
+  assert_eq!(Value::Integer(3) * Value::from("x"), Value::from("xxx"));
+  assert_eq!(Value::from("x") * Value::Integer(0), Value::from(""));
+  assert_eq!(Value::from("x") * Value::Integer(-1), Value::Nil);
+  // grows from a short string into a long one
+  let repeated = Value::from("abcd") * Value::Integer(5);
+  assert!(matches!(repeated, Value::LongStr(_)));
+  assert_eq!(repeated.as_str(), Some("abcdabcdabcdabcdabcd"));
+  assert_eq!(Value::from("ab") * Value::Float(2.0), Value::Nil);
+
+  let (_, fn_decl) = parser::declaration::function_decl(r#"function line(n) return "-" * n end"#).unwrap();
+  let mut vm = VirtualMachine::with_functions([("line", compiler::compile_function(&fn_decl).unwrap())]);
+  assert_eq!(vm.run("line", [4.into()]).unwrap(), Value::from("----"));
+}
+
+#[test]
+fn value_predicates() {
+  let values = [
+    Value::Nil,
+    Value::Boolean(false),
+    Value::Boolean(true),
+    Value::Integer(0),
+    Value::Float(0.0),
+    Value::from("short"),
+    Value::from("a much longer string"),
+    Value::from_iter([1, 2]),
+  ];
+  let predicates: [(&str, fn(&Value) -> bool); 7] = [
+    ("is_nil", Value::is_nil),
+    ("is_integer", Value::is_integer),
+    ("is_float", Value::is_float),
+    ("is_number", Value::is_number),
+    ("is_string", Value::is_string),
+    ("is_array", Value::is_array),
+    ("is_truthy", Value::is_truthy),
+  ];
+  // one row per value, one column per predicate in the order above
+  let expected = [
+    [true, false, false, false, false, false, false],
+    [false, false, false, false, false, false, false],
+    [false, false, false, false, false, false, true],
+    [false, true, false, true, false, false, true],
+    [false, false, true, true, false, false, true],
+    [false, false, false, false, true, false, true],
+    [false, false, false, false, true, false, true],
+    [false, false, false, false, false, true, true],
+  ];
+  for (value, row) in values.iter().zip(expected) {
+    for ((name, predicate), expected) in predicates.iter().zip(row) {
+      assert_eq!(predicate(value), expected, "{value}.{name}()");
+    }
+  }
+}
+
+#[test]
+fn elseif_chains() {
+  let source_code = r"
+function with_else(x)
+  if x == 1 then
+    return 10
+  elseif x == 2 then
+    return 20
+  elseif x == 3 then
+    return 30
+  elseif x == 4 then
+    return 40
+  else
+    return 0
+  end
+end
+
+function without_else(x)
+  result = nil
+  if x == 1 then
+    result = 10
+  elseif x == 2 then
+    result = 20
+  elseif x == 3 then
+    result = 30
+  elseif x == 4 then
+    result = 40
+  end
+  return result
+end
+";
+  let (remaining, declarations) = parser::parse_file(source_code).unwrap();
+  assert_eq!(remaining, "");
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+  for x in 1..=4 {
+    assert_eq!(vm.run("with_else", [x.into()]).unwrap(), Value::Integer(x * 10), "with_else({x})");
+    assert_eq!(vm.run("without_else", [x.into()]).unwrap(), Value::Integer(x * 10), "without_else({x})");
+  }
+  assert_eq!(vm.run("with_else", [5.into()]).unwrap(), Value::Integer(0));
+  assert_eq!(vm.run("without_else", [5.into()]).unwrap(), Value::Nil);
+}
+
+#[test]
+fn array_slices() {
+  let source_code = r"
+function middle(a)
+  return a[1:3]
+end
+
+function head(a)
+  return a[:2]
+end
+
+function tail(a)
+  return a[2:]
+end
+
+function between(a, i, j)
+  return a[i:j]
+end
+";
+  let (remaining, declarations) = parser::parse_file(source_code).unwrap();
+  assert_eq!(remaining, "");
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+  let array = || Value::from_iter([10, 20, 30, 40]);
+
+  assert_eq!(vm.run("middle", [array()]).unwrap(), Value::from_iter([20, 30]));
+  assert_eq!(vm.run("head", [array()]).unwrap(), Value::from_iter([10, 20]));
+  assert_eq!(vm.run("tail", [array()]).unwrap(), Value::from_iter([30, 40]));
+  // bounds past the end are clamped, and an empty range is an empty array
+  assert_eq!(vm.run("between", [array(), 3.into(), 10.into()]).unwrap(), Value::from_iter([40]));
+  assert_eq!(vm.run("between", [array(), 3.into(), 1.into()]).unwrap(), Value::from_iter::<[i64; 0]>([]));
+  // negative bounds give nil, like indexing
+  assert_eq!(vm.run("between", [array(), (-1).into(), 2.into()]).unwrap(), Value::Nil);
+  // the slice is a copy
+  let original = array();
+  let slice = vm.run("head", [original.clone()]).unwrap();
+  slice.clone().set(0.into(), 99.into()).unwrap();
+  assert_eq!(original, array());
+  assert!(matches!(vm.run("head", [5.into()]), Err(RuntimeError::NotIndexable { type_name: "integer" })));
+
+  // a script's own `slice` doesn't change what slice expressions do
+  let source_code = r"
+function slice(a, i, j)
+  return 0
+end
+
+function middle(a)
+  return a[1:3]
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("middle", [array()]).unwrap(), Value::from_iter([20, 30]));
+}
+
+#[test]
+fn nested_functions() {
+  let source_code = r"
+static SCALE = 10
+
+function main(x)
+  function scale(y)
+    return y * SCALE
+  end
+  function twice(y)
+    return scale(scale(y))
+  end
+  return twice(x) + 1
+end
+
+function scale(y)
+  return -y
+end
+
+function other(x)
+  return scale(x)
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("main", [2.into()]).unwrap(), Value::Integer(201));
+  // the nested function doesn't replace the top-level function with the same name
+  assert_eq!(vm.run("other", [2.into()]).unwrap(), Value::Integer(-2));
+  assert_eq!(vm.run("main.scale", [3.into()]).unwrap(), Value::Integer(30));
+}
+
+#[test]
+fn nested_functions_cannot_capture_locals() {
+  use lualite::compiler::CompileError;
+  let source_code = r"
+function outer(x)
+  offset = 1
+  function inner(y)
+    return y + offset
+  end
+  return inner(x)
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let error = compiler::compile_declarations(declarations.iter()).unwrap_err();
+  assert!(matches!(error, CompileError::CapturedVariable(name) if name == "offset"));
+
+  // a parameter of the nested function with the same name as a local is its own
+  let source_code = "function outer(x) function inner(x) return x * 3 end return inner(x + 1) end";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("outer", [1.into()]).unwrap(), Value::Integer(6));
+
+  // so is a local the nested function assigns before reading
+  let source_code = r"
+function outer(x)
+  t = 1
+  function inner(y)
+    t = y * 2
+    return t
+  end
+  return inner(x) + t
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("outer", [5.into()]).unwrap(), Value::Integer(11));
+
+  // but not one it only assigns in one branch
+  let source_code = r"
+function outer(x)
+  t = 1
+  function inner(y)
+    if y > 0 then t = y end
+    return t
+  end
+  return inner(x)
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let error = compiler::compile_declarations(declarations.iter()).unwrap_err();
+  assert!(matches!(error, CompileError::CapturedVariable(name) if name == "t"));
+}
+
+#[test]
+fn byte_buffers() {
+  let source_code = r"
+function fill(n)
+  buffer = bytes(n)
+  for i in 0..n do
+    buffer[i] = i * 16
+  end
+  set_byte(buffer, 0, 255)
+  return buffer
+end
+
+function read(buffer, i)
+  return [buffer[i], get_byte(buffer, i)]
+end
+
+function store(buffer, value)
+  buffer[0] = value
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  let buffer = vm.run("fill", [4.into()]).unwrap();
+  assert_eq!(buffer.to_string(), "bytes[255, 16, 32, 48]");
+  assert_eq!(buffer.type_name(), "bytes");
+  assert_eq!(vm.run("read", [buffer.clone(), 2.into()]).unwrap(), Value::from_iter([32, 32]));
+  assert_eq!(vm.run("read", [buffer.clone(), 9.into()]).unwrap(), Value::from_iter([Value::Nil, Value::Nil]));
+  assert_eq!(buffer.slice(Value::Integer(1), Value::Integer(3)).unwrap().to_string(), "bytes[16, 32]");
+
+  assert!(matches!(vm.run("store", [buffer.clone(), 256.into()]), Err(RuntimeError::InvalidByte)));
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert!(matches!(vm.run("store", [buffer.clone(), "a".into()]), Err(RuntimeError::InvalidByte)));
+  assert_eq!(buffer.get(Value::Integer(0)).unwrap(), Value::Integer(255));
+
+  // a buffer too large to allocate is nil instead of aborting
+  let mut vm = lualite::compile_program("function huge() return bytes(9223372036854775807) end").unwrap();
+  assert_eq!(vm.run("huge", []).unwrap(), Value::Nil);
+}
+
+#[test]
+fn run_while_paused_is_busy() {
+  use std::rc::Rc;
+  use lualite::runtime::{InstructionCount, ExecutionStatus};
+  let source_code = r"
+function count(n)
+  total = 0
+  for i in 0..n do
+    total = total + i
+  end
+  return total
+end
+
+function fail()
+  return [1][0][0]
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  let count = vm.get_function("count").unwrap();
+  vm.initialize_with_values(Rc::clone(&count), [100.into()]).unwrap();
+  assert!(matches!(vm.execution_loop(InstructionCount::Limited(10)), Ok(ExecutionStatus::Unfinished)));
+  assert!(matches!(vm.run("count", [3.into()]), Err(RuntimeError::VmBusy)));
+  // the paused computation wasn't disturbed
+  assert!(matches!(vm.execution_loop(InstructionCount::Unlimited), Ok(ExecutionStatus::Finished)));
+  assert_eq!(vm.get_result(), Value::Integer(4950));
+
+  vm.initialize_with_values(count, [100.into()]).unwrap();
+  vm.abort();
+  assert_eq!(vm.run("count", [3.into()]).unwrap(), Value::Integer(3));
+
+  // a failed run doesn't leave the virtual machine busy
+  assert!(matches!(vm.run("fail", []), Err(RuntimeError::NotIndexable { .. })));
+  assert_eq!(vm.run("count", [4.into()]).unwrap(), Value::Integer(6));
+}
+
+#[test]
+fn integer_fast_path_matches_value_operators() {
+  let source_code = r"
+function ops(a, b)
+  return [a + b, a - b, a * b, a // b, a % b, -a, a + 1, 3 - b, a < b, a == b, b >= 2, a != 7]
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  let samples = [-7, -1, 0, 1, 2, 3, 7, 100];
+  for a in samples {
+    for b in samples {
+      let (x, y) = (Value::Integer(a), Value::Integer(b));
+      let expected = Value::from_iter([
+        x.clone() + y.clone(),
+        x.clone() - y.clone(),
+        x.clone() * y.clone(),
+        x.clone().floor_div(y.clone()),
+        x.clone() % y.clone(),
+        -x.clone(),
+        x.clone() + Value::Integer(1),
+        Value::Integer(3) - y.clone(),
+        Value::from(x < y),
+        Value::from(x == y),
+        Value::from(y >= Value::Integer(2)),
+        Value::from(x != Value::Integer(7)),
+      ]);
+      assert_eq!(vm.run("ops", [a.into(), b.into()]).unwrap(), expected, "ops({a}, {b})");
+    }
+  }
+  // mixed operands still take the general path
+  assert_eq!(vm.run("ops", [1.into(), 2.5.into()]).unwrap().get(Value::Integer(0)).unwrap(), Value::Nil);
+}
+
+#[test]
+fn print_writes_to_the_configured_output() {
+  use std::{rc::Rc, cell::RefCell};
+
+  #[derive(Clone, Default)]
+  struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+  impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  let source_code = r#"
+function main()
+  print("hi")
+  println(" there", 1, 2.5, [nil, "s"])
+  println()
+  return print("done")
+end
+"#;
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  let buffer = SharedBuffer::default();
+  vm.set_output(Box::new(buffer.clone()));
+  assert_eq!(vm.run_main().unwrap(), Value::Nil);
+  assert_eq!(String::from_utf8(buffer.0.take()).unwrap(), "hi there\t1\t2.5\t[nil, \"s\"]\n\ndone");
+
+  // other natives write to the same output, and can replace `print`
+  fn shout(context: &mut lualite::runtime::native::Context, args: &[Value]) -> Result<Value, RuntimeError> {
+    let text = args.first().map(Value::to_display_string).unwrap_or_default().to_uppercase();
+    context.output().write_all(format!("{text}!").as_bytes()).unwrap();
+    Ok(Value::Nil)
+  }
+  vm.insert_native("print", shout);
+  vm.run_main().unwrap();
+  assert_eq!(String::from_utf8(buffer.0.take()).unwrap(), "HI! there\t1\t2.5\t[nil, \"s\"]\n\nDONE!");
+}
+
+#[test]
+fn expression_statements_do_not_set_the_return_value() {
+  let source_code = r"
+function f(x)
+  return x * 10
+end
+
+function g(y)
+  return y + 1
+end
+
+function explicit(x, y)
+  f(x)
+  return g(y)
+end
+
+function implicit(x)
+  f(x)
+  y = 2
+end
+
+function trailing(x)
+  f(x)
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("explicit", [3.into(), 4.into()]).unwrap(), Value::Integer(5));
+  assert_eq!(vm.run("implicit", [3.into()]).unwrap(), Value::Nil);
+  // a trailing expression is still the return value
+  assert_eq!(vm.run("trailing", [3.into()]).unwrap(), Value::Integer(30));
+}
+
+#[test]
+fn byte_string_literals_load_byte_buffers() {
+  let source_code = r#"
+function literal()
+  return b"abc"
+end
+
+function fresh()
+  a = b"abc"
+  a[0] = 65
+  return [a, b"abc"]
+end
+"#;
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("literal", []).unwrap(), Value::from(&b"abc"[..]));
+  assert_eq!(vm.run("literal", []).unwrap().to_string(), "bytes[97, 98, 99]");
+  // every load is a new buffer, so mutating one doesn't change the constant
+  assert_eq!(vm.run("fresh", []).unwrap(), Value::from([Value::from(&b"Abc"[..]), Value::from(&b"abc"[..])]));
+}
+
+#[test]
+fn sort_arrays() {
+  let source_code = r#"
+function sorted(a)
+  sort(a)
+  return a
+end
+
+function less(a, b)
+  return a < b
+end
+"#;
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  let ints = Value::from_iter([3, -1, 2, 10, 0].map(Value::from));
+  assert_eq!(vm.run("sorted", [ints]).unwrap(), Value::from_iter([-1, 0, 2, 3, 10].map(Value::from)));
+  let floats = Value::from_iter([2.5, -0.5, 1.25].map(Value::from));
+  assert_eq!(vm.run("sorted", [floats]).unwrap(), Value::from_iter([-0.5, 1.25, 2.5].map(Value::from)));
+  let strings = Value::from_iter(["pear", "apple", "fig"].map(Value::from));
+  assert_eq!(vm.run("sorted", [strings]).unwrap(), Value::from_iter(["apple", "fig", "pear"].map(Value::from)));
+  assert_eq!(vm.run("less", [1.5.into(), 2.5.into()]).unwrap(), Value::Boolean(true));
+  assert_eq!(vm.run("less", ["b".into(), "a".into()]).unwrap(), Value::Boolean(false));
+
+  let mixed = Value::from_iter([Value::from(2), Value::from("one"), Value::from(3)]);
+  let error = vm.run("sorted", [mixed.clone()]).unwrap_err();
+  assert!(matches!(error, RuntimeError::IncomparableTypes { .. }), "{error:?}");
+  // a failed sort leaves the array as it was
+  assert_eq!(mixed, Value::from_iter([Value::from(2), Value::from("one"), Value::from(3)]));
+
+  let mixed = Value::from_iter([Value::from(1), Value::from("a"), Value::from(2)]);
+  let error = vm.run("sorted", [mixed]).unwrap_err();
+  assert!(matches!(error, RuntimeError::IncomparableTypes { .. }), "{error:?}");
+  let nan = Value::from_iter([3.0, f64::NAN, 1.0, 2.0].map(Value::from));
+  let error = vm.run("sorted", [nan]).unwrap_err();
+  assert!(matches!(error, RuntimeError::IncomparableTypes { left: "float", right: "float" }), "{error:?}");
+}
+
+#[test]
+fn searching_arrays_and_strings() {
+  let source_code = r#"
+function search(container, value)
+  return [contains(container, value), index_of(container, value)]
+end
+"#;
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  let mut search = |container: Value, value: Value| vm.run("search", [container, value]).unwrap();
+  let found = |index: i64| Value::from_iter([Value::Boolean(true), Value::Integer(index)]);
+  let missing = Value::from_iter([Value::Boolean(false), Value::Integer(-1)]);
+
+  let array = Value::from_iter([Value::from(1), Value::from("two"), Value::from(3.0), Value::from("two")]);
+  assert_eq!(search(array.clone(), "two".into()), found(1));
+  assert_eq!(search(array.clone(), 3.0.into()), found(2));
+  assert_eq!(search(array.clone(), 3.into()), missing);
+  assert_eq!(search("hello world".into(), "world".into()), found(6));
+  assert_eq!(search("a much longer string to search".into(), "search".into()), found(24));
+  // indices count characters, like indexing a string does
+  assert_eq!(search("héllo".into(), "llo".into()), found(2));
+  assert_eq!(search("hello".into(), "xyz".into()), missing);
+  assert_eq!(search("hello".into(), 1.into()), missing);
+  assert_eq!(search(5.into(), 5.into()), Value::from_iter([Value::Nil, Value::Nil]));
+}
+
+#[test]
+fn conditional_expressions() {
+  let source_code = r#"
+function constant()
+  return 1 < 2 ? 10 : 20
+end
+
+function max(a, b)
+  m = a > b ? a : b
+  return m
+end
+
+function sign(x)
+  return x < 0 ? -1 : x == 0 ? 0 : 1
+end
+
+function record(log, value)
+  log[0] = value
+  return value
+end
+
+function only_taken(flag)
+  log = [nil]
+  result = flag ? record(log, "then") : record(log, "else")
+  return [result, log]
+end
+"#;
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("constant", []).unwrap(), Value::Integer(10));
+  assert_eq!(vm.run("max", [3.into(), 7.into()]).unwrap(), Value::Integer(7));
+  assert_eq!(vm.run("max", [9.into(), 7.into()]).unwrap(), Value::Integer(9));
+  for (x, expected) in [(-5, -1), (0, 0), (5, 1)] {
+    assert_eq!(vm.run("sign", [x.into()]).unwrap(), Value::Integer(expected), "sign({x})");
+  }
+  let taken = |branch: &str| Value::from_iter([Value::from(branch), Value::from_iter([Value::from(branch)])]);
+  assert_eq!(vm.run("only_taken", [true.into()]).unwrap(), taken("then"));
+  assert_eq!(vm.run("only_taken", [Value::Nil]).unwrap(), taken("else"));
+}
+
+#[test]
+fn not_is_a_boolean() {
+  let source_code = r"
+function negate(x)
+  return not x
+end
+
+function neither(a, b)
+  return not a and not b
+end
+
+function in_place(x)
+  x = not x
+  return x
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  for (x, expected) in [(Value::Nil, true), (false.into(), true), (true.into(), false), (0.into(), false)] {
+    assert_eq!(vm.run("negate", [x.clone()]).unwrap(), Value::Boolean(expected), "not {x}");
+    assert_eq!(vm.run("in_place", [x.clone()]).unwrap(), Value::Boolean(expected), "not {x}");
+  }
+  assert_eq!(vm.run("neither", [Value::Nil, false.into()]).unwrap(), Value::Boolean(true));
+  assert_eq!(vm.run("neither", [Value::Nil, 1.into()]).unwrap(), Value::Boolean(false));
+}
+
+#[test]
+fn dump_state_shows_every_frame() {
+  use lualite::runtime::{InstructionCount, ExecutionStatus};
+
+  let source_code = r"
+function inner(y)
+  z = y * 3
+  return z + 1
+end
+
+function outer(x)
+  return inner(x + 1)
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.dump_state(), "call stack: (empty)\n");
+  vm.initialize_with_values(vm.get_function("outer").unwrap(), [41.into()]).unwrap();
+  assert!(vm.dump_state().contains("frame 0: outer\n  not started\n"));
+  // step until `z = y * 3` has run inside `inner`
+  let mut state = String::new();
+  while !state.contains("= 126") {
+    assert!(matches!(vm.execution_loop(InstructionCount::Limited(1)).unwrap(), ExecutionStatus::Unfinished));
+    state = vm.dump_state();
+  }
+  assert!(state.starts_with("frame 0: inner\n"), "{state}");
+  assert!(state.contains("frame 1: outer\n"), "{state}");
+  assert!(state.contains("R1 = 42"), "{state}");
+  assert!(state.contains("call"), "{state}");
+
+  // a loop back to the first instruction doesn't look like the function hasn't started
+  let (_, declarations) = parser::parse_file("function spin(n) while true do n = n + 1 end end").unwrap();
+  let options = compiler::CompileOptions { nop_free: true, ..compiler::CompileOptions::default() };
+  let functions = compiler::compile_declarations_with_options(declarations.iter(), &options).unwrap();
+  let mut vm = VirtualMachine::with_functions(functions);
+  vm.initialize_with_values(vm.get_function("spin").unwrap(), [0.into()]).unwrap();
+  let mut looped = false;
+  for step in 0..10 {
+    vm.execution_loop(InstructionCount::Limited(1)).unwrap();
+    let state = vm.dump_state();
+    assert!(state.starts_with("frame 0: spin\n  at "), "{state}");
+    looped |= step > 0 && state.contains("  at 0: ");
+  }
+  assert!(looped);
+}
+
+#[test]
+fn short_strings_are_stored_inline() {
+  let inline = "fourteen bytes";
+  let heap = "fifteen bytes!!";
+  assert!(matches!(Value::from(inline), Value::ShortStr { len: 14, .. }));
+  assert!(matches!(Value::from(inline.to_owned()), Value::ShortStr { len: 14, .. }));
+  assert!(matches!(Value::from(heap), Value::LongStr(_)));
+  assert!(matches!(Value::from(heap.to_owned()), Value::LongStr(_)));
+  // a multibyte character that would straddle the end of the buffer moves the whole string
+  assert!(matches!(Value::from("thirteen byte\u{e9}"), Value::LongStr(_)));
+  for string in [inline, heap, "thirteen byte\u{e9}"] {
+    assert_eq!(Value::from(string).as_str(), Some(string));
+    assert_eq!(Value::from(string).to_string(), format!("\"{string}\""));
+  }
+}
+
+#[test]
+fn continue_in_for_loops_still_advances() {
+  let source_code = r"
+function odd_indices(n)
+  visited = [nil]
+  count = 0
+  for i in 0..n do
+    if i % 2 == 0 then
+      continue
+    end
+    visited[count] = i
+    count = count + 1
+  end
+  return visited
+end
+
+function first_multiple(n, m)
+  found = nil
+  for i in 1..n do
+    if i % m == 0 then
+      found = i
+      break
+    end
+  end
+  return found
+end
+
+function while_loops(n)
+  i = 0
+  total = 0
+  while true do
+    i = i + 1
+    if i > n then
+      break
+    elseif i % 3 == 0 then
+      continue
+    end
+    total = total + i
+  end
+  return total
+end
+
+function nested(n)
+  pairs = 0
+  for i in 0..n do
+    for j in 0..n do
+      if j >= i then
+        break
+      end
+      pairs = pairs + 1
+    end
+    if i == 2 then
+      continue
+    end
+  end
+  return pairs
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("odd_indices", [8.into()]).unwrap(), Value::from_iter([1, 3, 5, 7]));
+  assert_eq!(vm.run("first_multiple", [20.into(), 7.into()]).unwrap(), Value::Integer(7));
+  assert_eq!(vm.run("first_multiple", [5.into(), 7.into()]).unwrap(), Value::Nil);
+  // 1 + 2 + 4 + 5 + 7
+  assert_eq!(vm.run("while_loops", [7.into()]).unwrap(), Value::Integer(19));
+  assert_eq!(vm.run("nested", [5.into()]).unwrap(), Value::Integer(10));
+
+  for source_code in ["function main() break end", "function main() if true then continue end end"] {
+    match lualite::compile_program(source_code) {
+      Err(lualite::ProgramError::Compile(compiler::CompileError::OutsideLoop(_))) => (),
+      other => panic!("expected a loop control error for {source_code:?}, got {other:?}"),
+    }
+  }
+}
+
+#[test]
+fn numeric_natives() {
+  let source_code = r#"
+function numbers(a, b)
+  return [min(a, b), max(a, b), abs(a)]
+end
+"#;
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  let mut numbers = |a: Value, b: Value| vm.run("numbers", [a, b]).unwrap();
+  assert_eq!(numbers((-3).into(), 7.into()), Value::from_iter([-3, 7, 3]));
+  assert_eq!(numbers(4.into(), 4.into()), Value::from_iter([4, 4, 4]));
+  assert_eq!(numbers((-1.5).into(), 0.25.into()), Value::from_iter([-1.5, 0.25, 1.5]));
+  // mixed integers and floats compare by value but keep their type
+  assert_eq!(numbers(3.into(), 2.5.into()), Value::from_iter([Value::from(2.5), Value::from(3), Value::from(3)]));
+  assert_eq!(numbers(2.0.into(), 2.into()), Value::from_iter([Value::from(2.0), Value::from(2.0), Value::from(2.0)]));
+  assert_eq!(numbers("a".into(), 1.into()), Value::from_iter([Value::Nil, Value::Nil, Value::Nil]));
+  assert_eq!(numbers(f64::NAN.into(), 1.into()).get(Value::Integer(1)).unwrap(), Value::Nil);
+}
+
+#[test]
+fn tail_recursion_as_loops_matches_recursion() {
+  use lualite::compiler::CompileOptions;
+  let source_code = r"
+function binary_search_helper(array, first, last, needle)
+  if first <= last then
+    mid = (first + last) // 2
+    mid_value = array[mid]
+    if needle < mid_value then
+      return binary_search_helper(array, first, mid - 1, needle)
+    elseif needle > mid_value then
+      return binary_search_helper(array, mid + 1, last, needle)
+    else
+      return mid
+    end
+  else
+    return false
+  end
+end
+
+function gcd(a, b)
+  if b == 0 then
+    return a
+  end
+  gcd(b, a % b)
+end
+
+function seen_before(n, seen)
+  if n == 0 then
+    return seen
+  end
+  if n == 1 then
+    seen = previous
+  end
+  previous = n
+  return seen_before(n - 1)
+end
+
+function count_down(n)
+  if n > 0 then
+    return count_down(n - 1)
+  end
+  return n
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let recursive = compiler::compile_declarations(declarations.iter()).unwrap();
+  let options = CompileOptions { tail_recursion: true, ..CompileOptions::default() };
+  let looped = compiler::compile_declarations_with_options(declarations.iter(), &options).unwrap();
+  for (name, procedure) in looped.iter() {
+    assert!(procedure.function_table().is_empty(), "{name} still makes calls:\n{procedure}");
+  }
+  let mut recursive = VirtualMachine::with_functions(recursive);
+  let mut looped = VirtualMachine::with_functions(looped);
+
+  let array = Value::from_iter([1, 3, 4, 6, 8, 9, 10, 11, 14, 15, 20, 21, 33]);
+  for needle in -2..40 {
+    let args = || [array.clone(), 0.into(), 12.into(), needle.into()];
+    let expected = recursive.run("binary_search_helper", args()).unwrap();
+    assert_eq!(looped.run("binary_search_helper", args()).unwrap(), expected, "needle {needle}");
+  }
+  for (a, b) in [(48, 18), (17, 5), (0, 9), (270, 192)] {
+    let expected = recursive.run("gcd", [a.into(), b.into()]).unwrap();
+    assert_eq!(looped.run("gcd", [a.into(), b.into()]).unwrap(), expected);
+  }
+  // locals start out nil on every iteration, just like in a new call
+  let expected = recursive.run("seen_before", [3.into()]).unwrap();
+  assert_eq!(expected, Value::Nil);
+  assert_eq!(looped.run("seen_before", [3.into()]).unwrap(), expected);
+  assert_eq!(looped.run("count_down", [100_000.into()]).unwrap(), Value::Integer(0));
+}
+
+#[test]
+fn maps_reject_unhashable_keys() {
+  let source_code = r#"
+function store(key)
+  m = map()
+  m[key] = 1
+  return m
+end
+
+function lookup(key)
+  m = map()
+  return m[key]
+end
+
+function words()
+  m = map()
+  m["a"] = 1
+  m[1] = "one"
+  m[true] = [2]
+  m["a"] = m["a"] + 1
+  m[1] = nil
+  return m
+end
+
+function float_literal_key()
+  m = map()
+  m[1.5] = 1
+  return m
+end
+"#;
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  for (key, type_name) in [(Value::from(1.5), "float"), (Value::from_iter([1]), "array"), (Value::Nil, "nil")] {
+    for function in ["store", "lookup"] {
+      match vm.run(function, [key.clone()]) {
+        Err(RuntimeError::UnhashableKey { type_name: found }) => assert_eq!(found, type_name),
+        other => panic!("expected an unhashable key error from {function}({key}), got {other:?}"),
+      }
+    }
+  }
+  assert_eq!(vm.run("store", [1.into()]).unwrap().get(1.into()).unwrap(), Value::Integer(1));
+  assert_eq!(vm.run("store", ["a".into()]).unwrap().get("a".into()).unwrap(), Value::Integer(1));
+  assert_eq!(vm.run("lookup", ["missing".into()]).unwrap(), Value::Nil);
+  // the key written in the script
+  assert!(matches!(vm.run("float_literal_key", []), Err(RuntimeError::UnhashableKey { type_name: "float" })));
+  let words = vm.run("words", []).unwrap();
+  assert_eq!(words.type_name(), "map");
+  assert_eq!(words.to_string(), "{true: [2], \"a\": 2}");
+}
+
+#[test]
+fn result_as_converts_the_return_value() {
+  let mut vm = lualite::compile_program("function trisum(a, b, c) return a + b + c end").unwrap();
+  vm.run("trisum", [1.into(), 2.into(), 3.into()]).unwrap();
+  assert!(matches!(vm.result_as::<i64>(), Ok(6)));
+  assert!(matches!(vm.result_as::<f64>(), Err(RuntimeError::WrongType { expected: "float", found: "integer" })));
+  vm.run("trisum", [Value::from(0.5), 0.25.into(), 1.0.into()]).unwrap();
+  assert!(matches!(vm.result_as::<f64>(), Ok(1.75)));
+}
+
+#[test]
+fn running_out_of_gas_halts_loops() {
+  use lualite::runtime::GasModel;
+  let source_code = r"
+function count(n)
+  total = 0
+  for i in 0..n do
+    total = total + i
+  end
+  return total
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  vm.set_gas(Some(1_000));
+  assert!(matches!(vm.run("count", [10.into()]), Ok(Value::Integer(45))));
+  let left = vm.remaining_gas().unwrap();
+  assert!(left < 1_000);
+  assert!(matches!(vm.run("count", [1_000_000.into()]), Err(RuntimeError::OutOfGas)));
+  assert!(vm.remaining_gas().unwrap() < left);
+
+  // arithmetic costing more runs out sooner
+  vm.set_gas(Some(1_000));
+  vm.set_gas_model(GasModel { arithmetic: 100, ..GasModel::default() });
+  assert!(matches!(vm.run("count", [10.into()]), Err(RuntimeError::OutOfGas)));
+  vm.set_gas(None);
+  assert!(matches!(vm.run("count", [10_000.into()]), Ok(Value::Integer(49_995_000))));
+}
+
+#[test]
+fn function_names_are_values() {
+  use lualite::bytecode::constant_value::ConstantValue;
+  let source_code = r"
+function gcd(a, b)
+  while b != 0 do
+    t = b
+    b = a % b
+    a = t
+  end
+  return a
+end
+
+function main()
+  f = gcd
+  return f(250, 135)
+end
+
+function apply(f, x, y)
+  return f(x, y)
+end
+
+function twice()
+  return apply(gcd, 12, 18)
+end
+";
+  let declarations = parser::parse_program(source_code).unwrap();
+  let functions = compiler::compile_declarations(&declarations).unwrap();
+  let (_, main) = functions.iter().find(|(name, _)| name == "main").unwrap();
+  assert!(main.constant_values().any(|constant| *constant == ConstantValue::Function("gcd".to_owned())));
+
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run_main().unwrap(), Value::Integer(5));
+  assert_eq!(vm.run("twice", []).unwrap(), Value::Integer(6));
+  assert!(matches!(vm.run("apply", [1.into(), 2.into(), 3.into()]), Err(RuntimeError::NotCallable { type_name: "integer" })));
+}
+
+#[test]
+fn return_inside_nested_blocks_leaves_the_function() {
+  let source_code = r"
+function first_multiple(of, start)
+  if of > 0 then
+    n = start
+    while true do
+      if n % of == 0 then
+        return n
+      end
+      n = n + 1
+    end
+  end
+  return nil
+end
+
+function caller(x)
+  before = x * 10
+  found = first_multiple(7, x)
+  for i in 0..3 do
+    if i == 2 then
+      return [before, found, i]
+    end
+  end
+  return false
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("first_multiple", [7.into(), 30.into()]).unwrap(), Value::Integer(35));
+  assert_eq!(vm.run("first_multiple", [0.into(), 30.into()]).unwrap(), Value::Nil);
+  // the caller's registers are intact after the callee returns from inside its loop
+  assert_eq!(vm.run("caller", [15.into()]).unwrap(), Value::from_iter([150, 21, 2]));
+}
+
+#[test]
+fn tonumber_parses_numeric_strings() {
+  let source_code = r#"
+function convert(x)
+  return tonumber(x)
+end
+"#;
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("convert", ["42".into()]).unwrap(), Value::Integer(42));
+  assert_eq!(vm.run("convert", ["3.14".into()]).unwrap(), Value::Float(3.14));
+  assert_eq!(vm.run("convert", ["abc".into()]).unwrap(), Value::Nil);
+  assert_eq!(vm.run("convert", ["12abc".into()]).unwrap(), Value::Nil);
+  assert_eq!(vm.run("convert", [7.5.into()]).unwrap(), Value::Float(7.5));
+  assert_eq!(vm.run("convert", [Value::Nil]).unwrap(), Value::Nil);
+}
+
+#[test]
+fn tostring_does_not_quote_strings() {
+  let source_code = r#"
+function convert(x)
+  return tostring(x)
+end
+"#;
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("convert", [42.into()]).unwrap(), Value::from("42"));
+  assert_eq!(vm.run("convert", [true.into()]).unwrap(), Value::from("true"));
+  assert_eq!(vm.run("convert", ["hi".into()]).unwrap(), Value::from("hi"));
+  assert_eq!(vm.run("convert", [Value::Nil]).unwrap(), Value::from("nil"));
+}
+
+#[test]
+fn executing_arbitrary_instructions_never_panics() {
+  use std::rc::Rc;
+  use lualite::bytecode::{self, Procedure, Code, Instruction};
+  use lualite::bytecode::constant_value::ConstantValue;
+  let mut vm = VirtualMachine::new();
+  vm.insert_function("fuzz", Procedure::new("fuzz", Code {
+    bytecode: vec![bytecode::nop(), bytecode::ret()],
+    register_count: 8,
+    max_args: 2,
+    constants: vec![
+      ConstantValue::Integer(i64::MAX),
+      ConstantValue::Integer(i64::MIN),
+      ConstantValue::Float(-0.5),
+      ConstantValue::String("text".to_owned()),
+      ConstantValue::Array(vec![ConstantValue::Integer(0), ConstantValue::Nil]),
+      ConstantValue::Bytes(vec![0, 255]),
+    ].into(),
+    functions: vec!["fuzz".to_owned(), "type".to_owned(), "call".to_owned(), "missing".to_owned()],
+    leading_nop: true,
+  }));
+  let procedure = vm.get_function("fuzz").unwrap();
+  vm.set_output(Box::new(std::io::sink()));
+  vm.set_globals([("g", Value::Integer(1))]);
+  // xorshift, so every run executes the same instructions
+  let mut state = 0x2545_f491_4f6c_dd1d_u64;
+  let mut next = || {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    state
+  };
+  for _ in 0..20_000 {
+    if vm.dump_state().starts_with("call stack: (empty)") || next() % 64 == 0 {
+      vm.abort();
+      vm.initialize_with_values(Rc::clone(&procedure), [Value::Integer(-1), Value::from("s")]).unwrap();
+    }
+    let _ = vm.execute(Instruction(next() as u32));
+  }
+}
+
+#[test]
+fn identical_functions_share_their_code() {
+  use std::rc::Rc;
+  let source_code = r"
+function area(w, h)
+  return w * h
+end
+
+function product(a, b)
+  return a * b
+end
+
+function sum(a, b)
+  return a + b
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  let area = vm.get_function("area").unwrap();
+  let product = vm.get_function("product").unwrap();
+  assert!(Rc::ptr_eq(area.code(), product.code()));
+  assert!(!Rc::ptr_eq(area.code(), vm.get_function("sum").unwrap().code()));
+  // only the code is shared, each keeps its own name
+  assert_eq!(area.name, "area");
+  assert_eq!(product.name, "product");
+  assert_eq!(vm.run("area", [3.into(), 4.into()]).unwrap(), Value::Integer(12));
+  assert_eq!(vm.run("product", [5.into(), 6.into()]).unwrap(), Value::Integer(30));
+}
+
+#[test]
+fn named_arguments_bind_by_parameter_name() {
+  use lualite::{ProgramError, compiler::CompileError};
+  let source_code = r"
+function sub(x, y)
+  return x - y
+end
+
+function swapped()
+  return sub(y = 1, x = 10)
+end
+
+function nested()
+  function scale(value, by = 2)
+    return value * by
+  end
+  return scale(value = 21)
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("swapped", []).unwrap(), Value::Integer(9));
+  assert_eq!(vm.run("nested", []).unwrap(), Value::Integer(42));
+
+  let unknown = lualite::compile_program("function f(x) return x end\nfunction g() return f(z = 1) end");
+  assert!(matches!(unknown, Err(ProgramError::Compile(CompileError::UnknownParameter { parameter, .. })) if parameter == "z"));
+  let duplicate = lualite::compile_program("function f(x) return x end\nfunction g() return f(x = 1, x = 2) end");
+  assert!(matches!(duplicate, Err(ProgramError::Compile(CompileError::DuplicateArgument { parameter, .. })) if parameter == "x"));
+}
+
+#[test]
+fn host_can_iterate_a_returned_array() {
+  let source_code = r"
+function squares()
+  return [1, 4, 9, 16]
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  let result = vm.run("squares", []).unwrap();
+  let mut total = 0;
+  for element in result.iter().unwrap() {
+    total += i64::try_from(element).unwrap();
+  }
+  assert_eq!(total, 30);
+  assert!(vm.run("squares", []).unwrap().iter().unwrap().eq([1, 4, 9, 16].map(Value::Integer)));
+  assert!(Value::from("text").iter().is_none());
+}
+
+#[test]
+fn local_shadows_an_outer_variable_until_the_end_of_its_block() {
+  let source_code = r"
+function shadow(flag)
+  x = 1
+  if flag then
+    local x = x + 10
+    x = x * 2
+    inner = x
+  end
+  return [x, inner]
+end
+
+function per_iteration(n)
+  total = 0
+  for i in 0..n do
+    local square = i * i
+    total = total + square
+  end
+  square = 5
+  return total + square
+end
+";
+  let mut vm = lualite::compile_program(source_code).unwrap();
+  assert_eq!(vm.run("shadow", [true.into()]).unwrap(), Value::from_iter([1, 22]));
+  assert_eq!(vm.run("shadow", [false.into()]).unwrap(), Value::from_iter([Value::Integer(1), Value::Nil]));
+  // 0 + 1 + 4 + 9, then the outer `square`
+  assert_eq!(vm.run("per_iteration", [4.into()]).unwrap(), Value::Integer(19));
+}
+
+#[test]
+fn programs_disassemble_and_run() {
+  use lualite::bytecode::Program;
+  let source_code = r"
+function square(x)
+  return x * x
+end
+
+function sum_of_squares(a, b)
+  return square(a) + square(b)
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut program: Program = compiler::compile_declarations(declarations.iter()).unwrap().into_iter().collect();
+  assert_eq!(program.procedure_names(), ["square", "sum_of_squares"]);
+  assert_eq!(program.get("square").unwrap().max_args, 1);
+  assert!(program.get("cube").is_none());
+
+  let disassembly = program.to_string();
+  assert!(disassembly.contains("\"square\":\n"));
+  assert!(disassembly.contains("\"sum_of_squares\":\n"));
+  assert!(disassembly.contains("mul   R0 = R1 * R1"), "{disassembly}");
+
+  let replaced = program.insert("square", program.get("sum_of_squares").unwrap().clone());
+  assert!(replaced.is_some());
+  program.insert("square", replaced.unwrap());
+  let mut vm = VirtualMachine::with_functions(program);
+  assert_eq!(vm.run("sum_of_squares", [3.into(), 4.into()]).unwrap(), Value::Integer(25));
+}
+
+#[test]
+fn procedures_can_share_one_constant_pool() {
+  use lualite::bytecode::Program;
+  use lualite::bytecode::constant_value::{ConstantValue, Constants};
+  let source_code = r#"
+function greeting()
+  return "a greeting long enough that storing it twice would be a waste"
+end
+
+function greeting_again()
+  return "a greeting long enough that storing it twice would be a waste"
+end
+
+function scaled(n)
+  x = n * 1.5
+  return "a greeting long enough that storing it twice would be a waste"
+end
+"#;
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut program: Program = compiler::compile_declarations(declarations.iter()).unwrap().into_iter().collect();
+  let before = program.clone();
+  program.share_constants();
+
+  let greeting = program.get("greeting").unwrap();
+  let scaled = program.get("scaled").unwrap();
+  let Constants::Shared { pool, .. } = &greeting.constants else { panic!("greeting's constants weren't shared") };
+  let long_strings = pool.iter().filter(|constant| matches!(constant, ConstantValue::String(string) if string.len() > 10));
+  assert_eq!(long_strings.count(), 1);
+  let Constants::Shared { pool: scaled_pool, .. } = &scaled.constants else { panic!("scaled's constants weren't shared") };
+  assert!(std::rc::Rc::ptr_eq(pool, scaled_pool));
+  assert!(std::rc::Rc::ptr_eq(greeting.code(), program.get("greeting_again").unwrap().code()));
+  for (name, procedure) in program.iter() {
+    assert_eq!(**procedure, **before.get(name).unwrap(), "{name}");
+  }
+
+  let mut vm = VirtualMachine::with_functions(program);
+  let expected = Value::from("a greeting long enough that storing it twice would be a waste");
+  assert_eq!(vm.run("greeting", []).unwrap(), expected);
+  assert_eq!(vm.run("scaled", [Value::Float(2.0)]).unwrap(), expected);
+}
+
+#[test]
+fn call_arguments_can_not_wrap_past_the_last_register() {
+  use lualite::bytecode::{self, Procedure, Code, operand::{RawRegister, FunctionKey}};
+  use lualite::bytecode::opcode::misc::call_subcode::ArgCount;
+  let caller = |name: &str, callee: &str, arg_start: u8, arg_count: u8| Procedure::new(name, Code {
+    bytecode: vec![
+      bytecode::nop(),
+      bytecode::call(ArgCount(arg_count), RawRegister(0), FunctionKey(0), RawRegister(arg_start)),
+      bytecode::ret(),
+    ],
+    register_count: 256,
+    max_args: 0,
+    constants: vec![].into(),
+    functions: vec![callee.to_owned()],
+    leading_nop: true,
+  });
+  let mut vm = lualite::compile_program("function three(a, b, c) = 3").unwrap();
+  vm.insert_function("last_registers", caller("last_registers", "three", 253, 3));
+  vm.insert_function("past_the_end", caller("past_the_end", "three", 254, 3));
+  vm.insert_function("call_last_register", caller("call_last_register", "call", 255, 1));
+  assert_eq!(vm.run("last_registers", []).unwrap(), Value::Integer(3));
+  assert!(matches!(vm.run("past_the_end", []), Err(RuntimeError::InvalidRegister)));
+  assert!(matches!(vm.run("call_last_register", []), Err(RuntimeError::NotCallable { type_name: "nil" })));
+}
+
+#[test]
+fn natives_can_fail_with_custom_errors() {
+  use std::fmt;
+  #[derive(Debug)]
+  struct AccountLocked(i64);
+  impl fmt::Display for AccountLocked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "account {} is locked", self.0)
+    }
+  }
+  impl std::error::Error for AccountLocked { }
+
+  fn withdraw(_context: &mut lualite::runtime::native::Context, args: &[Value]) -> Result<Value, RuntimeError> {
+    match args {
+      [Value::Integer(13), _] => Err(RuntimeError::custom(AccountLocked(13))),
+      [Value::Integer(_), Value::Integer(amount)] if *amount < 0 => Err(RuntimeError::custom("negative amount")),
+      [Value::Integer(_), amount] => Ok(amount.clone()),
+      _ => Err(RuntimeError::WrongType { expected: "integer", found: "nil" }),
+    }
+  }
+  let source_code = r"
+function pay(account, amount)
+  return withdraw(account, amount) * 2
+end
+";
+  let (_, declarations) = parser::parse_file(source_code).unwrap();
+  let mut vm = VirtualMachine::with_functions(compiler::compile_declarations(declarations.iter()).unwrap());
+  vm.insert_native("withdraw", withdraw);
+  assert_eq!(vm.run("pay", [1.into(), 5.into()]).unwrap(), Value::Integer(10));
+  match vm.run("pay", [13.into(), 5.into()]) {
+    Err(RuntimeError::Custom(error)) => {
+      assert_eq!(error.to_string(), "account 13 is locked");
+      assert!(error.downcast_ref::<AccountLocked>().is_some());
+    }
+    other => panic!("expected a custom error, got {other:?}"),
+  }
+  match vm.run("pay", [1.into(), (-5).into()]) {
+    Err(RuntimeError::Custom(error)) => assert_eq!(error.to_string(), "negative amount"),
+    other => panic!("expected a custom error, got {other:?}"),
+  }
+  // the vm can still run after a native fails
+  assert_eq!(vm.run("pay", [2.into(), 1.into()]).unwrap(), Value::Integer(2));
+}